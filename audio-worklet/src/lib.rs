@@ -3,8 +3,10 @@ use shared_types::ToWorkletMessage;
 use wasm_bindgen::prelude::*;
 use web_sys::{AudioWorkletGlobalScope, MessagePort};
 
-pub mod inharmonicity;
+pub use shared_types::inharmonicity;
+
 pub mod limiter;
+pub mod resonance;
 pub mod reverb;
 pub mod synth;
 
@@ -75,6 +77,38 @@ impl DissonanceProcessor {
                 log::debug!("SustainPedal: active={active}");
                 self.synth.set_sustain_pedal(active);
             }
+            ToWorkletMessage::SetTuning { frequencies } => {
+                log::debug!("SetTuning: {} notes", frequencies.len());
+                self.synth.set_tuning(&frequencies);
+            }
+            ToWorkletMessage::SetPolyphony { voices } => {
+                log::debug!("SetPolyphony: {voices} voices");
+                self.synth.set_polyphony(voices);
+            }
+            ToWorkletMessage::SetVoiceStealing { strategy } => {
+                log::debug!("SetVoiceStealing: {strategy:?}");
+                self.synth.set_voice_stealing(strategy);
+            }
+            ToWorkletMessage::SetSympatheticResonance { wet_level } => {
+                log::debug!("SetSympatheticResonance: wet_level={wet_level}");
+                self.synth.set_sympathetic_resonance(wet_level);
+            }
+            ToWorkletMessage::SetVoiceEngine { engine } => {
+                log::debug!("SetVoiceEngine: {engine:?}");
+                self.synth.set_voice_engine(engine);
+            }
+            ToWorkletMessage::SetSpectrum { spectrum } => {
+                log::debug!("SetSpectrum: {spectrum:?}");
+                self.synth.set_spectrum(spectrum);
+            }
+            ToWorkletMessage::SetInstrument { instrument } => {
+                log::debug!("SetInstrument: {instrument:?}");
+                self.synth.set_instrument(instrument);
+            }
+            ToWorkletMessage::SetEffectParam { param, value } => {
+                log::debug!("SetEffectParam: {param:?} = {value}");
+                self.synth.set_effect_param(param, value);
+            }
         }
     }
 
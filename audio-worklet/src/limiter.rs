@@ -1,5 +1,7 @@
 // AI generated
 
+use shared_types::effects::LimiterParam;
+
 /// Audio peak limiter
 /// Prevents audio signals from exceeding a specified threshold
 pub struct Limiter {
@@ -19,14 +21,11 @@ pub struct Limiter {
 
 impl Limiter {
     pub fn new(sample_rate: f32) -> Self {
-        let attack = 0.005; // 5ms default attack
-        let release = 0.050; // 50ms default release
-
         let mut limiter = Limiter {
-            threshold: -3.0, // -3 dB default threshold
-            attack,
-            release,
-            makeup_gain: 0.0, // 0 dB default makeup gain
+            threshold: LimiterParam::Threshold.default_value(),
+            attack: LimiterParam::Attack.default_value(),
+            release: LimiterParam::Release.default_value(),
+            makeup_gain: LimiterParam::MakeupGain.default_value(),
             envelope: 0.0,
             gain_reduction: 1.0, // No reduction initially (1.0 = 0dB)
             sample_rate,
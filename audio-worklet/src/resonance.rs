@@ -0,0 +1,353 @@
+//! Sympathetic resonance of the strings that the sustain pedal leaves undamped
+
+use shared_types::{SynthSpectrum, tuning::TuningTable};
+use std::{f32::consts::PI, ops::RangeInclusive};
+
+/// MIDI notes that have strings on a piano
+const PIANO_STRINGS: RangeInclusive<u8> = 21..=108;
+/// How close a partial of a sounding note has to be to a partial of a string to be able to set it ringing.
+/// Partials further apart than this barely excite the narrow resonance of a string.
+const COINCIDENCE_CENTS: f32 = 20.0;
+/// Time for the partials of an undamped string to decay to 1/e, at the reference frequency.
+/// Higher partials decay faster.
+const UNDAMPED_DECAY_TIME: f32 = 2.0;
+const DECAY_REFERENCE_HZ: f32 = 110.0;
+/// Time for the strings to fall silent once the dampers are back on them
+const DAMPED_DECAY_TIME: f32 = 0.1;
+/// Level below which a string that is no longer excited is dropped
+const SILENCE: f32 = 1e-6;
+/// Bounds the processing, as big chords set lots of strings ringing
+const MAX_RESONATORS: usize = 256;
+
+/// A partial of a string, ringing as a two-pole resonator
+struct Resonator {
+    string: u8,
+    partial: u32,
+    frequency: f32,
+    /// Coefficients of y[n] = gain·x[n] + a1·y[n-1] - a2·y[n-2]
+    gain: f32,
+    a1: f32,
+    a2: f32,
+    y1: f32,
+    y2: f32,
+    /// Whether the partial coincides with a partial of a sounding note while the strings are undamped
+    excited: bool,
+}
+
+impl Resonator {
+    fn new(string: u8, partial: u32, frequency: f32, sample_rate: f32) -> Self {
+        let mut resonator = Self {
+            string,
+            partial,
+            frequency,
+            gain: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+            excited: true,
+        };
+        resonator.set_decay_time(undamped_decay_time(frequency), sample_rate);
+        resonator
+    }
+
+    fn set_decay_time(&mut self, decay_time: f32, sample_rate: f32) {
+        let omega = 2.0 * PI * self.frequency / sample_rate;
+        let radius = (-1.0 / (decay_time * sample_rate)).exp();
+        self.a1 = 2.0 * radius * omega.cos();
+        self.a2 = radius * radius;
+        // Unity gain at the resonance frequency
+        self.gain =
+            (1.0 - radius) * (1.0 - 2.0 * radius * (2.0 * omega).cos() + radius * radius).sqrt();
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.gain * input + self.a1 * self.y1 - self.a2 * self.y2;
+        self.y2 = self.y1;
+        self.y1 = output;
+        output
+    }
+
+    fn is_silent(&self) -> bool {
+        self.y1.abs() + self.y2.abs() < SILENCE
+    }
+}
+
+fn undamped_decay_time(frequency: f32) -> f32 {
+    UNDAMPED_DECAY_TIME * (DECAY_REFERENCE_HZ / frequency).sqrt()
+}
+
+/// Number and frequency of the partials that the synth plays with `spectrum` for `midi_note`, tuned to
+/// `fundamental` Hz. Silent partials are left out, as they neither set strings ringing nor ring along.
+fn string_partials(
+    midi_note: u8,
+    fundamental: f32,
+    spectrum: &SynthSpectrum,
+) -> impl Iterator<Item = (u32, f32)> {
+    let inharmonicity = spectrum.inharmonicity(midi_note);
+    (0..SynthSpectrum::NUM_PARTIALS)
+        .filter(|&index| spectrum.amplitudes[index] > 0.0)
+        .map(move |index| {
+            (
+                index as u32 + 1,
+                spectrum.partial_frequency(&inharmonicity, fundamental, index),
+            )
+        })
+}
+
+/// A partial of one of the strings that can ring along
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct StringPartial {
+    string: u8,
+    partial: u32,
+    frequency: f32,
+}
+
+/// The partials out of the `sorted_partials` of the strings that coincide with a partial of one of the `sounding` notes,
+/// given as MIDI note and frequency and played with `spectrum`. The strings of the sounding notes themselves are left out.
+fn coinciding_partials<'a, I>(
+    sorted_partials: &'a [StringPartial],
+    sounding: I,
+    spectrum: &'a SynthSpectrum,
+) -> impl Iterator<Item = StringPartial> + 'a
+where
+    I: Iterator<Item = (u8, f32)> + Clone + 'a,
+{
+    const CENTS_PER_OCTAVE: f32 = 1200.0;
+    let max_ratio = 2f32.powf(COINCIDENCE_CENTS / CENTS_PER_OCTAVE);
+    let sounding_strings = sounding.clone();
+    sounding
+        .flat_map(move |(note, fundamental)| string_partials(note, fundamental, spectrum))
+        .flat_map(move |(_, frequency)| {
+            // The string partials are sorted, so the close ones are a contiguous range
+            let start = sorted_partials
+                .partition_point(|partial| partial.frequency * max_ratio <= frequency);
+            let end = sorted_partials
+                .partition_point(|partial| partial.frequency < frequency * max_ratio);
+            sorted_partials[start..end].iter().copied()
+        })
+        .filter(move |partial| {
+            sounding_strings
+                .clone()
+                .all(|(note, _)| note != partial.string)
+        })
+}
+
+/// The strings of a piano with the dampers raised, ringing along with the partials of the notes that are played
+pub struct SympatheticResonance {
+    sample_rate: f32,
+    wet_level: f32,
+    /// The partials below Nyquist of every string, sorted by frequency. Cached as finding the ones that coincide
+    /// with the sounding notes would otherwise mean going through all strings each time a note is played.
+    string_partials: Vec<StringPartial>,
+    /// Partials of the strings and of the sounding notes
+    spectrum: SynthSpectrum,
+    /// Never grows beyond the capacity it is created with, so that playing notes doesn't allocate
+    resonators: Vec<Resonator>,
+}
+
+impl SympatheticResonance {
+    /// `wet_level` is as in [`Self::set_wet_level`], and `tuning` and `spectrum` as in [`Self::set_strings`]
+    pub fn new(
+        sample_rate: f32,
+        wet_level: f32,
+        tuning: &TuningTable,
+        spectrum: &SynthSpectrum,
+    ) -> Self {
+        let mut resonance = Self {
+            sample_rate,
+            wet_level: wet_level.clamp(0.0, 1.0),
+            string_partials: Vec::with_capacity(PIANO_STRINGS.len() * SynthSpectrum::NUM_PARTIALS),
+            spectrum: *spectrum,
+            resonators: Vec::with_capacity(MAX_RESONATORS),
+        };
+        resonance.set_strings(tuning, spectrum);
+        resonance
+    }
+
+    /// Tune the strings to `tuning`, ringing with the partials the synth plays with `spectrum`.
+    /// Strings that are already ringing keep their pitch.
+    pub fn set_strings(&mut self, tuning: &TuningTable, spectrum: &SynthSpectrum) {
+        let nyquist = self.sample_rate / 2.0;
+        self.spectrum = *spectrum;
+        self.string_partials.clear();
+        for string in PIANO_STRINGS {
+            let Some(fundamental) = tuning.frequency(string) else {
+                continue;
+            };
+            self.string_partials.extend(
+                string_partials(string, fundamental, spectrum)
+                    .filter(|&(_, frequency)| frequency < nyquist)
+                    .map(|(partial, frequency)| StringPartial {
+                        string,
+                        partial,
+                        frequency,
+                    }),
+            );
+        }
+        self.string_partials
+            .sort_unstable_by(|a, b| a.frequency.total_cmp(&b.frequency));
+    }
+
+    /// How loud the ringing strings are mixed in, from 0 to 1
+    pub fn set_wet_level(&mut self, wet_level: f32) {
+        self.wet_level = wet_level.clamp(0.0, 1.0);
+    }
+
+    /// Raise the dampers of the strings with partials that coincide with partials of the `sounding` notes,
+    /// given as MIDI note and frequency. The sounding notes have the same partials as the strings from
+    /// [`Self::set_strings`]. Strings that are already ringing keep their energy.
+    pub fn undamp(&mut self, sounding: impl Iterator<Item = (u8, f32)> + Clone) {
+        for resonator in &mut self.resonators {
+            resonator.excited = false;
+        }
+        for StringPartial {
+            string,
+            partial,
+            frequency,
+        } in coinciding_partials(&self.string_partials, sounding, &self.spectrum)
+        {
+            if let Some(resonator) = self
+                .resonators
+                .iter_mut()
+                .find(|resonator| resonator.string == string && resonator.partial == partial)
+            {
+                resonator.excited = true;
+                resonator.set_decay_time(undamped_decay_time(frequency), self.sample_rate);
+            } else if self.resonators.len() < MAX_RESONATORS {
+                self.resonators
+                    .push(Resonator::new(string, partial, frequency, self.sample_rate));
+            }
+        }
+    }
+
+    /// Put the dampers back on all strings, letting them fall silent quickly
+    pub fn damp(&mut self) {
+        for resonator in &mut self.resonators {
+            resonator.excited = false;
+            resonator.set_decay_time(DAMPED_DECAY_TIME, self.sample_rate);
+        }
+    }
+
+    /// Drop the strings that are no longer excited once they have fallen silent
+    pub fn remove_silent_strings(&mut self) {
+        self.resonators
+            .retain(|resonator| resonator.excited || !resonator.is_silent());
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f32) -> f32 {
+        if self.resonators.is_empty() {
+            return input;
+        }
+        let resonance: f32 = self
+            .resonators
+            .iter_mut()
+            .map(|resonator| resonator.process(input))
+            .sum();
+        input + self.wet_level * resonance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_types::Instrument;
+
+    const TEST_SAMPLE_RATE: f32 = 44100.0;
+    const C4: u8 = 60;
+
+    fn sounding_c4(tuning: &TuningTable) -> impl Iterator<Item = (u8, f32)> + Clone {
+        [(C4, tuning.frequency(C4).unwrap())].into_iter()
+    }
+
+    #[test]
+    fn test_coinciding_partials() {
+        let tuning = TuningTable::default();
+        let resonance =
+            SympatheticResonance::new(TEST_SAMPLE_RATE, 0.3, &tuning, &SynthSpectrum::default());
+        let partials: Vec<(u8, u32)> = coinciding_partials(
+            &resonance.string_partials,
+            sounding_c4(&tuning),
+            &resonance.spectrum,
+        )
+        .map(|partial| (partial.string, partial.partial))
+        .collect();
+        // The octaves above and below, and the 2nd partial of the G above, two cents from the 3rd of C
+        for expected in [(C4 + 12, 1), (C4 - 12, 2), (C4 + 7, 2)] {
+            assert!(partials.contains(&expected), "{expected:?} in {partials:?}");
+        }
+        assert!(partials.iter().all(|&(string, _)| string != C4));
+        // The minor second above has no partials close to those of C4
+        assert!(partials.iter().all(|&(string, _)| string != C4 + 1));
+    }
+
+    #[test]
+    fn test_strings_ring_with_the_partials_of_the_spectrum() {
+        let tuning = TuningTable::default();
+        let coinciding_strings = |instrument: Instrument| {
+            let resonance =
+                SympatheticResonance::new(TEST_SAMPLE_RATE, 0.3, &tuning, &instrument.spectrum());
+            coinciding_partials(
+                &resonance.string_partials,
+                sounding_c4(&tuning),
+                &resonance.spectrum,
+            )
+            .map(|partial| partial.string)
+            .collect::<Vec<_>>()
+        };
+        // A sine has no partials in common with the other strings
+        assert!(coinciding_strings(Instrument::Sine).is_empty());
+        // The minor third partial of the bell sets the E flat above ringing, which the harmonic piano partials don't
+        const E_FLAT4: u8 = C4 + 3;
+        assert!(coinciding_strings(Instrument::Bell).contains(&E_FLAT4));
+        assert!(!coinciding_strings(Instrument::Piano).contains(&E_FLAT4));
+    }
+
+    #[test]
+    fn test_strings_ring_along_until_damped() {
+        let tuning = TuningTable::default();
+        let mut resonance =
+            SympatheticResonance::new(TEST_SAMPLE_RATE, 0.3, &tuning, &SynthSpectrum::default());
+        resonance.undamp(sounding_c4(&tuning));
+        assert!(!resonance.resonators.is_empty());
+
+        // A tone at the octave above sets the C5 string ringing
+        let frequency = tuning.frequency(C4 + 12).unwrap();
+        let mut max_resonance = 0.0f32;
+        for n in 0..TEST_SAMPLE_RATE as usize {
+            let input = (2.0 * PI * frequency * n as f32 / TEST_SAMPLE_RATE).sin();
+            max_resonance = max_resonance.max((resonance.process(input) - input).abs());
+        }
+        assert!(max_resonance > 0.1, "{max_resonance}");
+
+        // The strings still ring a while after the tone stops
+        let ringing = (0..100)
+            .map(|_| resonance.process(0.0).abs())
+            .fold(0.0, f32::max);
+        assert!(ringing > 0.01, "{ringing}");
+
+        // and fall silent once damped
+        resonance.damp();
+        for _ in 0..2 * TEST_SAMPLE_RATE as usize {
+            resonance.process(0.0);
+        }
+        resonance.remove_silent_strings();
+        assert!(resonance.resonators.is_empty());
+        assert_eq!(resonance.process(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_dry_signal_without_wet_level() {
+        let tuning = TuningTable::default();
+        let mut resonance =
+            SympatheticResonance::new(TEST_SAMPLE_RATE, 0.0, &tuning, &SynthSpectrum::default());
+        resonance.undamp(sounding_c4(&tuning));
+        let frequency = tuning.frequency(C4 + 12).unwrap();
+        for n in 0..1000 {
+            let input = (2.0 * PI * frequency * n as f32 / TEST_SAMPLE_RATE).sin();
+            assert_eq!(resonance.process(input), input);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 // AI generated
 
+use shared_types::effects::ReverbParam;
+
 const MS_TO_S: f32 = 0.001;
 
 // Delay times in ms for comb filters based on classic Schroeder reverb
@@ -11,11 +13,11 @@ const COMB_FILTER_DAMPING: f32 = 0.2;
 const ALLPASS_FILTER_DELAYS_MS: [f32; 2] = [5.0, 1.7];
 const ALLPASS_FILTER_FEEDBACK: f32 = 0.5;
 
-const DEFAULT_ROOM_SIZE: f32 = 0.7;
-const DEFAULT_DAMPING: f32 = 0.4;
-const DEFAULT_WET_LEVEL: f32 = 0.5;
-const DEFAULT_DRY_LEVEL: f32 = 0.4;
-const DEFAULT_WIDTH: f32 = 1.0;
+const DEFAULT_ROOM_SIZE: f32 = ReverbParam::RoomSize.default_value();
+const DEFAULT_DAMPING: f32 = ReverbParam::Damping.default_value();
+const DEFAULT_WET_LEVEL: f32 = ReverbParam::Wet.default_value();
+const DEFAULT_DRY_LEVEL: f32 = ReverbParam::Dry.default_value();
+const DEFAULT_WIDTH: f32 = ReverbParam::Width.default_value();
 
 /// Shroeder reverb
 pub struct Reverb {
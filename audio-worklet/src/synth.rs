@@ -1,13 +1,23 @@
 use crate::{
-    inharmonicity::{InharmonicityModel, PianoStringParameters},
-    limiter::Limiter,
+    inharmonicity::InharmonicityModel, limiter::Limiter, resonance::SympatheticResonance,
     reverb::Reverb,
 };
 use bitvec::{BitArr, order::Msb0};
-use std::{cmp::Ordering, f32::consts::PI};
+use shared_types::{
+    DEFAULT_POLYPHONY, DEFAULT_SYMPATHETIC_RESONANCE, Instrument, MAX_POLYPHONY, SynthEnvelope,
+    SynthSpectrum, VoiceEngine, VoiceStealing,
+    effects::{EffectParam, EffectSettings, LimiterParam, ReverbParam},
+    tuning::TuningTable,
+};
+use std::{
+    f32::consts::PI,
+    ops::{Deref, DerefMut},
+};
 
 mod envelope;
+mod waveguide;
 use envelope::{EnvelopeGenerator, EnvelopeState};
+use waveguide::WaveguideVoice;
 
 /// Synth trait for audio synthesis
 pub trait Synth {
@@ -22,8 +32,7 @@ struct PianoKey {
 }
 
 impl PianoKey {
-    fn new(midi_note: wmidi::Note) -> Self {
-        let frequency = midi_note.to_freq_f32();
+    fn new(midi_note: wmidi::Note, frequency: f32) -> Self {
         Self {
             frequency,
             midi_note,
@@ -31,7 +40,96 @@ impl PianoKey {
     }
 }
 
-/// Piano voice with oscillator and envelope
+/// A single note of the synth, played by one of the voice engines
+trait Voice {
+    fn note_on(&mut self, key: PianoKey, velocity: wmidi::U7);
+    fn note_off(&mut self);
+    /// Play with the partials of `spectrum`, from now on or from the next note depending on the engine
+    fn set_spectrum(&mut self, spectrum: &SynthSpectrum);
+    /// Shape the notes played from now on with `envelope`
+    fn set_envelope(&mut self, envelope: &SynthEnvelope);
+    fn process(&mut self) -> f32;
+    fn is_active(&self) -> bool;
+    fn current_key(&self) -> Option<PianoKey>;
+    fn envelope_state(&self) -> &EnvelopeState;
+    /// How loud the voice currently is, to find the quietest one
+    fn level(&self) -> f32;
+}
+
+/// A voice of the synth along with when its note was started. Each voice has one of every engine, so that
+/// switching engines doesn't allocate on the audio thread. It derefs to the engine of its latest note.
+struct SynthVoice {
+    additive: PianoVoice,
+    waveguide: WaveguideVoice,
+    engine: VoiceEngine,
+    /// How many notes the synth had started when this voice's note was, to find the oldest voice
+    started: u64,
+}
+
+impl SynthVoice {
+    fn new(
+        engine: VoiceEngine,
+        sample_rate: f32,
+        spectrum: &SynthSpectrum,
+        envelope: &SynthEnvelope,
+    ) -> Self {
+        let mut voice = Self {
+            additive: PianoVoice::new(sample_rate),
+            waveguide: WaveguideVoice::new(sample_rate),
+            engine,
+            started: 0,
+        };
+        voice.set_spectrum(spectrum);
+        voice.set_envelope(envelope);
+        voice
+    }
+
+    /// Play the next note with `engine`. A note still sounding on the previous engine is released.
+    fn switch_engine(&mut self, engine: VoiceEngine) {
+        if engine != self.engine {
+            self.deref_mut().note_off();
+            self.engine = engine;
+        }
+    }
+
+    fn set_spectrum(&mut self, spectrum: &SynthSpectrum) {
+        self.additive.set_spectrum(spectrum);
+        self.waveguide.set_spectrum(spectrum);
+    }
+
+    fn set_envelope(&mut self, envelope: &SynthEnvelope) {
+        self.additive.set_envelope(envelope);
+        self.waveguide.set_envelope(envelope);
+    }
+
+    /// Both engines, so that a released note of the previous engine fades out
+    #[inline]
+    fn process(&mut self) -> f32 {
+        self.additive.process() + self.waveguide.process()
+    }
+}
+
+impl Deref for SynthVoice {
+    type Target = dyn Voice;
+
+    fn deref(&self) -> &Self::Target {
+        match self.engine {
+            VoiceEngine::Additive => &self.additive,
+            VoiceEngine::Waveguide => &self.waveguide,
+        }
+    }
+}
+
+impl DerefMut for SynthVoice {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self.engine {
+            VoiceEngine::Additive => &mut self.additive,
+            VoiceEngine::Waveguide => &mut self.waveguide,
+        }
+    }
+}
+
+/// Piano voice adding up the partials of oscillators, with an envelope
 struct PianoVoice {
     // TODO: couldn't these be part of the partial_phases array?
     phase: f32,
@@ -49,37 +147,36 @@ struct PianoVoice {
     note_phase: f32,
     // Inharmonicity model for realistic piano string behavior
     inharmonicity: InharmonicityModel,
+    spectrum: SynthSpectrum,
+    // Envelope of the notes played from now on
+    shape: SynthEnvelope,
     // Individual phase accumulators for inharmonic partials
-    partial_phases: [f32; 7], // Phases for partials 2-8 (partial 1 uses main phase)
+    partial_phases: [f32; SynthSpectrum::NUM_PARTIALS - 1], // Phases for partials 2-16 (partial 1 uses main phase)
     // Cached phase deltas for inharmonic partials to avoid recalculation in hot path
-    partial_phase_deltas: [f32; 7], // Phase deltas for partials 2-8 (7 partials)
+    partial_phase_deltas: [f32; SynthSpectrum::NUM_PARTIALS - 1], // Phase deltas for partials 2-16
 }
 
 impl PianoVoice {
     fn new(sample_rate: f32) -> Self {
-        const ATTACK_TIME: f32 = 0.01;
-        const DECAY_TIME: f32 = 0.1;
-        const SUSTAIN_LEVEL: f32 = 0.7;
-        const RELEASE_TIME: f32 = 0.3;
-
         const DETUNING: f32 = 1.003; // Creates chorus-like effect for richer tone
         const BRIGHTNESS: f32 = 0.8; // Controls higher harmonic content
 
         // Initialize with a default inharmonicity - will be updated when note is played
         // Use middle C (MIDI 60) as default
         const DEFAULT_MIDI_NOTE: u8 = 60;
-        let string_params = PianoStringParameters::for_midi_note(DEFAULT_MIDI_NOTE);
-        let inharmonicity: InharmonicityModel = string_params.into();
+        let spectrum = SynthSpectrum::default();
+        let inharmonicity = spectrum.inharmonicity(DEFAULT_MIDI_NOTE);
+        let shape = SynthEnvelope::default();
 
         Self {
             phase: 0.0,
             detuned_phase: 0.0,
             phase_delta: 0.0,
             envelope: EnvelopeGenerator::new(
-                ATTACK_TIME,
-                DECAY_TIME,
-                SUSTAIN_LEVEL,
-                RELEASE_TIME,
+                shape.attack,
+                shape.decay,
+                shape.sustain,
+                shape.release,
                 sample_rate,
             ),
             sample_rate,
@@ -91,11 +188,34 @@ impl PianoVoice {
             attack_phase: 0.0, // Initialize attack phase
             note_phase: 0.0,
             inharmonicity,
-            partial_phases: [0.0; 7], // Initialize all partial phases to 0
-            partial_phase_deltas: [0.0; 7], // Initialize all partial phase deltas to 0
+            spectrum,
+            shape,
+            partial_phases: [0.0; SynthSpectrum::NUM_PARTIALS - 1], // Initialize all partial phases to 0
+            partial_phase_deltas: [0.0; SynthSpectrum::NUM_PARTIALS - 1], // Initialize all partial phase deltas to 0
         }
     }
 
+    fn update_phase_delta(&mut self) {
+        if let Some(key) = &self.current_key {
+            self.phase_delta = key.frequency / self.sample_rate;
+
+            // Cache partial phase deltas to avoid recalculation in hot audio processing loop
+            let fundamental_freq = key.frequency;
+            for partial_num in 2..=SynthSpectrum::NUM_PARTIALS {
+                let partial_freq = self.spectrum.partial_frequency(
+                    &self.inharmonicity,
+                    fundamental_freq,
+                    partial_num - 1,
+                );
+                let partial_phase_delta = partial_freq / self.sample_rate;
+                let partial_index = partial_num - 2; // Array index (0-14 for partials 2-16)
+                self.partial_phase_deltas[partial_index] = partial_phase_delta;
+            }
+        }
+    }
+}
+
+impl Voice for PianoVoice {
     fn note_on(&mut self, key: PianoKey, velocity: wmidi::U7) {
         const VELOCITY_POWER_CURVE: f32 = 0.8;
         const MIDI_VELOCITY_MAX: f32 = 127.0;
@@ -111,9 +231,7 @@ impl PianoVoice {
         self.current_key = Some(key);
 
         // Update inharmonicity model for this specific note
-        let midi_note_value = u8::from(key.midi_note);
-        let string_params = PianoStringParameters::for_midi_note(midi_note_value);
-        self.inharmonicity = string_params.into();
+        self.inharmonicity = self.spectrum.inharmonicity(u8::from(key.midi_note));
 
         self.update_phase_delta();
 
@@ -122,6 +240,13 @@ impl PianoVoice {
         self.velocity = normalized_velocity.powf(VELOCITY_POWER_CURVE);
 
         self.envelope.set_velocity(self.velocity);
+        self.envelope.set_shape(
+            self.shape.attack,
+            self.shape.decay,
+            self.shape.sustain,
+            self.shape.release,
+            self.sample_rate,
+        );
         self.attack_phase = 0.0; // Reset attack phase on new note
         self.note_phase = 0.0;
 
@@ -141,7 +266,8 @@ impl PianoVoice {
             // Also scale by velocity - higher velocity notes decay slightly slower
             let velocity_factor = 1.0 - (self.velocity * VELOCITY_DECAY_FACTOR); // 0.7 to 1.0 range
 
-            let sustain_decay_rate = base_decay_rate * freq_factor * velocity_factor;
+            let sustain_decay_rate =
+                base_decay_rate * freq_factor * velocity_factor * self.shape.sustain_decay;
             self.envelope.set_sustain_decay_rate(sustain_decay_rate);
         }
 
@@ -153,23 +279,21 @@ impl PianoVoice {
         self.envelope.release();
     }
 
-    fn update_phase_delta(&mut self) {
-        if let Some(key) = &self.current_key {
-            self.phase_delta = key.frequency / self.sample_rate;
-
-            // Cache partial phase deltas to avoid recalculation in hot audio processing loop
-            let fundamental_freq = key.frequency;
-            for partial_num in 2..=8 {
-                let partial_freq = self
-                    .inharmonicity
-                    .partial_frequency(fundamental_freq, partial_num as u32);
-                let partial_phase_delta = partial_freq / self.sample_rate;
-                let partial_index = (partial_num - 2) as usize; // Array index (0-6 for partials 2-8)
-                self.partial_phase_deltas[partial_index] = partial_phase_delta;
-            }
+    fn set_spectrum(&mut self, spectrum: &SynthSpectrum) {
+        let frequencies_changed =
+            spectrum.stretch != self.spectrum.stretch || spectrum.ratios != self.spectrum.ratios;
+        self.spectrum = *spectrum;
+        if frequencies_changed && let Some(key) = self.current_key {
+            // The phases carry on, so the partials glide to their new frequencies without clicks
+            self.inharmonicity = spectrum.inharmonicity(u8::from(key.midi_note));
+            self.update_phase_delta();
         }
     }
 
+    fn set_envelope(&mut self, envelope: &SynthEnvelope) {
+        self.shape = *envelope;
+    }
+
     #[inline]
     fn process(&mut self) -> f32 {
         if !self.is_active && !self.envelope.is_active() {
@@ -213,47 +337,59 @@ impl PianoVoice {
 
         let mut sample = 0.0;
 
-        // Calculate attack intensity - strongest at the beginning
-        let attack_intensity = (MAX_ATTACK_PHASE - self.attack_phase) * self.velocity;
+        // Calculate attack intensity - strongest at the beginning.
+        // Only struck strings get the hammer transients
+        let attack_intensity = if self.shape.struck {
+            (MAX_ATTACK_PHASE - self.attack_phase) * self.velocity
+        } else {
+            0.0
+        };
 
-        const FUNDAMENTAL_AMPLITUDE: f32 = 0.6;
-        const SECOND_HARMONIC_AMPLITUDE: f32 = 0.4;
-        const THIRD_HARMONIC_AMPLITUDE: f32 = 0.15;
         const TWO_PI: f32 = 2.0 * PI;
 
         // Fundamental (always exactly 1.0 multiplier, uses main phase)
-        sample += FUNDAMENTAL_AMPLITUDE * (TWO_PI * self.phase).sin();
-
-        // Second partial - inharmonic (uses partial_phases[0])
-        sample += SECOND_HARMONIC_AMPLITUDE * (TWO_PI * self.partial_phases[0]).sin();
-
-        // Third partial - inharmonic (uses partial_phases[1])
-        sample += THIRD_HARMONIC_AMPLITUDE * (TWO_PI * self.partial_phases[1]).sin();
+        sample += self.spectrum.amplitudes[0] * (TWO_PI * self.phase).sin();
 
         const DYNAMIC_BRIGHTNESS_BASE: f32 = 0.7;
         const DYNAMIC_BRIGHTNESS_VELOCITY_FACTOR: f32 = 0.3;
+        // The spectrum is how a note of medium velocity sounds
+        const SPECTRUM_VELOCITY: f32 = 0.5;
 
-        // Higher harmonics with brightness control and dynamic attack
+        // Brightness of the hammer ping during the attack
         let dynamic_brightness = self.brightness
             * (DYNAMIC_BRIGHTNESS_BASE + DYNAMIC_BRIGHTNESS_VELOCITY_FACTOR * self.velocity);
+        // Harder notes have stronger higher partials than the spectrum
+        let spectrum_brightness = (DYNAMIC_BRIGHTNESS_BASE
+            + DYNAMIC_BRIGHTNESS_VELOCITY_FACTOR * self.velocity)
+            / (DYNAMIC_BRIGHTNESS_BASE + DYNAMIC_BRIGHTNESS_VELOCITY_FACTOR * SPECTRUM_VELOCITY);
 
         const ATTACK_HARMONIC_BOOST_FACTOR: f32 = 2.0;
 
-        // 4th and 5th harmonics are stronger during attack phase
+        // Partials from the 4th up are stronger during attack phase, and brighter with velocity
         let attack_harmonic_boost =
             MAX_ATTACK_PHASE + (attack_intensity * ATTACK_HARMONIC_BOOST_FACTOR);
-
-        const FOURTH_HARMONIC_AMPLITUDE: f32 = 0.2;
-        const FIFTH_HARMONIC_AMPLITUDE: f32 = 0.14;
-
-        sample += dynamic_brightness
-            * FOURTH_HARMONIC_AMPLITUDE
-            * attack_harmonic_boost
-            * (TWO_PI * self.partial_phases[2]).sin(); // 4th partial
-        sample += dynamic_brightness
-            * FIFTH_HARMONIC_AMPLITUDE
-            * attack_harmonic_boost
-            * (TWO_PI * self.partial_phases[3]).sin(); // 5th partial
+        const FIRST_BRIGHT_PARTIAL: usize = 4;
+        // Partials above the Nyquist frequency would alias
+        const MAX_PHASE_DELTA: f32 = 0.5;
+
+        for (partial_index, ((&phase, &phase_delta), &amplitude)) in self
+            .partial_phases
+            .iter()
+            .zip(&self.partial_phase_deltas)
+            .zip(&self.spectrum.amplitudes[1..])
+            .enumerate()
+        {
+            if amplitude == 0.0 || phase_delta >= MAX_PHASE_DELTA {
+                continue;
+            }
+            let partial_num = partial_index + 2;
+            let gain = if partial_num >= FIRST_BRIGHT_PARTIAL {
+                spectrum_brightness * attack_harmonic_boost
+            } else {
+                1.0
+            };
+            sample += amplitude * gain * (TWO_PI * phase).sin();
+        }
 
         const ATTACK_INTENSITY_THRESHOLD: f32 = 0.01;
 
@@ -279,8 +415,10 @@ impl PianoVoice {
 
         const DETUNED_OSCILLATOR_AMPLITUDE: f32 = 0.1;
 
-        // Detuned oscillator for richness
-        sample += DETUNED_OSCILLATOR_AMPLITUDE * (TWO_PI * self.detuned_phase).sin();
+        // Detuned oscillator for richness, like the slightly mistuned strings of a piano's unisons
+        if self.shape.struck {
+            sample += DETUNED_OSCILLATOR_AMPLITUDE * (TWO_PI * self.detuned_phase).sin();
+        }
 
         const NOISE1_FREQ: f32 = 3.71;
         const NOISE2_FREQ: f32 = 5.83;
@@ -332,16 +470,65 @@ impl PianoVoice {
 
         sample
     }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+
+    fn current_key(&self) -> Option<PianoKey> {
+        self.current_key
+    }
+
+    fn envelope_state(&self) -> &EnvelopeState {
+        &self.envelope.state
+    }
+
+    fn level(&self) -> f32 {
+        self.envelope.current_level()
+    }
+}
+
+fn apply_reverb_param(reverb: &mut Reverb, param: ReverbParam, value: f32) {
+    match param {
+        ReverbParam::RoomSize => reverb.set_room_size(value),
+        ReverbParam::Damping => reverb.set_damping(value),
+        ReverbParam::Wet => reverb.set_wet_level(value),
+        ReverbParam::Dry => reverb.set_dry_level(value),
+        ReverbParam::Width => reverb.set_width(value),
+    }
+}
+
+fn apply_limiter_param(limiter: &mut Limiter, param: LimiterParam, value: f32) {
+    match param {
+        LimiterParam::Threshold => limiter.set_threshold(value),
+        LimiterParam::Attack => limiter.set_attack(value),
+        LimiterParam::Release => limiter.set_release(value),
+        LimiterParam::MakeupGain => limiter.set_makeup_gain(value),
+    }
 }
 
 /// Piano synth managing multiple voices for polyphony
 pub struct PianoSynth {
-    voices: Vec<PianoVoice>,
+    /// Can hold more than `polyphony` voices for a while after it shrinks, until the surplus voices fade out
+    voices: Vec<SynthVoice>,
+    polyphony: usize,
+    voice_stealing: VoiceStealing,
+    /// Engine for the notes played from now on
+    engine: VoiceEngine,
+    spectrum: SynthSpectrum,
+    envelope: SynthEnvelope,
+    /// Number of notes started so far
+    notes_started: u64,
     sample_rate: Option<u32>,
+    resonance: Option<SympatheticResonance>,
+    resonance_wet_level: f32,
     reverb: Option<Reverb>,
     limiter: Option<Limiter>,
+    /// Applied to the reverb and limiter, also when they are recreated for a new sample rate
+    effects: EffectSettings,
     sustain_pedal_active: bool,
     sustained_notes: BitArr!(for 128, in u32, Msb0),
+    tuning: TuningTable,
 }
 
 impl Default for PianoSynth {
@@ -355,11 +542,21 @@ impl PianoSynth {
         // Used in tests where sample_rate will be set via play() before use.
         Self {
             voices: Vec::new(),
+            polyphony: usize::from(DEFAULT_POLYPHONY),
+            voice_stealing: VoiceStealing::default(),
+            engine: VoiceEngine::default(),
+            spectrum: SynthSpectrum::default(),
+            envelope: SynthEnvelope::default(),
+            notes_started: 0,
             sample_rate: None,
+            resonance: None,
+            resonance_wet_level: DEFAULT_SYMPATHETIC_RESONANCE,
             reverb: None,
             limiter: None,
+            effects: EffectSettings::default(),
             sustain_pedal_active: false,
             sustained_notes: Default::default(),
+            tuning: TuningTable::default(),
         }
     }
 
@@ -372,13 +569,83 @@ impl PianoSynth {
     }
 
     fn allocate_voices_if_needed(&mut self) {
-        if self.voices.is_empty()
-            && let Some(sr) = self.sample_rate
-        {
-            const NUM_VOICES: usize = 8;
-            self.voices.reserve(NUM_VOICES);
-            for _ in 0..NUM_VOICES {
-                self.voices.push(PianoVoice::new(sr as f32));
+        if let Some(sr) = self.sample_rate {
+            while self.voices.len() < self.polyphony {
+                self.voices.push(SynthVoice::new(
+                    self.engine,
+                    sr as f32,
+                    &self.spectrum,
+                    &self.envelope,
+                ));
+            }
+        }
+    }
+
+    /// Drop voices beyond the polyphony that have faded out
+    fn remove_finished_surplus_voices(&mut self) {
+        let mut surplus = self.voices.len().saturating_sub(self.polyphony);
+        self.voices.retain(|voice| {
+            let remove = surplus > 0 && !voice.is_active();
+            if remove {
+                surplus -= 1;
+            }
+            !remove
+        });
+    }
+
+    /// Play at most `voices` notes at once. Extra voices are added right away, while notes that are sounding
+    /// when the polyphony shrinks are left to fade out rather than cut off with a click.
+    pub fn set_polyphony(&mut self, voices: u8) {
+        self.polyphony = usize::from(voices.clamp(1, MAX_POLYPHONY));
+        self.allocate_voices_if_needed();
+        self.remove_finished_surplus_voices();
+    }
+
+    pub fn set_voice_stealing(&mut self, strategy: VoiceStealing) {
+        self.voice_stealing = strategy;
+    }
+
+    /// Play the notes started from now on with `engine`. Sounding notes keep the engine they were started with.
+    pub fn set_voice_engine(&mut self, engine: VoiceEngine) {
+        self.engine = engine;
+    }
+
+    /// Play with the partials of `spectrum`. The additive voices change their sounding notes right away,
+    /// while the waveguide strings take the stretch from their next note.
+    pub fn set_spectrum(&mut self, spectrum: SynthSpectrum) {
+        let changed = spectrum != self.spectrum;
+        self.spectrum = spectrum;
+        for voice in &mut self.voices {
+            voice.set_spectrum(&spectrum);
+        }
+        if changed {
+            self.update_resonance_strings();
+        }
+    }
+
+    /// Play with the partials and envelope of `instrument`. Sounding notes keep their envelope.
+    pub fn set_instrument(&mut self, instrument: Instrument) {
+        self.envelope = instrument.envelope();
+        for voice in &mut self.voices {
+            voice.set_envelope(&self.envelope);
+        }
+        self.set_spectrum(instrument.spectrum());
+    }
+
+    /// Set a parameter of the reverb or limiter, which is kept if they are recreated for a new sample rate
+    pub fn set_effect_param(&mut self, param: EffectParam, value: f32) {
+        self.effects.set(param, value);
+        let value = self.effects.get(param);
+        match param {
+            EffectParam::Reverb(param) => {
+                if let Some(reverb) = &mut self.reverb {
+                    apply_reverb_param(reverb, param, value);
+                }
+            }
+            EffectParam::Limiter(param) => {
+                if let Some(limiter) = &mut self.limiter {
+                    apply_limiter_param(limiter, param, value);
+                }
             }
         }
     }
@@ -390,75 +657,70 @@ impl PianoSynth {
             self.sample_rate.is_some(),
             "sample_rate should be set before note_on"
         );
-        let key = PianoKey::new(note);
-
-        // First try to find an inactive voice
-        let voice = if let Some(voice) = self.voices.iter_mut().find(|v| !v.is_active) {
-            voice
-        } else {
-            // Voice stealing - prioritize voices based on envelope state
-            // Find the voice furthest along in its envelope cycle
-            self.find_voice_to_steal()
+        let Some(frequency) = self.tuning.frequency(u8::from(note)) else {
+            // Notes that the tuning doesn't map are silent
+            return;
         };
+        let key = PianoKey::new(note, frequency);
 
+        let index = self.choose_voice(note);
+        self.notes_started += 1;
+        let voice = &mut self.voices[index];
+        voice.switch_engine(self.engine);
+        voice.started = self.notes_started;
         voice.note_on(key, velocity);
+        if self.sustain_pedal_active {
+            self.update_resonance();
+        }
     }
 
-    // Helper method to find the best voice to steal
-    fn find_voice_to_steal(&mut self) -> &mut PianoVoice {
-        // Strategy: find index first, then get the voice by index
-        let voice_index = {
-            // First check for voices in release state (already note-off)
-            let release_index = self
-                .voices
+    /// Index of the voice to play `note` on: a free one while fewer than `polyphony` voices are sounding,
+    /// otherwise one stolen according to `voice_stealing`
+    fn choose_voice(&self, note: wmidi::Note) -> usize {
+        if self.voice_stealing == VoiceStealing::SameNote
+            && let Some(index) = self.voices.iter().position(|voice| {
+                voice.is_active() && voice.current_key().is_some_and(|key| key.midi_note == note)
+            })
+        {
+            return index;
+        }
+        let active_voices = self.voices.iter().filter(|voice| voice.is_active()).count();
+        if active_voices < self.polyphony
+            && let Some(index) = self.voices.iter().position(|voice| !voice.is_active())
+        {
+            return index;
+        }
+        match self.voice_stealing {
+            VoiceStealing::Quietest => self.quietest_voice(),
+            VoiceStealing::Oldest | VoiceStealing::SameNote => self.oldest_voice(),
+        }
+    }
+
+    /// The sounding voice whose note was started first
+    fn oldest_voice(&self) -> usize {
+        self.voices
+            .iter()
+            .enumerate()
+            .filter(|(_, voice)| voice.is_active())
+            .min_by_key(|(_, voice)| voice.started)
+            .map(|(index, _)| index)
+            .expect("voices are only stolen while all of them are sounding")
+    }
+
+    /// The quietest sounding voice, preferring voices that have been released and then voices in sustain
+    fn quietest_voice(&self) -> usize {
+        let quietest = |in_state: &dyn Fn(&EnvelopeState) -> bool| {
+            self.voices
                 .iter()
                 .enumerate()
-                .filter(|(_, v)| v.envelope.state == EnvelopeState::Release)
-                .min_by(|(_, a), (_, b)| {
-                    a.envelope
-                        .current_level()
-                        .partial_cmp(&b.envelope.current_level())
-                        .unwrap_or(Ordering::Equal)
-                })
-                .map(|(idx, _)| idx);
-
-            if let Some(idx) = release_index {
-                idx
-            } else {
-                // Then check for voices in sustain state
-                let sustain_index = self
-                    .voices
-                    .iter()
-                    .enumerate()
-                    .filter(|(_, v)| v.envelope.state == EnvelopeState::Sustain)
-                    .min_by(|(_, a), (_, b)| {
-                        a.envelope
-                            .current_level()
-                            .partial_cmp(&b.envelope.current_level())
-                            .unwrap_or(Ordering::Equal)
-                    })
-                    .map(|(idx, _)| idx);
-
-                if let Some(idx) = sustain_index {
-                    idx
-                } else {
-                    // Last resort: take the voice with the lowest current envelope level
-                    self.voices
-                        .iter()
-                        .enumerate()
-                        .min_by(|(_, a), (_, b)| {
-                            a.envelope
-                                .current_level()
-                                .partial_cmp(&b.envelope.current_level())
-                                .unwrap_or(Ordering::Equal)
-                        })
-                        .map(|(idx, _)| idx)
-                        .unwrap()
-                }
-            }
+                .filter(|(_, voice)| voice.is_active() && in_state(voice.envelope_state()))
+                .min_by(|(_, a), (_, b)| a.level().total_cmp(&b.level()))
+                .map(|(index, _)| index)
         };
-
-        &mut self.voices[voice_index]
+        quietest(&|state| *state == EnvelopeState::Release)
+            .or_else(|| quietest(&|state| *state == EnvelopeState::Sustain))
+            .or_else(|| quietest(&|_| true))
+            .expect("voices are only stolen while all of them are sounding")
     }
 
     pub fn note_off(&mut self, midi_note: wmidi::Note) {
@@ -477,7 +739,7 @@ impl PianoSynth {
     /// Actually release a note (used both for normal note-off and when sustain pedal is released)
     fn release_note(&mut self, midi_note: wmidi::Note) {
         for voice in self.voices.iter_mut() {
-            if let Some(key) = &voice.current_key
+            if let Some(key) = voice.current_key()
                 && key.midi_note == midi_note
             {
                 voice.note_off();
@@ -498,7 +760,68 @@ impl PianoSynth {
             // Clear all sustained notes
             self.sustained_notes.fill(false);
         }
+        let changed = self.sustain_pedal_active != active;
         self.sustain_pedal_active = active;
+        if changed {
+            self.update_resonance();
+        }
+    }
+
+    /// How loud the strings that ring along with the played notes are while the sustain pedal is down, from 0 to 1
+    pub fn set_sympathetic_resonance(&mut self, wet_level: f32) {
+        self.resonance_wet_level = wet_level;
+        if let Some(resonance) = &mut self.resonance {
+            resonance.set_wet_level(wet_level);
+        }
+    }
+
+    /// Retune the strings that ring along to the tuning and partials of the played notes
+    fn update_resonance_strings(&mut self) {
+        if let Some(resonance) = &mut self.resonance {
+            resonance.set_strings(&self.tuning, &self.spectrum);
+        }
+    }
+
+    /// With the sustain pedal down every string is undamped, so the ones with partials in common with the
+    /// sounding notes ring along. Releasing the pedal damps them again.
+    fn update_resonance(&mut self) {
+        let Some(sample_rate) = self.sample_rate else {
+            return;
+        };
+        let resonance = self.resonance.get_or_insert_with(|| {
+            SympatheticResonance::new(
+                sample_rate as f32,
+                self.resonance_wet_level,
+                &self.tuning,
+                &self.spectrum,
+            )
+        });
+        if self.sustain_pedal_active {
+            let sounding = self
+                .voices
+                .iter()
+                .filter(|voice| voice.is_active())
+                .filter_map(|voice| voice.current_key())
+                .map(|key| (u8::from(key.midi_note), key.frequency));
+            resonance.undamp(sounding);
+        } else {
+            resonance.damp();
+        }
+    }
+
+    /// Use the frequency of every MIDI note from `frequencies` for the notes played from now on.
+    /// Notes that are already sounding keep their pitch.
+    pub fn set_tuning(&mut self, frequencies: &[f32]) {
+        match <[f32; 128]>::try_from(frequencies) {
+            Ok(frequencies) => {
+                self.tuning = TuningTable::new(frequencies);
+                self.update_resonance_strings();
+            }
+            Err(_) => log::error!(
+                "Tuning table should have a frequency for each of the 128 MIDI notes, got {}",
+                frequencies.len()
+            ),
+        }
     }
 
     #[inline]
@@ -512,21 +835,57 @@ impl Synth for PianoSynth {
         if self.sample_rate != Some(sample_rate) {
             // Sample rate changed or first-time setup.
             self.voices.clear();
+            self.resonance = None;
             self.reverb = None;
             self.limiter = None;
             self.sample_rate = Some(sample_rate);
         }
         self.allocate_voices_if_needed();
+        self.remove_finished_surplus_voices();
+        if let Some(resonance) = &mut self.resonance {
+            resonance.remove_silent_strings();
+        }
 
         for out_channels in out_samples.chunks_exact_mut(num_channels) {
             let s = self.process();
+            let s = self
+                .resonance
+                .get_or_insert_with(|| {
+                    SympatheticResonance::new(
+                        sample_rate as f32,
+                        self.resonance_wet_level,
+                        &self.tuning,
+                        &self.spectrum,
+                    )
+                })
+                .process(s);
             let s = self
                 .reverb
-                .get_or_insert_with(|| Reverb::new(sample_rate as f32))
+                .get_or_insert_with(|| {
+                    let mut reverb = Reverb::new(sample_rate as f32);
+                    for param in ReverbParam::ALL {
+                        apply_reverb_param(
+                            &mut reverb,
+                            param,
+                            self.effects.get(EffectParam::Reverb(param)),
+                        );
+                    }
+                    reverb
+                })
                 .process(s);
             let s = self
                 .limiter
-                .get_or_insert_with(|| Limiter::new(sample_rate as f32))
+                .get_or_insert_with(|| {
+                    let mut limiter = Limiter::new(sample_rate as f32);
+                    for param in LimiterParam::ALL {
+                        apply_limiter_param(
+                            &mut limiter,
+                            param,
+                            self.effects.get(EffectParam::Limiter(param)),
+                        );
+                    }
+                    limiter
+                })
                 .process(s);
             for c in out_channels.iter_mut() {
                 *c = s;
@@ -586,7 +945,7 @@ mod tests {
     fn test_voice_phase_accumulator_independence() {
         // Test that each partial's phase accumulator works independently
         let mut voice = PianoVoice::new(44100.0);
-        let key = PianoKey::new(wmidi::Note::A4);
+        let key = PianoKey::new(wmidi::Note::A4, wmidi::Note::A4.to_freq_f32());
         let velocity = wmidi::U7::try_from(100).unwrap();
 
         voice.note_on(key, velocity);
@@ -625,7 +984,7 @@ mod tests {
     fn test_partial_phase_rem_euclid_no_discontinuities() {
         // Test that rem_euclid phase wrapping doesn't create audio discontinuities
         let mut voice = PianoVoice::new(44100.0);
-        let key = PianoKey::new(wmidi::Note::A4);
+        let key = PianoKey::new(wmidi::Note::A4, wmidi::Note::A4.to_freq_f32());
         let velocity = wmidi::U7::try_from(100).unwrap();
 
         voice.note_on(key, velocity);
@@ -681,7 +1040,7 @@ mod tests {
     fn test_partial_phase_delta_caching() {
         // Test that partial phase deltas are correctly cached when a note is played
         let mut voice = PianoVoice::new(44100.0);
-        let key = PianoKey::new(wmidi::Note::A4);
+        let key = PianoKey::new(wmidi::Note::A4, wmidi::Note::A4.to_freq_f32());
         let velocity = wmidi::U7::try_from(100).unwrap();
 
         // Initially, all cached phase deltas should be 0
@@ -723,4 +1082,230 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_spectrum_stretch_retunes_sounding_partials() {
+        let mut voice = PianoVoice::new(44100.0);
+        voice.note_on(
+            PianoKey::new(wmidi::Note::A2, wmidi::Note::A2.to_freq_f32()),
+            wmidi::U7::MAX,
+        );
+        let stretched = voice.partial_phase_deltas;
+        assert!(stretched[14] > 16.0 * voice.phase_delta * 1.001);
+
+        // Without stretch the partials are whole multiples of the fundamental
+        voice.set_spectrum(&SynthSpectrum {
+            stretch: 0.0,
+            ..SynthSpectrum::default()
+        });
+        for (index, &delta) in voice.partial_phase_deltas.iter().enumerate() {
+            let expected = (index + 2) as f32 * voice.phase_delta;
+            assert!((delta - expected).abs() < 1e-6, "partial {}", index + 2);
+        }
+    }
+
+    #[test]
+    fn test_instrument_presets() {
+        let key = PianoKey::new(wmidi::Note::A3, wmidi::Note::A3.to_freq_f32());
+        let preset_voice = |instrument: Instrument| {
+            let mut voice = PianoVoice::new(44100.0);
+            voice.set_spectrum(&instrument.spectrum());
+            voice.set_envelope(&instrument.envelope());
+            voice.note_on(key, wmidi::U7::MAX);
+            voice
+        };
+
+        // The second partial of the bell is its minor third tierce
+        let bell = preset_voice(Instrument::Bell);
+        assert!((bell.partial_phase_deltas[0] - 1.19 * bell.phase_delta).abs() < 1e-6);
+
+        // Without a hammer or detuned string the sine preset holds a steady level once the attack is over
+        let mut sine = preset_voice(Instrument::Sine);
+        let samples: Vec<f32> = (0..4410).map(|_| sine.process()).collect();
+        let peak = |samples: &[f32]| {
+            samples
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+        };
+        let period = (1.0 / sine.phase_delta).ceil() as usize;
+        let overall_peak = peak(&samples);
+        assert!(overall_peak > 0.0);
+        assert!((overall_peak - peak(&samples[samples.len() - period..])).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_effect_params_survive_sample_rate_change() {
+        let mut synth = PianoSynth::new();
+        synth.set_effect_param(EffectParam::Reverb(ReverbParam::Wet), 0.0);
+        synth.set_effect_param(EffectParam::Reverb(ReverbParam::Dry), 0.0);
+        for sample_rate in [44100, 48000] {
+            let mut buffer = vec![0.0f32; 1];
+            synth.play(sample_rate, 1, &mut buffer);
+            synth.note_on(wmidi::Note::A4, wmidi::U7::MAX);
+            let mut buffer = vec![0.0f32; 4800];
+            synth.play(sample_rate, 1, &mut buffer);
+            assert!(
+                buffer.iter().all(|&sample| sample == 0.0),
+                "the reverb should mute everything at {sample_rate} Hz"
+            );
+        }
+
+        synth.set_effect_param(EffectParam::Reverb(ReverbParam::Dry), 1.0);
+        let mut buffer = vec![0.0f32; 4800];
+        synth.play(48000, 1, &mut buffer);
+        assert!(buffer.iter().any(|&sample| sample != 0.0));
+    }
+
+    #[test]
+    fn test_notes_use_tuning_table() {
+        use shared_types::tuning::{Temperament, Tuning};
+
+        let mut synth = PianoSynth::with_sample_rate(44100);
+        let tuning = Tuning {
+            temperament: Temperament::Just,
+            ..Tuning::default()
+        };
+        synth.set_tuning(tuning.frequency_table().frequencies());
+        synth.note_on(wmidi::Note::E4, wmidi::U7::MAX);
+        let key = synth
+            .voices
+            .iter()
+            .find_map(|voice| voice.current_key())
+            .unwrap();
+        assert_eq!(key.frequency, tuning.frequency(u8::from(wmidi::Note::E4)));
+        assert_ne!(key.frequency, wmidi::Note::E4.to_freq_f32());
+
+        // A table of the wrong size is ignored
+        synth.set_tuning(&[440.0]);
+        synth.note_on(wmidi::Note::E4, wmidi::U7::MAX);
+        assert!(
+            synth
+                .voices
+                .iter()
+                .filter_map(|voice| voice.current_key())
+                .all(|key| key.frequency == tuning.frequency(u8::from(wmidi::Note::E4)))
+        );
+    }
+
+    #[test]
+    fn test_unmapped_notes_are_silent() {
+        let mut synth = PianoSynth::with_sample_rate(44100);
+        let mut frequencies = *TuningTable::default().frequencies();
+        frequencies[usize::from(u8::from(wmidi::Note::C4))] = TuningTable::UNMAPPED;
+        synth.set_tuning(&frequencies);
+        synth.note_on(wmidi::Note::C4, wmidi::U7::MAX);
+        assert!(synth.voices.iter().all(|voice| !voice.is_active()));
+    }
+
+    fn sounding_notes(synth: &PianoSynth) -> Vec<wmidi::Note> {
+        let mut notes: Vec<wmidi::Note> = synth
+            .voices
+            .iter()
+            .filter(|voice| voice.is_active())
+            .filter_map(|voice| voice.current_key().map(|key| key.midi_note))
+            .collect();
+        notes.sort();
+        notes
+    }
+
+    fn synth_with_voices(voices: u8, strategy: VoiceStealing) -> PianoSynth {
+        let mut synth = PianoSynth::with_sample_rate(44100);
+        synth.set_polyphony(voices);
+        synth.set_voice_stealing(strategy);
+        synth
+    }
+
+    #[test]
+    fn test_oldest_voice_is_stolen() {
+        use wmidi::Note::{C4, E4, G4};
+        let mut synth = synth_with_voices(2, VoiceStealing::Oldest);
+        for note in [C4, E4, G4] {
+            synth.note_on(note, wmidi::U7::MAX);
+        }
+        assert_eq!(sounding_notes(&synth), [E4, G4]);
+    }
+
+    #[test]
+    fn test_quietest_voice_is_stolen() {
+        use wmidi::Note::{C4, E4, G4};
+        let mut synth = synth_with_voices(2, VoiceStealing::Quietest);
+        synth.note_on(C4, wmidi::U7::MAX);
+        synth.note_on(E4, wmidi::U7::MAX);
+        // The released note fades out, so it goes first even though it is the newer one
+        synth.note_off(E4);
+        let mut buffer = [0.0; 1024];
+        synth.play(44100, 1, &mut buffer);
+        synth.note_on(G4, wmidi::U7::MAX);
+        assert_eq!(sounding_notes(&synth), [C4, G4]);
+    }
+
+    #[test]
+    fn test_same_note_is_retriggered() {
+        use wmidi::Note::{C4, E4, G4};
+        let mut synth = synth_with_voices(4, VoiceStealing::SameNote);
+        synth.note_on(C4, wmidi::U7::MAX);
+        synth.note_on(C4, wmidi::U7::MAX);
+        assert_eq!(sounding_notes(&synth), [C4]);
+
+        // Other strategies give the repeated note a voice of its own
+        let mut synth = synth_with_voices(4, VoiceStealing::Quietest);
+        synth.note_on(C4, wmidi::U7::MAX);
+        synth.note_on(C4, wmidi::U7::MAX);
+        assert_eq!(sounding_notes(&synth), [C4, C4]);
+
+        // Without a voice playing the note, the oldest is stolen
+        let mut synth = synth_with_voices(2, VoiceStealing::SameNote);
+        for note in [C4, E4, G4] {
+            synth.note_on(note, wmidi::U7::MAX);
+        }
+        assert_eq!(sounding_notes(&synth), [E4, G4]);
+    }
+
+    #[test]
+    fn test_sounding_notes_keep_their_engine() {
+        use wmidi::Note::{C4, E4};
+        let mut synth = synth_with_voices(4, VoiceStealing::Oldest);
+        synth.note_on(C4, wmidi::U7::MAX);
+        synth.set_voice_engine(VoiceEngine::Waveguide);
+        synth.note_on(E4, wmidi::U7::MAX);
+        let engine = |note| {
+            synth
+                .voices
+                .iter()
+                .find(|voice| voice.current_key().is_some_and(|key| key.midi_note == note))
+                .map(|voice| voice.engine)
+        };
+        assert_eq!(engine(C4), Some(VoiceEngine::Additive));
+        assert_eq!(engine(E4), Some(VoiceEngine::Waveguide));
+        assert_eq!(sounding_notes(&synth), [C4, E4]);
+    }
+
+    #[test]
+    fn test_polyphony_changes_without_cutting_notes() {
+        use wmidi::Note::{C4, D4, E4, F4, G4};
+        let mut synth = synth_with_voices(4, VoiceStealing::Oldest);
+        for note in [C4, D4, E4, F4] {
+            synth.note_on(note, wmidi::U7::MAX);
+        }
+        // The sounding notes keep their voices until they have faded out
+        synth.set_polyphony(2);
+        assert_eq!(sounding_notes(&synth), [C4, D4, E4, F4]);
+        // but new notes can't use more than the new polyphony
+        synth.note_on(G4, wmidi::U7::MAX);
+        assert_eq!(sounding_notes(&synth), [D4, E4, F4, G4]);
+        for note in [D4, E4, F4, G4] {
+            synth.note_off(note);
+        }
+        let mut buffer = [0.0; 1024];
+        for _ in 0..100 {
+            synth.play(44100, 1, &mut buffer);
+        }
+        assert!(sounding_notes(&synth).is_empty());
+        assert_eq!(synth.voices.len(), 2);
+
+        synth.set_polyphony(16);
+        assert_eq!(synth.voices.len(), 16);
+        synth.set_polyphony(0);
+        assert_eq!(synth.voices.len(), 1);
+    }
 }
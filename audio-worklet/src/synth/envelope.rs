@@ -27,37 +27,51 @@ impl EnvelopeGenerator {
     /// - `release`: Release time in seconds
     /// - `sample_rate`: Sample rate in Hz
     pub fn new(attack: f32, decay: f32, sustain: f32, release: f32, sample_rate: f32) -> Self {
+        let mut envelope = Self {
+            sustain_level: sustain,
+            current_level: 0.0,
+            state: EnvelopeState::Idle,
+            // Will be set based on note frequency
+            sustain_decay_rate: 0.0,
+            attack_rate: None,
+            decay_rate: None,
+            release_rate: None,
+            velocity_level: 1.0, // Default full velocity
+        };
+        envelope.set_shape(attack, decay, sustain, release, sample_rate);
+        envelope
+    }
+
+    /// Change the ADSR parameters, as in [`Self::new`]. The current level and state are kept.
+    pub fn set_shape(
+        &mut self,
+        attack: f32,
+        decay: f32,
+        sustain: f32,
+        release: f32,
+        sample_rate: f32,
+    ) {
         const EPSILON: f32 = 0.000001;
 
-        let attack_rate = if attack > EPSILON {
+        self.attack_rate = if attack > EPSILON {
             Some(1.0 / (sample_rate * attack))
         } else {
             None // Immediate attack
         };
 
-        let decay_rate = if decay > EPSILON {
+        self.decay_rate = if decay > EPSILON {
             Some((1.0 - sustain) / (sample_rate * decay))
         } else {
             None // Immediate decay
         };
 
-        let release_rate = if release > EPSILON {
+        self.release_rate = if release > EPSILON {
             Some(1.0 / (sample_rate * release))
         } else {
             None // Immediate release
         };
 
-        Self {
-            sustain_level: sustain,
-            current_level: 0.0,
-            state: EnvelopeState::Idle,
-            // Will be set based on note frequency
-            sustain_decay_rate: 0.0,
-            attack_rate,
-            decay_rate,
-            release_rate,
-            velocity_level: 1.0, // Default full velocity
-        }
+        self.sustain_level = sustain;
     }
 
     pub fn trigger(&mut self) {
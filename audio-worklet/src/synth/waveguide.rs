@@ -0,0 +1,463 @@
+//! A piano string modelled as a digital waveguide, a Karplus-Strong loop with allpass filters for the
+//! dispersion of the stiff string. The partials come out as inharmonic as [`InharmonicityModel`] says
+//! instead of being placed one by one.
+
+use super::{
+    PianoKey, Voice,
+    envelope::{EnvelopeGenerator, EnvelopeState},
+};
+use crate::inharmonicity::InharmonicityModel;
+use shared_types::{SynthEnvelope, SynthSpectrum};
+use std::f32::consts::PI;
+
+/// The loop is tuned so that both the fundamental and this partial are where they should be
+const FITTED_PARTIAL: u32 = 8;
+/// Fitted partials are kept below this fraction of the sample rate, where the filters are well behaved
+const MAX_FITTED_FREQUENCY: f32 = 0.4;
+const MAX_DISPERSION_STAGES: usize = 4;
+/// Short strings have fewer dispersion stages, as each of them adds delay to the loop
+const SAMPLES_PER_DISPERSION_STAGE: f32 = 16.0;
+/// Keeps the dispersion allpass filters stable
+const MIN_DISPERSION_COEFFICIENT: f32 = -0.95;
+/// Lowest fundamental the delay line has room for without allocating when a note is played
+const LOWEST_FREQUENCY: f32 = 20.0;
+/// The fractional delay filter needs at least half a sample, and the delay line one more
+const MIN_DELAY: f32 = 1.5;
+/// Time for the fundamental to decay by 60 dB, at the reference frequency. Higher strings decay faster.
+/// Much slower than a real piano, so that many notes can be heard at once like with the additive voice.
+const DECAY_TIME: f32 = 30.0;
+const DECAY_REFERENCE_HZ: f32 = 110.0;
+/// Pole of the loss filter, higher values make the higher partials die out sooner
+const LOSS_POLE: f32 = 0.1;
+/// Envelope level the string fades out from once released
+const RELEASE_TIME: f32 = 0.3;
+/// Level below which the string counts as silent
+const SILENCE: f32 = 1e-5;
+const OUTPUT_GAIN: f32 = 0.5;
+
+/// First-order allpass filter y[n] = a·x[n] + x[n-1] - a·y[n-1]
+#[derive(Clone, Copy, Default)]
+struct Allpass {
+    coefficient: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl Allpass {
+    fn new(coefficient: f32) -> Self {
+        Self {
+            coefficient,
+            ..Self::default()
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.coefficient * (input - self.y1) + self.x1;
+        self.x1 = input;
+        self.y1 = output;
+        output
+    }
+
+    /// Delay in samples of a filter with `coefficient` at `omega` radians per sample.
+    /// Negative coefficients delay low frequencies more than high ones, like a stiff string does.
+    fn phase_delay(coefficient: f32, omega: f32) -> f32 {
+        let (sin, cos) = omega.sin_cos();
+        let phase =
+            (-sin).atan2(coefficient + cos) - (-coefficient * sin).atan2(1.0 + coefficient * cos);
+        -phase / omega
+    }
+}
+
+/// Delay in samples of the one-pole loss filter at `omega` radians per sample
+fn loss_phase_delay(omega: f32) -> f32 {
+    let (sin, cos) = omega.sin_cos();
+    (LOSS_POLE * sin).atan2(1.0 - LOSS_POLE * cos) / omega
+}
+
+/// Coefficient of the dispersion allpass stages that make the loop delay of `partial` shorter than
+/// that of the fundamental by `target` samples, given the delays the loss filter already causes
+fn fit_dispersion(stages: usize, omega: f32, partial_omega: f32, target: f32) -> f32 {
+    let difference = |coefficient: f32| {
+        stages as f32
+            * (Allpass::phase_delay(coefficient, omega)
+                - Allpass::phase_delay(coefficient, partial_omega))
+            + loss_phase_delay(omega)
+            - loss_phase_delay(partial_omega)
+    };
+    if difference(0.0) >= target {
+        return 0.0;
+    }
+    if difference(MIN_DISPERSION_COEFFICIENT) <= target {
+        return MIN_DISPERSION_COEFFICIENT;
+    }
+    // The difference grows as the coefficient gets more negative
+    const ITERATIONS: usize = 30;
+    let (mut low, mut high) = (MIN_DISPERSION_COEFFICIENT, 0.0);
+    for _ in 0..ITERATIONS {
+        let middle = (low + high) / 2.0;
+        if difference(middle) > target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+/// Deterministic noise for the hammer strike, so that every note sounds the same each time
+struct Noise(u32);
+
+impl Noise {
+    fn next(&mut self) -> f32 {
+        // xorshift32
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+pub(super) struct WaveguideVoice {
+    sample_rate: f32,
+    envelope: EnvelopeGenerator,
+    is_active: bool,
+    current_key: Option<PianoKey>,
+    /// Displacement of the string travelling around the loop
+    delay_line: Vec<f32>,
+    position: usize,
+    /// Gain of the loss filter per trip around the loop
+    loss_gain: f32,
+    loss_state: f32,
+    dispersion: [Allpass; MAX_DISPERSION_STAGES],
+    dispersion_stages: usize,
+    /// Tunes the loop to the fractional part of its delay
+    fractional_delay: Allpass,
+    /// Removes any DC the strike leaves in the string
+    dc_blocker: (f32, f32),
+    /// Peak level of the output, decaying over time
+    level: f32,
+    /// Factor on the stiffness of the strings, from the spectrum
+    stretch: f32,
+}
+
+impl WaveguideVoice {
+    pub(super) fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            envelope: EnvelopeGenerator::new(0.0, 0.0, 1.0, RELEASE_TIME, sample_rate),
+            is_active: false,
+            current_key: None,
+            delay_line: Vec::with_capacity((sample_rate / LOWEST_FREQUENCY) as usize),
+            position: 0,
+            loss_gain: 0.0,
+            loss_state: 0.0,
+            dispersion: [Allpass::default(); MAX_DISPERSION_STAGES],
+            dispersion_stages: 0,
+            fractional_delay: Allpass::default(),
+            dc_blocker: (0.0, 0.0),
+            level: 0.0,
+            stretch: SynthSpectrum::default().stretch,
+        }
+    }
+
+    /// Whether the delay line has room for `frequency`, and the loop can be made short enough for it
+    /// with at least a sample to spare beyond [`MIN_DELAY`]
+    fn can_play(&self, frequency: f32) -> bool {
+        let omega = 2.0 * PI * frequency / self.sample_rate;
+        (LOWEST_FREQUENCY..self.sample_rate / 2.0).contains(&frequency)
+            && self.sample_rate / frequency - loss_phase_delay(omega) >= MIN_DELAY + 1.0
+    }
+
+    /// Set up the loop filters and its length so that the partials of the string ring at the frequencies
+    /// that `inharmonicity` gives for `frequency`
+    fn tune(&mut self, frequency: f32, inharmonicity: &InharmonicityModel) {
+        let period = self.sample_rate / frequency;
+        let omega = 2.0 * PI * frequency / self.sample_rate;
+        let stages =
+            ((period / SAMPLES_PER_DISPERSION_STAGE) as usize).clamp(1, MAX_DISPERSION_STAGES);
+        let fitted_partial = (2..=FITTED_PARTIAL).rev().find(|&partial| {
+            inharmonicity.partial_frequency(frequency, partial)
+                < MAX_FITTED_FREQUENCY * self.sample_rate
+        });
+        let mut coefficient = fitted_partial.map_or(0.0, |partial| {
+            let partial_frequency = inharmonicity.partial_frequency(frequency, partial);
+            let partial_omega = 2.0 * PI * partial_frequency / self.sample_rate;
+            // Each partial needs a loop delay of a whole number of its own periods
+            let partial_period = partial as f32 * self.sample_rate / partial_frequency;
+            fit_dispersion(stages, omega, partial_omega, period - partial_period)
+        });
+        let remaining_delay = |coefficient: f32| {
+            period
+                - stages as f32 * Allpass::phase_delay(coefficient, omega)
+                - loss_phase_delay(omega)
+        };
+        if remaining_delay(coefficient) < MIN_DELAY {
+            // Too short a string for the dispersion it should have
+            coefficient = 0.0;
+        }
+        let delay = remaining_delay(coefficient);
+        let length = (delay - 0.5).floor();
+        let fraction = delay - length;
+        self.delay_line.clear();
+        self.delay_line.resize(length as usize, 0.0);
+        self.position = 0;
+        self.dispersion_stages = stages;
+        self.dispersion = [Allpass::new(coefficient); MAX_DISPERSION_STAGES];
+        self.fractional_delay = Allpass::new((1.0 - fraction) / (1.0 + fraction));
+        self.loss_state = 0.0;
+
+        let decay_time = DECAY_TIME * (DECAY_REFERENCE_HZ / frequency).sqrt();
+        const SILENCE_AFTER_DECAY: f32 = 0.001;
+        self.loss_gain = SILENCE_AFTER_DECAY.powf(1.0 / (decay_time * frequency));
+    }
+
+    /// Add the displacement of a hammer strike to the string. Harder strikes are brighter.
+    fn strike(&mut self, midi_note: u8, velocity: f32) {
+        const SOFT_SMOOTHING: f32 = 0.9;
+        const HARD_SMOOTHING: f32 = 0.3;
+        let smoothing = SOFT_SMOOTHING + (HARD_SMOOTHING - SOFT_SMOOTHING) * velocity;
+        // The noise is generated twice rather than stored, so that a note doesn't allocate
+        let smoothed_noise = || {
+            let mut noise = Noise(0x9e37_79b9 ^ u32::from(midi_note));
+            let mut smoothed = 0.0;
+            move || {
+                smoothed = smoothing * smoothed + (1.0 - smoothing) * noise.next();
+                smoothed
+            }
+        };
+        let mut strike = smoothed_noise();
+        let (mut sum, mut min, mut max) = (0.0, f32::MAX, f32::MIN);
+        for _ in 0..self.delay_line.len() {
+            let sample = strike();
+            sum += sample;
+            min = min.min(sample);
+            max = max.max(sample);
+        }
+        let mean = sum / self.delay_line.len() as f32;
+        let peak = (max - mean).max(mean - min).max(f32::EPSILON);
+        let mut strike = smoothed_noise();
+        for displacement in &mut self.delay_line {
+            *displacement += (strike() - mean) / peak * velocity;
+        }
+    }
+}
+
+impl Voice for WaveguideVoice {
+    fn note_on(&mut self, key: PianoKey, velocity: wmidi::U7) {
+        const VELOCITY_POWER_CURVE: f32 = 0.8;
+        const MIDI_VELOCITY_MAX: f32 = 127.0;
+        let velocity = (u8::from(velocity) as f32 / MIDI_VELOCITY_MAX).powf(VELOCITY_POWER_CURVE);
+        let midi_note = u8::from(key.midi_note);
+        if !self.can_play(key.frequency) {
+            // Like the additive voice's partials above Nyquist, such notes are left silent.
+            // Those below the lowest frequency are too low to be heard anyway.
+            self.current_key = None;
+            self.is_active = false;
+            return;
+        }
+        let restrike = self.is_active
+            && self
+                .current_key
+                .is_some_and(|current| current.midi_note == key.midi_note);
+        if !restrike {
+            // A string that is still vibrating is struck again, otherwise it starts at rest
+            let inharmonicity = InharmonicityModel::stretched_piano_string(midi_note, self.stretch);
+            self.tune(key.frequency, &inharmonicity);
+        }
+        self.current_key = Some(key);
+        self.strike(midi_note, velocity);
+        self.level = velocity;
+        self.envelope.trigger();
+        self.is_active = true;
+    }
+
+    fn note_off(&mut self) {
+        self.envelope.release();
+    }
+
+    fn set_spectrum(&mut self, spectrum: &SynthSpectrum) {
+        // The partial amplitudes come from the strike, only the stiffness can be changed.
+        // Retuning a sounding string would throw away its vibration, so that waits for the next note.
+        self.stretch = spectrum.stretch;
+    }
+
+    fn set_envelope(&mut self, _envelope: &SynthEnvelope) {
+        // The string is always struck and dies away by its own losses
+    }
+
+    #[inline]
+    fn process(&mut self) -> f32 {
+        if !self.is_active {
+            return 0.0;
+        }
+        let envelope = self.envelope.process();
+        if !self.envelope.is_active() || self.level * envelope < SILENCE {
+            self.is_active = false;
+            return 0.0;
+        }
+
+        let output = self.delay_line[self.position];
+        self.loss_state = self.loss_gain * (1.0 - LOSS_POLE) * output + LOSS_POLE * self.loss_state;
+        let mut feedback = self.loss_state;
+        for stage in &mut self.dispersion[..self.dispersion_stages] {
+            feedback = stage.process(feedback);
+        }
+        self.delay_line[self.position] = self.fractional_delay.process(feedback);
+        self.position = (self.position + 1) % self.delay_line.len();
+
+        const DC_BLOCKER_POLE: f32 = 0.995;
+        let (previous_input, previous_output) = self.dc_blocker;
+        let blocked = output - previous_input + DC_BLOCKER_POLE * previous_output;
+        self.dc_blocker = (output, blocked);
+
+        const LEVEL_DECAY: f32 = 0.9995;
+        self.level = (self.level * LEVEL_DECAY).max(blocked.abs());
+        blocked * envelope * OUTPUT_GAIN
+    }
+
+    fn is_active(&self) -> bool {
+        self.is_active
+    }
+
+    fn current_key(&self) -> Option<PianoKey> {
+        self.current_key
+    }
+
+    fn envelope_state(&self) -> &EnvelopeState {
+        &self.envelope.state
+    }
+
+    fn level(&self) -> f32 {
+        self.level * self.envelope.current_level()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44100.0;
+
+    /// Squared magnitude of the already windowed `samples` at `frequency`, with the Goertzel algorithm
+    fn power(samples: &[f64], frequency: f32) -> f64 {
+        let coefficient =
+            2.0 * (2.0 * std::f64::consts::PI * f64::from(frequency / SAMPLE_RATE)).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for &sample in samples {
+            (s1, s2) = (sample + coefficient * s1 - s2, s1);
+        }
+        s1 * s1 + s2 * s2 - coefficient * s1 * s2
+    }
+
+    /// The frequency within 10 cents of `expected` where `samples` are loudest
+    fn peak_frequency(samples: &[f32], expected: f32) -> f32 {
+        let windowed: Vec<f64> = samples
+            .iter()
+            .enumerate()
+            .map(|(n, &sample)| {
+                let window = 0.5 - 0.5 * (2.0 * PI * n as f32 / samples.len() as f32).cos();
+                f64::from(sample * window)
+            })
+            .collect();
+        const STEPS: i32 = 100;
+        const RANGE_CENTS: f32 = 10.0;
+        (-STEPS..=STEPS)
+            .map(|step| expected * 2f32.powf(RANGE_CENTS * step as f32 / STEPS as f32 / 1200.0))
+            .map(|frequency| (frequency, power(&windowed, frequency)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap()
+            .0
+    }
+
+    fn cents(a: f32, b: f32) -> f32 {
+        1200.0 * (a / b).log2()
+    }
+
+    #[test]
+    fn test_partials_follow_the_inharmonicity_model() {
+        for note in [wmidi::Note::C2, wmidi::Note::C4] {
+            let frequency = note.to_freq_f32();
+            let mut voice = WaveguideVoice::new(SAMPLE_RATE);
+            voice.note_on(PianoKey::new(note, frequency), wmidi::U7::MAX);
+            let samples: Vec<f32> = (0..SAMPLE_RATE as usize).map(|_| voice.process()).collect();
+
+            let model = SynthSpectrum::default().inharmonicity(u8::from(note));
+            for partial in 1..=FITTED_PARTIAL {
+                let expected = model.partial_frequency(frequency, partial);
+                let error = cents(peak_frequency(&samples, expected), expected);
+                // The allpass filters have a single coefficient, so only the fitted partials can be exact
+                let tolerance = if partial == 1 || partial == FITTED_PARTIAL {
+                    1.0
+                } else {
+                    5.0
+                };
+                assert!(
+                    error.abs() < tolerance,
+                    "partial {partial} of {note:?} is {error} cents off"
+                );
+            }
+            // Stiffness really does stretch the partials, by more than the tolerance
+            let stretch = cents(
+                model.partial_frequency(frequency, FITTED_PARTIAL),
+                FITTED_PARTIAL as f32 * frequency,
+            );
+            assert!(
+                stretch > 4.0,
+                "{note:?} is only stretched by {stretch} cents"
+            );
+        }
+    }
+
+    #[test]
+    fn test_released_string_falls_silent() {
+        let mut voice = WaveguideVoice::new(SAMPLE_RATE);
+        voice.note_on(
+            PianoKey::new(wmidi::Note::A4, wmidi::Note::A4.to_freq_f32()),
+            wmidi::U7::MAX,
+        );
+        let peak = (0..1000).map(|_| voice.process().abs()).fold(0.0, f32::max);
+        assert!(peak > 0.05, "{peak}");
+        voice.note_off();
+        for _ in 0..3 * SAMPLE_RATE as usize {
+            voice.process();
+        }
+        assert!(!voice.is_active());
+        assert_eq!(voice.process(), 0.0);
+    }
+
+    #[test]
+    fn test_very_high_notes_are_stable() {
+        let mut voice = WaveguideVoice::new(SAMPLE_RATE);
+        voice.note_on(
+            PianoKey::new(wmidi::Note::C8, wmidi::Note::C8.to_freq_f32()),
+            wmidi::U7::MAX,
+        );
+        for _ in 0..SAMPLE_RATE as usize {
+            let sample = voice.process();
+            assert!(sample.is_finite() && sample.abs() < 1.0, "{sample}");
+        }
+    }
+
+    #[test]
+    fn test_notes_at_and_above_nyquist_are_silent() {
+        for frequency in [
+            LOWEST_FREQUENCY / 2.0,
+            18000.0,
+            SAMPLE_RATE / 2.0,
+            SAMPLE_RATE,
+        ] {
+            let mut voice = WaveguideVoice::new(SAMPLE_RATE);
+            voice.note_on(PianoKey::new(wmidi::Note::G9, frequency), wmidi::U7::MAX);
+            assert!(!voice.is_active(), "{frequency} Hz");
+            assert_eq!(voice.process(), 0.0);
+        }
+        // The highest notes that fit are still played
+        let mut voice = WaveguideVoice::new(SAMPLE_RATE);
+        voice.note_on(PianoKey::new(wmidi::Note::G9, 15000.0), wmidi::U7::MAX);
+        assert!(voice.is_active());
+        for _ in 0..1000 {
+            assert!(voice.process().is_finite());
+        }
+    }
+}
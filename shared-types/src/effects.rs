@@ -0,0 +1,233 @@
+//! Parameters of the effects the synth sends its output through
+
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Parameters of the Schroeder reverb, each from 0 to 1
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReverbParam {
+    /// Feedback of the comb filters, which sets how long the reverb tail is
+    RoomSize,
+    /// How much the high frequencies of the tail are damped
+    Damping,
+    /// Level of the reverberated signal
+    Wet,
+    /// Level of the direct signal
+    Dry,
+    /// Stereo spread of the tail, which makes no difference as long as the synth plays in mono
+    Width,
+}
+
+/// Parameters of the peak limiter at the end of the chain
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimiterParam {
+    /// Level in dB above which the signal is turned down
+    Threshold,
+    /// Seconds for the limiter to react to a peak
+    Attack,
+    /// Seconds for the gain to recover after a peak
+    Release,
+    /// Gain in dB applied after limiting
+    MakeupGain,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectParam {
+    Reverb(ReverbParam),
+    Limiter(LimiterParam),
+}
+
+impl ReverbParam {
+    pub const ALL: [Self; 5] = [
+        Self::RoomSize,
+        Self::Damping,
+        Self::Wet,
+        Self::Dry,
+        Self::Width,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RoomSize => "room size",
+            Self::Damping => "damping",
+            Self::Wet => "wet",
+            Self::Dry => "dry",
+            Self::Width => "width",
+        }
+    }
+
+    pub const fn default_value(self) -> f32 {
+        match self {
+            Self::RoomSize => 0.7,
+            Self::Damping => 0.4,
+            Self::Wet => 0.5,
+            Self::Dry => 0.4,
+            Self::Width => 1.0,
+        }
+    }
+}
+
+impl LimiterParam {
+    pub const ALL: [Self; 4] = [
+        Self::Threshold,
+        Self::Attack,
+        Self::Release,
+        Self::MakeupGain,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Threshold => "threshold",
+            Self::Attack => "attack",
+            Self::Release => "release",
+            Self::MakeupGain => "makeup gain",
+        }
+    }
+
+    pub const fn default_value(self) -> f32 {
+        match self {
+            Self::Threshold => -3.0,
+            Self::Attack => 0.005,
+            Self::Release => 0.05,
+            Self::MakeupGain => 0.0,
+        }
+    }
+}
+
+impl EffectParam {
+    pub const ALL: [Self; ReverbParam::ALL.len() + LimiterParam::ALL.len()] = [
+        Self::Reverb(ReverbParam::RoomSize),
+        Self::Reverb(ReverbParam::Damping),
+        Self::Reverb(ReverbParam::Wet),
+        Self::Reverb(ReverbParam::Dry),
+        Self::Reverb(ReverbParam::Width),
+        Self::Limiter(LimiterParam::Threshold),
+        Self::Limiter(LimiterParam::Attack),
+        Self::Limiter(LimiterParam::Release),
+        Self::Limiter(LimiterParam::MakeupGain),
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Reverb(param) => param.name(),
+            Self::Limiter(param) => param.name(),
+        }
+    }
+
+    /// Values the effect accepts, anything outside is clamped
+    pub fn range(self) -> RangeInclusive<f32> {
+        match self {
+            Self::Reverb(_) => 0.0..=1.0,
+            Self::Limiter(LimiterParam::Threshold) => -60.0..=0.0,
+            Self::Limiter(LimiterParam::Attack) => 0.001..=1.0,
+            Self::Limiter(LimiterParam::Release) => 0.001..=3.0,
+            Self::Limiter(LimiterParam::MakeupGain) => 0.0..=30.0,
+        }
+    }
+
+    pub const fn default_value(self) -> f32 {
+        match self {
+            Self::Reverb(param) => param.default_value(),
+            Self::Limiter(param) => param.default_value(),
+        }
+    }
+
+    /// Unit shown after the value
+    pub fn unit(self) -> &'static str {
+        match self {
+            Self::Reverb(_) => "",
+            Self::Limiter(LimiterParam::Threshold | LimiterParam::MakeupGain) => " dB",
+            Self::Limiter(LimiterParam::Attack | LimiterParam::Release) => " s",
+        }
+    }
+
+    fn index(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|&param| param == self)
+            .expect("every parameter should be in ALL")
+    }
+}
+
+/// A value for every effect parameter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EffectSettings {
+    values: [f32; EffectParam::ALL.len()],
+}
+
+impl Default for EffectSettings {
+    fn default() -> Self {
+        Self {
+            values: EffectParam::ALL.map(EffectParam::default_value),
+        }
+    }
+}
+
+impl EffectSettings {
+    pub fn get(&self, param: EffectParam) -> f32 {
+        self.values[param.index()]
+    }
+
+    /// Set `param` to `value`, clamped to its range
+    pub fn set(&mut self, param: EffectParam, value: f32) {
+        let range = param.range();
+        self.values[param.index()] = value.clamp(*range.start(), *range.end());
+    }
+
+    /// Every parameter with its value
+    pub fn iter(&self) -> impl Iterator<Item = (EffectParam, f32)> + '_ {
+        EffectParam::ALL.into_iter().zip(self.values)
+    }
+
+    /// The values in the order of [`EffectParam::ALL`], separated by commas
+    pub fn storage_string(&self) -> String {
+        self.values
+            .iter()
+            .map(f32::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn from_storage_string(string: &str) -> Option<Self> {
+        let values: Vec<f32> = string
+            .split(',')
+            .map(|value| value.parse().ok())
+            .collect::<Option<_>>()?;
+        if values.len() != EffectParam::ALL.len() {
+            return None;
+        }
+        let mut settings = Self::default();
+        for (param, value) in EffectParam::ALL.into_iter().zip(values) {
+            settings.set(param, value);
+        }
+        Some(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_in_range() {
+        for param in EffectParam::ALL {
+            assert!(param.range().contains(&param.default_value()), "{param:?}");
+        }
+    }
+
+    #[test]
+    fn test_storage_string_round_trip() {
+        let mut settings = EffectSettings::default();
+        settings.set(EffectParam::Reverb(ReverbParam::Wet), 0.25);
+        settings.set(EffectParam::Limiter(LimiterParam::Threshold), -100.0);
+        assert_eq!(
+            settings.get(EffectParam::Limiter(LimiterParam::Threshold)),
+            -60.0
+        );
+        assert_eq!(
+            EffectSettings::from_storage_string(&settings.storage_string()),
+            Some(settings)
+        );
+        assert_eq!(EffectSettings::from_storage_string("0.5,0.5"), None);
+    }
+}
@@ -75,6 +75,13 @@ impl InharmonicityModel {
     pub fn coefficient(&self) -> f32 {
         self.coefficient
     }
+
+    /// Model of the piano string of `midi_note`, with its coefficient scaled by `stretch`.
+    /// A stretch of 0 gives harmonic partials, 1 the string as modelled.
+    pub fn stretched_piano_string(midi_note: u8, stretch: f32) -> Self {
+        let string = Self::from(PianoStringParameters::for_midi_note(midi_note));
+        Self::from_coefficient(string.coefficient * stretch)
+    }
 }
 
 /// Piano string parameters for different register ranges
@@ -1,12 +1,355 @@
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
+pub mod effects;
+pub mod inharmonicity;
+pub mod scala;
+pub mod tuning;
+
+/// Number of notes the synth plays at once unless told otherwise
+pub const DEFAULT_POLYPHONY: u8 = 8;
+pub const MAX_POLYPHONY: u8 = 64;
+/// How loud the strings ringing along with the played notes are while the sustain pedal is down, unless told otherwise
+pub const DEFAULT_SYMPATHETIC_RESONANCE: f32 = 0.3;
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum ToWorkletMessage {
-    NoteOn { note: u8, velocity: u8 },
-    NoteOff { note: u8 },
-    SustainPedal { active: bool },
+    NoteOn {
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        note: u8,
+    },
+    SustainPedal {
+        active: bool,
+    },
+    /// Frequency in Hz of every MIDI note, used for the notes played from now on.
+    /// Notes with a frequency of 0 are unmapped and stay silent.
+    SetTuning {
+        frequencies: Vec<f32>,
+    },
+    /// How many notes the synth can play at once. Voices that are sounding when it shrinks fade out first.
+    SetPolyphony {
+        voices: u8,
+    },
+    SetVoiceStealing {
+        strategy: VoiceStealing,
+    },
+    /// How loud the strings that ring along with the played notes are while the sustain pedal is down, from 0 to 1
+    SetSympatheticResonance {
+        wet_level: f32,
+    },
+    /// Engine that plays the notes started from now on
+    SetVoiceEngine {
+        engine: VoiceEngine,
+    },
+    /// Partials of the additive voices, applied to the sounding notes as well
+    SetSpectrum {
+        spectrum: SynthSpectrum,
+    },
+    /// Envelope and partials of `instrument` for the additive voices. The partials can be changed
+    /// with [`Self::SetSpectrum`] afterwards.
+    SetInstrument {
+        instrument: Instrument,
+    },
+    /// Set a parameter of the reverb or limiter, clamped to [`effects::EffectParam::range`]
+    SetEffectParam {
+        param: effects::EffectParam,
+        value: f32,
+    },
+}
+
+/// Which sounding voice the synth cuts off to play a new note when all of its voices are in use
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoiceStealing {
+    /// The voice whose note was started the longest ago
+    Oldest,
+    /// The voice that is the quietest, preferring voices that have been released
+    #[default]
+    Quietest,
+    /// A voice already playing the same note is restarted, even while other voices are free.
+    /// Otherwise the oldest voice is stolen.
+    SameNote,
+}
+
+impl VoiceStealing {
+    pub const ALL: [Self; 3] = [Self::Oldest, Self::Quietest, Self::SameNote];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Oldest => "oldest",
+            Self::Quietest => "quietest",
+            Self::SameNote => "same note",
+        }
+    }
+}
+
+/// How the synth makes the sound of a piano string
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VoiceEngine {
+    /// A sine oscillator for each partial, placed where the inharmonicity of the string puts it
+    #[default]
+    Additive,
+    /// A struck string simulated as a delay line loop, with allpass filters for the dispersion
+    Waveguide,
+}
+
+impl VoiceEngine {
+    pub const ALL: [Self; 2] = [Self::Additive, Self::Waveguide];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Additive => "additive",
+            Self::Waveguide => "waveguide",
+        }
+    }
+}
+
+/// Sustained partials the synth plays each note with
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SynthSpectrum {
+    /// Amplitudes of the partials from 0 to 1, starting with the fundamental
+    pub amplitudes: [f32; SynthSpectrum::NUM_PARTIALS],
+    /// Frequency of each partial as a multiple of the fundamental, before the stretch is applied
+    pub ratios: [f32; SynthSpectrum::NUM_PARTIALS],
+    /// Factor on the inharmonicity of the piano strings, 0 for unstretched partials and 1 for the modelled piano
+    pub stretch: f32,
+}
+
+impl Default for SynthSpectrum {
+    fn default() -> Self {
+        Instrument::default().spectrum()
+    }
+}
+
+impl SynthSpectrum {
+    pub const NUM_PARTIALS: usize = 16;
+    pub const MAX_STRETCH: f32 = 4.0;
+    /// Whole multiples of the fundamental
+    pub const HARMONIC_RATIOS: [f32; Self::NUM_PARTIALS] = {
+        let mut ratios = [0.0; Self::NUM_PARTIALS];
+        let mut index = 0;
+        while index < Self::NUM_PARTIALS {
+            ratios[index] = (index + 1) as f32;
+            index += 1;
+        }
+        ratios
+    };
+
+    /// Harmonic partials with the given amplitudes for the lowest partials, the rest silent
+    fn harmonic(amplitudes: &[f32], stretch: f32) -> Self {
+        let mut all_amplitudes = [0.0; Self::NUM_PARTIALS];
+        all_amplitudes[..amplitudes.len()].copy_from_slice(amplitudes);
+        Self {
+            amplitudes: all_amplitudes,
+            ratios: Self::HARMONIC_RATIOS,
+            stretch,
+        }
+    }
+
+    /// Inharmonicity of the string of `midi_note`, with the stretch applied
+    pub fn inharmonicity(&self, midi_note: u8) -> inharmonicity::InharmonicityModel {
+        inharmonicity::InharmonicityModel::stretched_piano_string(midi_note, self.stretch)
+    }
+
+    /// Frequency of the partial at `index`, 0 being the fundamental, of a note at `fundamental` Hz on a string
+    /// with `inharmonicity`. With harmonic ratios this is the same as
+    /// [`inharmonicity::InharmonicityModel::partial_frequency`].
+    pub fn partial_frequency(
+        &self,
+        inharmonicity: &inharmonicity::InharmonicityModel,
+        fundamental: f32,
+        index: usize,
+    ) -> f32 {
+        let ratio = self.ratios[index];
+        if ratio == 1.0 {
+            return fundamental;
+        }
+        ratio
+            * fundamental
+            * inharmonicity
+                .coefficient()
+                .mul_add(ratio * ratio, 1.0)
+                .sqrt()
+    }
+
+    /// The amplitudes, the ratios and then the stretch, separated by commas
+    pub fn storage_string(&self) -> String {
+        self.amplitudes
+            .iter()
+            .chain(&self.ratios)
+            .chain([&self.stretch])
+            .map(f32::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn from_storage_string(text: &str) -> Option<Self> {
+        let values: Vec<f32> = text
+            .split(',')
+            .map(|value| value.parse().ok())
+            .collect::<Option<_>>()?;
+        let (&stretch, values) = values.split_last()?;
+        if values.len() != 2 * Self::NUM_PARTIALS {
+            return None;
+        }
+        let (amplitudes, ratios) = values.split_at(Self::NUM_PARTIALS);
+        let mut amplitudes: [f32; Self::NUM_PARTIALS] = amplitudes.try_into().ok()?;
+        for amplitude in &mut amplitudes {
+            *amplitude = amplitude.clamp(0.0, 1.0);
+        }
+        if ratios
+            .iter()
+            .any(|&ratio| ratio <= 0.0 || !ratio.is_finite())
+        {
+            return None;
+        }
+        Some(Self {
+            amplitudes,
+            ratios: ratios.try_into().ok()?,
+            stretch: stretch.clamp(0.0, Self::MAX_STRETCH),
+        })
+    }
+}
+
+/// How the additive voices shape the loudness of each note over time
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SynthEnvelope {
+    /// Seconds to reach full level
+    pub attack: f32,
+    /// Seconds to fall from full level to the sustain level
+    pub decay: f32,
+    /// Level held while the key is down, from 0 to 1
+    pub sustain: f32,
+    /// Seconds to fade out once the key is released
+    pub release: f32,
+    /// How fast the sustain level dies away compared to a piano string, 0 to hold it as long as the key is down
+    pub sustain_decay: f32,
+    /// Whether notes start with the thump and noise of a hammer, with brighter partials, and ring on a second,
+    /// slightly detuned string like on a piano
+    pub struck: bool,
+}
+
+impl Default for SynthEnvelope {
+    fn default() -> Self {
+        Instrument::default().envelope()
+    }
+}
+
+/// Sounds the synth can play, each with its own partials and envelope
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Instrument {
+    #[default]
+    Piano,
+    /// Just the fundamental
+    Sine,
+    /// Harmonic drawbars
+    Organ,
+    /// A sawtooth-like harmonic spectrum that is held as long as the bow moves
+    BowedString,
+    /// Strongly inharmonic partials of a struck bell or gamelan metallophone
+    Bell,
+}
+
+impl Instrument {
+    pub const ALL: [Self; 5] = [
+        Self::Piano,
+        Self::Sine,
+        Self::Organ,
+        Self::BowedString,
+        Self::Bell,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Piano => "piano",
+            Self::Sine => "sine",
+            Self::Organ => "organ",
+            Self::BowedString => "bowed string",
+            Self::Bell => "bell",
+        }
+    }
+
+    pub fn spectrum(self) -> SynthSpectrum {
+        match self {
+            // The piano sound the synth was designed with, at medium velocity
+            Self::Piano => SynthSpectrum::harmonic(&[0.6, 0.4, 0.15, 0.14, 0.1], 1.0),
+            Self::Sine => SynthSpectrum::harmonic(&[1.0], 0.0),
+            // The 8', 4', 2 2/3', 2', 1 3/5', 1 1/3' and 1' drawbars, which are the 1st to 6th and 8th harmonics
+            Self::Organ => {
+                SynthSpectrum::harmonic(&[0.8, 0.6, 0.45, 0.45, 0.25, 0.3, 0.0, 0.25], 0.0)
+            }
+            // The bow locks the partials of the string into a harmonic series, falling off as 1/n
+            Self::BowedString => {
+                let mut amplitudes = [0.0; SynthSpectrum::NUM_PARTIALS];
+                for (index, amplitude) in amplitudes.iter_mut().enumerate() {
+                    const LEVEL: f32 = 0.6;
+                    *amplitude = LEVEL / (index + 1) as f32;
+                }
+                SynthSpectrum::harmonic(&amplitudes, 0.0)
+            }
+            // Prime, minor third tierce, quint, nominal and the partials above it of a church bell,
+            // whose higher partials are close to those of a gamelan bar
+            Self::Bell => SynthSpectrum {
+                amplitudes: [
+                    0.5, 0.35, 0.2, 0.6, 0.3, 0.25, 0.2, 0.15, 0.12, 0.1, 0.08, 0.06, 0.0, 0.0,
+                    0.0, 0.0,
+                ],
+                ratios: [
+                    1.0, 1.19, 1.5, 2.0, 2.51, 2.66, 3.01, 4.06, 5.05, 5.4, 6.08, 8.93, 9.0, 10.0,
+                    11.0, 12.0,
+                ],
+                stretch: 0.0,
+            },
+        }
+    }
+
+    pub fn envelope(self) -> SynthEnvelope {
+        match self {
+            Self::Piano => SynthEnvelope {
+                attack: 0.01,
+                decay: 0.1,
+                sustain: 0.7,
+                release: 0.3,
+                sustain_decay: 1.0,
+                struck: true,
+            },
+            Self::Sine => SynthEnvelope {
+                attack: 0.01,
+                decay: 0.0,
+                sustain: 1.0,
+                release: 0.1,
+                sustain_decay: 0.0,
+                struck: false,
+            },
+            Self::Organ => SynthEnvelope {
+                attack: 0.005,
+                decay: 0.0,
+                sustain: 1.0,
+                release: 0.05,
+                sustain_decay: 0.0,
+                struck: false,
+            },
+            Self::BowedString => SynthEnvelope {
+                attack: 0.12,
+                decay: 0.1,
+                sustain: 0.9,
+                release: 0.25,
+                sustain_decay: 0.0,
+                struck: false,
+            },
+            Self::Bell => SynthEnvelope {
+                attack: 0.002,
+                decay: 0.3,
+                sustain: 0.5,
+                release: 1.0,
+                sustain_decay: 2.0,
+                struck: false,
+            },
+        }
+    }
 }
 
 impl From<ToWorkletMessage> for JsValue {
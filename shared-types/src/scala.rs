@@ -0,0 +1,559 @@
+//! Parser for Scala scale (`.scl`) and keyboard mapping (`.kbm`) files
+//!
+//! The formats are described at <https://www.huygens-fokker.org/scala/scl_format.html>
+//! and in the Scala help under "Keyboard mappings".
+
+use crate::tuning::{Tuning, TuningTable};
+
+const CENTS_PER_OCTAVE: f64 = 1200.0;
+const NUM_NOTES: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("missing {0}")]
+    Missing(&'static str),
+    #[error("line {line}: invalid {what} {text:?}")]
+    Invalid {
+        line: usize,
+        what: &'static str,
+        text: String,
+    },
+    #[error("expected {expected} entries but found {found}")]
+    WrongCount { expected: usize, found: usize },
+    #[error("the scale has no notes")]
+    EmptyScale,
+    #[error("reference note {0} is not mapped to a scale degree")]
+    UnmappedReference(u8),
+}
+
+/// A scale degree, in cents or as a frequency ratio above the first note of the scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pitch {
+    Cents(f64),
+    Ratio(u64, u64),
+}
+
+impl Pitch {
+    pub fn cents(self) -> f64 {
+        match self {
+            Self::Cents(cents) => cents,
+            Self::Ratio(numerator, denominator) => {
+                CENTS_PER_OCTAVE * (numerator as f64 / denominator as f64).log2()
+            }
+        }
+    }
+}
+
+/// The contents of a `.scl` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    /// The degrees above the implicit 1/1. The last one is the period the scale repeats at, usually 2/1.
+    pub pitches: Vec<Pitch>,
+}
+
+/// The lines of a file that aren't comments, with their 1-based line numbers
+fn content_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_end_matches('\r')))
+        .filter(|(_, line)| !line.starts_with('!'))
+}
+
+/// The first whitespace separated word of a line, anything after it is a comment
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or_default()
+}
+
+fn parse_number<T: std::str::FromStr>(
+    line: usize,
+    text: &str,
+    what: &'static str,
+) -> Result<T, Error> {
+    first_word(text).parse().map_err(|_| Error::Invalid {
+        line,
+        what,
+        text: text.to_string(),
+    })
+}
+
+fn parse_pitch(line: usize, text: &str) -> Result<Pitch, Error> {
+    let invalid = || Error::Invalid {
+        line,
+        what: "pitch",
+        text: text.to_string(),
+    };
+    let word = first_word(text);
+    // A period means cents, anything else is a ratio, where a plain integer n means n/1
+    let pitch = if word.contains('.') {
+        Pitch::Cents(word.parse().map_err(|_| invalid())?)
+    } else {
+        let (numerator, denominator) = word.split_once('/').unwrap_or((word, "1"));
+        let numerator: u64 = numerator.parse().map_err(|_| invalid())?;
+        let denominator: u64 = denominator.parse().map_err(|_| invalid())?;
+        if numerator == 0 || denominator == 0 {
+            return Err(invalid());
+        }
+        Pitch::Ratio(numerator, denominator)
+    };
+    Ok(pitch)
+}
+
+impl Scale {
+    /// Parse the contents of a `.scl` file
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut lines = content_lines(text);
+        let (_, description) = lines.next().ok_or(Error::Missing("description"))?;
+        let (line, count) = lines.next().ok_or(Error::Missing("number of notes"))?;
+        let count: usize = parse_number(line, count, "number of notes")?;
+        let pitches = lines
+            .take(count)
+            .map(|(line, text)| parse_pitch(line, text))
+            .collect::<Result<Vec<_>, _>>()?;
+        if pitches.len() != count {
+            return Err(Error::WrongCount {
+                expected: count,
+                found: pitches.len(),
+            });
+        }
+        if pitches.is_empty() {
+            return Err(Error::EmptyScale);
+        }
+        Ok(Self {
+            description: description.trim().to_string(),
+            pitches,
+        })
+    }
+
+    /// Cents above the first note of the scale of `degree`, which may be beyond the period or negative
+    fn degree_cents(&self, degree: i64) -> f64 {
+        let len = self.pitches.len() as i64;
+        let periods = degree.div_euclid(len);
+        let within = degree.rem_euclid(len);
+        let period_cents = self.pitches.last().map_or(CENTS_PER_OCTAVE, |p| p.cents());
+        let within_cents = match within {
+            0 => 0.0,
+            _ => self.pitches[within as usize - 1].cents(),
+        };
+        periods as f64 * period_cents + within_cents
+    }
+}
+
+/// The contents of a `.kbm` file, which says how the degrees of a scale are laid out on the MIDI keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    /// Number of keys the mapping pattern repeats after, 0 for mapping the keys to consecutive degrees
+    pub size: usize,
+    pub first_note: u8,
+    pub last_note: u8,
+    /// The key that the first entry of the mapping, and degree 0 when the size is 0, is on
+    pub middle_note: u8,
+    pub reference_note: u8,
+    pub reference_frequency: f64,
+    /// The degree that one repetition of the pattern moves by, 0 for the period of the scale
+    pub octave_degree: usize,
+    /// Scale degree of each key in the pattern, starting at the middle note. `None` for keys that are unmapped.
+    /// Entries missing at the end are unmapped.
+    pub mapping: Vec<Option<usize>>,
+}
+
+impl Default for KeyboardMapping {
+    /// Consecutive keys play consecutive degrees, with the first degree on middle C at its usual frequency.
+    /// This is what Scala uses when no mapping is given.
+    fn default() -> Self {
+        const MIDDLE_C: u8 = 60;
+        Self {
+            size: 0,
+            first_note: 0,
+            last_note: (NUM_NOTES - 1) as u8,
+            middle_note: MIDDLE_C,
+            reference_note: MIDDLE_C,
+            reference_frequency: f64::from(Tuning::default().frequency(MIDDLE_C)),
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+}
+
+impl KeyboardMapping {
+    /// Parse the contents of a `.kbm` file
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut lines = content_lines(text).filter(|(_, line)| !line.trim().is_empty());
+        let mut next = |what: &'static str| lines.next().ok_or(Error::Missing(what));
+        let note = |(line, text): (usize, &str), what| -> Result<u8, Error> {
+            let note: u8 = parse_number(line, text, what)?;
+            if usize::from(note) < NUM_NOTES {
+                Ok(note)
+            } else {
+                Err(Error::Invalid {
+                    line,
+                    what,
+                    text: text.to_string(),
+                })
+            }
+        };
+        let (line, text) = next("map size")?;
+        let size = parse_number(line, text, "map size")?;
+        let first_note = note(next("first note")?, "first note")?;
+        let last_note = note(next("last note")?, "last note")?;
+        let middle_note = note(next("middle note")?, "middle note")?;
+        let reference_note = note(next("reference note")?, "reference note")?;
+        let (line, text) = next("reference frequency")?;
+        let reference_frequency: f64 = parse_number(line, text, "reference frequency")?;
+        if !(reference_frequency.is_finite() && reference_frequency > 0.0) {
+            return Err(Error::Invalid {
+                line,
+                what: "reference frequency",
+                text: text.to_string(),
+            });
+        }
+        let (line, text) = next("octave degree")?;
+        let octave_degree = parse_number(line, text, "octave degree")?;
+        let mapping = lines
+            .map(|(line, text)| {
+                if first_word(text).eq_ignore_ascii_case("x") {
+                    Ok(None)
+                } else {
+                    parse_number(line, text, "scale degree").map(Some)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if mapping.len() > size {
+            return Err(Error::WrongCount {
+                expected: size,
+                found: mapping.len(),
+            });
+        }
+        Ok(Self {
+            size,
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree,
+            mapping,
+        })
+    }
+
+    /// The scale degree played by `note`, `None` if it is unmapped
+    fn degree(&self, scale: &Scale, note: u8) -> Option<i64> {
+        let steps = i64::from(note) - i64::from(self.middle_note);
+        if self.size == 0 {
+            return Some(steps);
+        }
+        let size = self.size as i64;
+        let octave_degree = match self.octave_degree {
+            0 => scale.pitches.len(),
+            degree => degree,
+        } as i64;
+        let entry = (*self.mapping.get(steps.rem_euclid(size) as usize)?)? as i64;
+        Some(steps.div_euclid(size) * octave_degree + entry)
+    }
+
+    /// Frequency of every MIDI note when playing `scale` with this mapping.
+    /// Notes outside of the retuned range keep their usual 12-TET frequency.
+    pub fn frequency_table(&self, scale: &Scale) -> Result<TuningTable, Error> {
+        let reference_cents = scale.degree_cents(
+            self.degree(scale, self.reference_note)
+                .ok_or(Error::UnmappedReference(self.reference_note))?,
+        );
+        let equal = Tuning::default();
+        let frequencies = std::array::from_fn(|note| {
+            let note = note as u8;
+            if !(self.first_note..=self.last_note).contains(&note) {
+                return equal.frequency(note);
+            }
+            match self.degree(scale, note) {
+                Some(degree) => {
+                    let cents = scale.degree_cents(degree) - reference_cents;
+                    (self.reference_frequency * 2f64.powf(cents / CENTS_PER_OCTAVE)) as f32
+                }
+                None => TuningTable::UNMAPPED,
+            }
+        });
+        Ok(TuningTable::new(frequencies))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f64 = 0.001;
+
+    fn assert_approx_eq(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < TOLERANCE,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    /// Example from the Scala scale file format description
+    const MEANTONE_SCL: &str = "\
+! meanquar.scl
+!
+1/4-comma meantone scale. Pietro Aaron's temperament (1523)
+ 12
+!
+ 76.04900
+ 193.15686
+ 310.26471
+ 5/4
+ 503.42157
+ 579.47057
+ 696.57843
+ 25/16
+ 889.73529
+ 1006.84314
+ 1082.89214
+ 2/1
+";
+
+    /// Example of the pitch notations allowed by the format description
+    const NOTATIONS_SCL: &str = "\
+! notations.scl
+!
+Pitch notations
+ 9
+!
+ 81/64
+ 408.0
+ 408.
+ 5
+ -5.0
+ 10/20
+ 100.0 cents
+ 100.0 C#
+ 5/4   E\\
+";
+
+    /// The keyboard mapping template from the Scala help, with a whole tone scale on the white keys
+    const TEMPLATE_KBM: &str = "\
+! Template for a keyboard mapping
+!
+! Size of map. The pattern repeats every so many keys:
+12
+! First MIDI note number to retune:
+0
+! Last MIDI note number to retune:
+127
+! Middle note where the first entry of the mapping is mapped to:
+60
+! Reference note for which frequency is given:
+69
+! Frequency to tune the above note to (floating point e.g. 440.0):
+440.0
+! Scale degree to consider as formal octave (determines difference in pitch
+! between adjacent mapping patterns):
+12
+! Mapping.
+! The numbers represent scale degrees mapped to keys. The first entry is for
+! the given middle note, the next for subsequent higher keys.
+! For an unmapped key, put in an \"x\". At the end, unmapped keys may be left out.
+0
+x
+2
+x
+4
+5
+x
+7
+x
+9
+x
+11
+";
+
+    #[test]
+    fn test_parse_meantone_scale() {
+        let scale = Scale::parse(MEANTONE_SCL).unwrap();
+        assert_eq!(
+            scale.description,
+            "1/4-comma meantone scale. Pietro Aaron's temperament (1523)"
+        );
+        assert_eq!(scale.pitches.len(), 12);
+        assert_eq!(scale.pitches[0], Pitch::Cents(76.049));
+        assert_eq!(scale.pitches[3], Pitch::Ratio(5, 4));
+        assert_eq!(scale.pitches[11], Pitch::Ratio(2, 1));
+        // The ratios agree with the cents values around them
+        assert_approx_eq(scale.pitches[3].cents(), 386.31371);
+        assert_approx_eq(scale.pitches[7].cents(), 2.0 * 386.31371);
+    }
+
+    #[test]
+    fn test_parse_pitch_notations() {
+        let scale = Scale::parse(NOTATIONS_SCL).unwrap();
+        assert_eq!(
+            scale.pitches,
+            vec![
+                Pitch::Ratio(81, 64),
+                Pitch::Cents(408.0),
+                Pitch::Cents(408.0),
+                Pitch::Ratio(5, 1),
+                Pitch::Cents(-5.0),
+                Pitch::Ratio(10, 20),
+                Pitch::Cents(100.0),
+                Pitch::Cents(100.0),
+                Pitch::Ratio(5, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_scale_errors() {
+        assert_eq!(
+            Scale::parse("! only a comment\n"),
+            Err(Error::Missing("description"))
+        );
+        assert_eq!(
+            Scale::parse("too few\n 3\n 100.0\n 2/1\n"),
+            Err(Error::WrongCount {
+                expected: 3,
+                found: 2
+            })
+        );
+        assert!(matches!(
+            Scale::parse("negative ratio\n 1\n -3/2\n"),
+            Err(Error::Invalid { line: 3, .. })
+        ));
+        assert!(matches!(
+            Scale::parse("zero ratio\n 1\n 0/1\n"),
+            Err(Error::Invalid { line: 3, .. })
+        ));
+        assert_eq!(Scale::parse("empty\n 0\n"), Err(Error::EmptyScale));
+    }
+
+    #[test]
+    fn test_empty_description() {
+        let scale = Scale::parse("\n 1\n 2/1\n").unwrap();
+        assert_eq!(scale.description, "");
+        assert_eq!(scale.pitches, vec![Pitch::Ratio(2, 1)]);
+    }
+
+    #[test]
+    fn test_parse_keyboard_mapping() {
+        let mapping = KeyboardMapping::parse(TEMPLATE_KBM).unwrap();
+        assert_eq!(mapping.size, 12);
+        assert_eq!((mapping.first_note, mapping.last_note), (0, 127));
+        assert_eq!(mapping.middle_note, 60);
+        assert_eq!(mapping.reference_note, 69);
+        assert_eq!(mapping.reference_frequency, 440.0);
+        assert_eq!(mapping.octave_degree, 12);
+        assert_eq!(mapping.mapping.len(), 12);
+        assert_eq!(mapping.mapping[0], Some(0));
+        assert_eq!(mapping.mapping[1], None);
+        assert_eq!(mapping.mapping[11], Some(11));
+    }
+
+    #[test]
+    fn test_parse_keyboard_mapping_errors() {
+        assert_eq!(
+            KeyboardMapping::parse("12\n0\n127\n60\n"),
+            Err(Error::Missing("reference note"))
+        );
+        assert!(matches!(
+            KeyboardMapping::parse("0\n0\n128\n60\n69\n440.0\n0\n"),
+            Err(Error::Invalid {
+                what: "last note",
+                ..
+            })
+        ));
+        assert!(matches!(
+            KeyboardMapping::parse("0\n0\n127\n60\n69\n-440.0\n0\n"),
+            Err(Error::Invalid {
+                what: "reference frequency",
+                ..
+            })
+        ));
+        assert_eq!(
+            KeyboardMapping::parse("1\n0\n127\n60\n69\n440.0\n1\n0\n1\n"),
+            Err(Error::WrongCount {
+                expected: 1,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_default_mapping_frequencies() {
+        let scale = Scale::parse(MEANTONE_SCL).unwrap();
+        let table = KeyboardMapping::default().frequency_table(&scale).unwrap();
+        let frequency = |note: u8| f64::from(table.frequency(note).unwrap());
+        assert_approx_eq(frequency(60), 261.6256);
+        assert_approx_eq(frequency(72), 2.0 * 261.6256);
+        assert_approx_eq(frequency(48), 261.6256 / 2.0);
+        // Middle C plus a pure major third
+        assert_approx_eq(frequency(64), 261.6256 * 5.0 / 4.0);
+        assert_approx_eq(frequency(61) / frequency(60), 2f64.powf(76.049 / 1200.0));
+    }
+
+    #[test]
+    fn test_mapped_frequencies() {
+        let scale = Scale::parse(MEANTONE_SCL).unwrap();
+        let table = KeyboardMapping::parse(TEMPLATE_KBM)
+            .unwrap()
+            .frequency_table(&scale)
+            .unwrap();
+        let frequency = |note: u8| f64::from(table.frequency(note).unwrap());
+        assert_approx_eq(frequency(69), 440.0);
+        assert_approx_eq(frequency(81), 880.0);
+        // A down to C is a meantone major sixth
+        assert_approx_eq(frequency(60), 440.0 / 2f64.powf(889.73529 / 1200.0));
+        // Black keys are unmapped
+        assert_eq!(table.frequency(61), None);
+        assert_eq!(table.frequency(70), None);
+        assert_eq!(table.frequency(1), None);
+    }
+
+    #[test]
+    fn test_mapping_with_repeating_degrees_and_range() {
+        // A 5 note scale on the white keys, where each pattern of 12 keys moves up the period of the scale
+        let scale = Scale::parse("pentatonic\n5\n9/8\n5/4\n3/2\n5/3\n2/1\n").unwrap();
+        let mapping = KeyboardMapping {
+            size: 12,
+            first_note: 48,
+            last_note: 83,
+            middle_note: 60,
+            reference_note: 60,
+            reference_frequency: 256.0,
+            octave_degree: 0,
+            mapping: vec![
+                Some(0),
+                None,
+                Some(1),
+                None,
+                Some(2),
+                None,
+                None,
+                Some(3),
+                None,
+                Some(4),
+            ],
+        };
+        let table = mapping.frequency_table(&scale).unwrap();
+        let frequency = |note: u8| f64::from(table.frequency(note).unwrap());
+        assert_approx_eq(frequency(60), 256.0);
+        assert_approx_eq(frequency(67), 256.0 * 3.0 / 2.0);
+        assert_approx_eq(frequency(72), 512.0);
+        assert_approx_eq(frequency(57), 256.0 * 5.0 / 6.0);
+        // Missing entries at the end of the pattern are unmapped
+        assert_eq!(table.frequency(71), None);
+        // Outside of the retuned range the notes keep their 12-TET frequencies
+        assert_approx_eq(frequency(47), f64::from(Tuning::default().frequency(47)));
+        assert_approx_eq(frequency(84), f64::from(Tuning::default().frequency(84)));
+    }
+
+    #[test]
+    fn test_unmapped_reference_note() {
+        let scale = Scale::parse(MEANTONE_SCL).unwrap();
+        let mapping = KeyboardMapping {
+            reference_note: 61,
+            ..KeyboardMapping::parse(TEMPLATE_KBM).unwrap()
+        };
+        assert_eq!(
+            mapping.frequency_table(&scale),
+            Err(Error::UnmappedReference(61))
+        );
+    }
+}
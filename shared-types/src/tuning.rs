@@ -0,0 +1,442 @@
+/// Tuning systems used both for synthesis and for analysis
+///
+/// Each temperament is described as the deviation of every pitch class from 12-tone equal temperament,
+/// with the tonic of the temperament as pitch class 0. The tuning then places those pitch classes
+/// relative to a reference A4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Temperament {
+    /// 12-tone equal temperament
+    Equal,
+    /// 5-limit just intonation, with the same ratios as the interval display
+    Just,
+    /// Pure fifths, from the minor third to the augmented fifth above the tonic
+    Pythagorean,
+    /// Fifths narrowed by a quarter of the syntonic comma, which makes the major thirds pure
+    QuarterCommaMeantone,
+    WerckmeisterIII,
+    KirnbergerIII,
+    Vallotti,
+}
+
+const CENTS_PER_OCTAVE: f32 = 1200.0;
+const CENTS_PER_SEMITONE: f32 = 100.0;
+const PITCH_CLASSES: u8 = 12;
+const NUM_NOTES: usize = 128;
+const A4: u8 = 69;
+const C4: u8 = 60;
+
+fn ratio_cents(ratio: f32) -> f32 {
+    CENTS_PER_OCTAVE * ratio.log2()
+}
+
+impl Temperament {
+    pub const ALL: [Self; 7] = [
+        Self::Equal,
+        Self::Just,
+        Self::Pythagorean,
+        Self::QuarterCommaMeantone,
+        Self::WerckmeisterIII,
+        Self::KirnbergerIII,
+        Self::Vallotti,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Equal => "12-TET",
+            Self::Just => "just intonation",
+            Self::Pythagorean => "Pythagorean",
+            Self::QuarterCommaMeantone => "1/4-comma meantone",
+            Self::WerckmeisterIII => "Werckmeister III",
+            Self::KirnbergerIII => "Kirnberger III",
+            Self::Vallotti => "Vallotti",
+        }
+    }
+
+    /// Stable identifier for persisting the setting
+    pub fn storage_key(self) -> &'static str {
+        match self {
+            Self::Equal => "equal",
+            Self::Just => "just",
+            Self::Pythagorean => "pythagorean",
+            Self::QuarterCommaMeantone => "quarter_comma_meantone",
+            Self::WerckmeisterIII => "werckmeister_iii",
+            Self::KirnbergerIII => "kirnberger_iii",
+            Self::Vallotti => "vallotti",
+        }
+    }
+
+    pub fn from_storage_key(key: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|temperament| temperament.storage_key() == key)
+    }
+
+    /// Deviation from equal temperament in cents of each pitch class above the tonic
+    pub fn offsets_cents(self) -> [f32; 12] {
+        match self {
+            Self::Equal => [0.0; 12],
+            Self::Just => {
+                const RATIOS: [(u16, u16); 12] = [
+                    (1, 1),
+                    (16, 15),
+                    (9, 8),
+                    (6, 5),
+                    (5, 4),
+                    (4, 3),
+                    (45, 32),
+                    (3, 2),
+                    (8, 5),
+                    (5, 3),
+                    (9, 5),
+                    (15, 8),
+                ];
+                std::array::from_fn(|pitch_class| {
+                    let (numerator, denominator) = RATIOS[pitch_class];
+                    ratio_cents(f32::from(numerator) / f32::from(denominator))
+                        - CENTS_PER_SEMITONE * pitch_class as f32
+                })
+            }
+            Self::Pythagorean => chain_of_fifths(ratio_cents(3.0 / 2.0)),
+            Self::QuarterCommaMeantone => chain_of_fifths(ratio_cents(5.0) / 4.0),
+            // The well temperaments are usually given as cents tables
+            Self::WerckmeisterIII => [
+                0.0, -9.775, -7.82, -5.865, -9.775, -1.955, -11.73, -3.91, -7.82, -11.73, -3.91,
+                -7.82,
+            ],
+            Self::KirnbergerIII => [
+                0.0, -9.775, -6.843, -5.865, -13.686, -1.955, -9.775, -3.422, -7.82, -10.265,
+                -3.91, -11.73,
+            ],
+            Self::Vallotti => [
+                0.0, -5.865, -3.91, -1.955, -7.82, 1.955, -7.82, -1.955, -3.91, -5.865, 0.0, -9.775,
+            ],
+        }
+    }
+}
+
+/// Offsets of a temperament built from a chain of equal fifths, from the minor third below the tonic's
+/// chain position to the augmented fifth. The wolf fifth ends up between the augmented fifth and the minor third.
+fn chain_of_fifths(fifth_cents: f32) -> [f32; 12] {
+    const FIRST_FIFTH: i32 = -3;
+    let mut offsets = [0.0; 12];
+    for position in FIRST_FIFTH..FIRST_FIFTH + i32::from(PITCH_CLASSES) {
+        let pitch_class = (7 * position).rem_euclid(i32::from(PITCH_CLASSES));
+        let cents = (position as f32 * fifth_cents).rem_euclid(CENTS_PER_OCTAVE);
+        offsets[pitch_class as usize] = cents - CENTS_PER_SEMITONE * pitch_class as f32;
+    }
+    offsets
+}
+
+/// A temperament placed on a tonic and a reference pitch
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tuning {
+    /// Number of steps in an octave. With 12 the temperament is used, any other number divides the octave
+    /// into that many equal steps, with one MIDI note per step.
+    pub steps_per_octave: u8,
+    pub temperament: Temperament,
+    /// Pitch class the temperament is built on, 0 for C.
+    /// The well temperaments are normally tuned from C, other tonics transpose them.
+    pub tonic: u8,
+    /// Frequency of A4 in Hz. A4 is always exactly this, whatever the temperament.
+    pub a4_frequency: f32,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self {
+            steps_per_octave: PITCH_CLASSES,
+            temperament: Temperament::Equal,
+            tonic: 0,
+            a4_frequency: Self::STANDARD_A4_FREQUENCY,
+        }
+    }
+}
+
+impl Tuning {
+    pub const STANDARD_A4_FREQUENCY: f32 = 440.0;
+
+    /// The divisions of the octave that can be chosen. 12 is the usual semitones, the others are
+    /// equal divisions with good approximations of just intervals.
+    pub const STEPS_PER_OCTAVE_CHOICES: [u8; 6] = [12, 19, 22, 24, 31, 53];
+
+    /// Whether the octave is divided into something other than the 12 semitones of the temperaments
+    pub fn is_edo(&self) -> bool {
+        self.steps_per_octave != PITCH_CLASSES
+    }
+
+    /// Size of a step of the equal division of the octave, in cents
+    fn edo_step_cents(&self) -> f32 {
+        CENTS_PER_OCTAVE / f32::from(self.steps_per_octave)
+    }
+
+    /// Deviation of a MIDI note from equal temperament in cents, before applying the reference pitch
+    fn offset_cents(&self, note: u8) -> f32 {
+        let degree =
+            (note % PITCH_CLASSES + PITCH_CLASSES - self.tonic % PITCH_CLASSES) % PITCH_CLASSES;
+        self.temperament.offsets_cents()[usize::from(degree)]
+    }
+
+    /// Frequency in Hz of a MIDI note.
+    /// In an equal division of the octave middle C keeps its 12-TET pitch and every note above it is a step higher,
+    /// since A4 isn't one of the steps in most divisions.
+    pub fn frequency(&self, note: u8) -> f32 {
+        if self.is_edo() {
+            let c4_cents_from_a4 = CENTS_PER_SEMITONE * (f32::from(C4) - f32::from(A4));
+            let cents_from_a4 =
+                c4_cents_from_a4 + self.edo_step_cents() * (f32::from(note) - f32::from(C4));
+            return self.a4_frequency * 2f32.powf(cents_from_a4 / CENTS_PER_OCTAVE);
+        }
+        let cents_from_a4 = CENTS_PER_SEMITONE * (f32::from(note) - f32::from(A4))
+            + self.offset_cents(note)
+            - self.offset_cents(A4);
+        self.a4_frequency * 2f32.powf(cents_from_a4 / CENTS_PER_OCTAVE)
+    }
+
+    /// Frequencies in Hz of all MIDI notes, for sending to the synth
+    pub fn frequency_table(&self) -> TuningTable {
+        TuningTable::new(std::array::from_fn(|note| self.frequency(note as u8)))
+    }
+
+    /// Size in cents of the interval of `semitones` above `root` when played in this tuning.
+    /// Only depends on the pitch class of `root`. In an equal division of the octave `semitones` counts steps.
+    pub fn interval_cents(&self, root: u8, semitones: u8) -> f32 {
+        if self.is_edo() {
+            return self.edo_step_cents() * f32::from(semitones);
+        }
+        let root = root % PITCH_CLASSES;
+        CENTS_PER_SEMITONE * f32::from(semitones)
+            + self.offset_cents(root + semitones % PITCH_CLASSES)
+            - self.offset_cents(root)
+    }
+}
+
+/// Frequency of every MIDI note, which is what the synth plays from and what the analysis compares against.
+/// Any tuning can be described this way, including ones loaded from Scala files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningTable {
+    /// Frequency in Hz of each MIDI note, 0 for notes that aren't mapped to any pitch and stay silent
+    frequencies: [f32; NUM_NOTES],
+}
+
+impl Default for TuningTable {
+    fn default() -> Self {
+        Tuning::default().frequency_table()
+    }
+}
+
+impl TuningTable {
+    pub const UNMAPPED: f32 = 0.0;
+
+    pub fn new(frequencies: [f32; NUM_NOTES]) -> Self {
+        Self { frequencies }
+    }
+
+    pub fn frequencies(&self) -> &[f32; NUM_NOTES] {
+        &self.frequencies
+    }
+
+    /// Frequency in Hz of a MIDI note, `None` if it isn't mapped
+    pub fn frequency(&self, note: u8) -> Option<f32> {
+        self.frequencies
+            .get(usize::from(note))
+            .copied()
+            .filter(|&frequency| frequency > Self::UNMAPPED)
+    }
+
+    /// Size in cents of the interval from `root` to `semitones` above it.
+    /// `None` if either note is outside of the MIDI range or isn't mapped.
+    pub fn interval_cents(&self, root: u8, semitones: u8) -> Option<f32> {
+        let lower = self.frequency(root)?;
+        let upper = self.frequency(root.checked_add(semitones)?)?;
+        Some(ratio_cents(upper / lower))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: f32 = 0.01;
+
+    fn assert_approx_eq(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < TOLERANCE,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    fn tuning(temperament: Temperament) -> Tuning {
+        Tuning {
+            temperament,
+            ..Tuning::default()
+        }
+    }
+
+    #[test]
+    fn test_equal_temperament_frequencies() {
+        let tuning = Tuning::default();
+        assert_approx_eq(tuning.frequency(A4), 440.0);
+        assert_approx_eq(tuning.frequency(A4 + 12), 880.0);
+        assert_approx_eq(tuning.frequency(C4), 261.63);
+        for note in 0..128 {
+            assert_approx_eq(tuning.interval_cents(note, 7), 7.0 * CENTS_PER_SEMITONE);
+        }
+    }
+
+    #[test]
+    fn test_a4_is_the_reference_in_every_temperament() {
+        for temperament in Temperament::ALL {
+            for tonic in 0..PITCH_CLASSES {
+                let tuning = Tuning {
+                    temperament,
+                    tonic,
+                    a4_frequency: 415.0,
+                    ..Tuning::default()
+                };
+                assert_approx_eq(tuning.frequency(A4), 415.0);
+                // Octaves are always pure
+                assert_approx_eq(tuning.frequency(A4 - 12), 207.5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_just_intonation_is_pure_from_the_tonic() {
+        let tuning = Tuning {
+            temperament: Temperament::Just,
+            tonic: 2, // D
+            ..Tuning::default()
+        };
+        let d4 = 62;
+        let ratio = |semitones: u8| tuning.frequency(d4 + semitones) / tuning.frequency(d4);
+        assert_approx_eq(ratio(4), 5.0 / 4.0);
+        assert_approx_eq(ratio(7), 3.0 / 2.0);
+        assert_approx_eq(ratio(9), 5.0 / 3.0);
+        assert_approx_eq(tuning.interval_cents(d4, 4), ratio_cents(5.0 / 4.0));
+    }
+
+    #[test]
+    fn test_pythagorean_fifths_are_pure() {
+        let tuning = tuning(Temperament::Pythagorean);
+        // Every fifth in the chain from Eb to G# is pure
+        for root in [3, 10, 5, 0, 7, 2, 9, 4, 11, 6, 1] {
+            assert_approx_eq(tuning.interval_cents(root, 7), ratio_cents(1.5));
+        }
+        // The wolf fifth from G# to Eb
+        assert!(tuning.interval_cents(8, 7) < 680.0);
+    }
+
+    #[test]
+    fn test_meantone_major_thirds_are_pure() {
+        let tuning = tuning(Temperament::QuarterCommaMeantone);
+        for root in [0, 2, 5, 7, 9, 10, 3] {
+            assert_approx_eq(tuning.interval_cents(root, 4), ratio_cents(5.0 / 4.0));
+        }
+    }
+
+    #[test]
+    fn test_well_temperaments_are_close_to_equal() {
+        for temperament in [
+            Temperament::WerckmeisterIII,
+            Temperament::KirnbergerIII,
+            Temperament::Vallotti,
+        ] {
+            let tuning = tuning(temperament);
+            // All keys are usable, no fifth is further than a Pythagorean comma from pure
+            for root in 0..PITCH_CLASSES {
+                let fifth = tuning.interval_cents(root, 7);
+                assert!(
+                    (fifth - ratio_cents(1.5)).abs() < 23.5,
+                    "{temperament:?} fifth on {root} is {fifth}"
+                );
+            }
+        }
+        // Kirnberger III has a pure major third on C
+        assert_approx_eq(
+            tuning(Temperament::KirnbergerIII).interval_cents(0, 4),
+            ratio_cents(5.0 / 4.0),
+        );
+    }
+
+    #[test]
+    fn test_tonic_transposes_the_temperament() {
+        let on_c = tuning(Temperament::WerckmeisterIII);
+        let on_g = Tuning { tonic: 7, ..on_c };
+        for semitones in 0..=12 {
+            assert_approx_eq(
+                on_g.interval_cents(7, semitones),
+                on_c.interval_cents(0, semitones),
+            );
+        }
+    }
+
+    #[test]
+    fn test_frequency_table() {
+        let tuning = tuning(Temperament::Vallotti);
+        let table = tuning.frequency_table();
+        assert_approx_eq(table.frequency(A4).unwrap(), 440.0);
+        assert!(table.frequencies().windows(2).all(|pair| pair[0] < pair[1]));
+        for root in 0..100 {
+            assert_approx_eq(
+                table.interval_cents(root, 4).unwrap(),
+                tuning.interval_cents(root, 4),
+            );
+        }
+        assert_eq!(table.interval_cents(120, 12), None);
+
+        let mut frequencies = *table.frequencies();
+        frequencies[usize::from(A4)] = TuningTable::UNMAPPED;
+        let table = TuningTable::new(frequencies);
+        assert_eq!(table.frequency(A4), None);
+        assert_eq!(table.interval_cents(C4, 9), None);
+    }
+
+    #[test]
+    fn test_equal_divisions_of_the_octave() {
+        for steps_per_octave in Tuning::STEPS_PER_OCTAVE_CHOICES
+            .into_iter()
+            .filter(|&steps| steps != PITCH_CLASSES)
+        {
+            let tuning = Tuning {
+                steps_per_octave,
+                // Temperaments only apply to 12 steps
+                temperament: Temperament::Just,
+                ..Tuning::default()
+            };
+            let table = tuning.frequency_table();
+            assert_approx_eq(table.frequency(C4).unwrap(), 261.63);
+            // Octaves are pure
+            assert_approx_eq(
+                table.frequency(C4 + steps_per_octave).unwrap() / table.frequency(C4).unwrap(),
+                2.0,
+            );
+            let step = CENTS_PER_OCTAVE / f32::from(steps_per_octave);
+            for root in 0..100 {
+                assert_approx_eq(table.interval_cents(root, 1).unwrap(), step);
+                assert_approx_eq(tuning.interval_cents(root, 3), 3.0 * step);
+            }
+        }
+        // 31 steps have a nearly pure major third, 10 steps
+        let tuning = Tuning {
+            steps_per_octave: 31,
+            ..Tuning::default()
+        };
+        assert!((tuning.interval_cents(C4, 10) - ratio_cents(5.0 / 4.0)).abs() < 1.0);
+        // 53 steps have a nearly pure fifth, 31 steps
+        let tuning = Tuning {
+            steps_per_octave: 53,
+            ..Tuning::default()
+        };
+        assert!((tuning.interval_cents(C4, 31) - ratio_cents(3.0 / 2.0)).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_storage_keys_round_trip() {
+        for temperament in Temperament::ALL {
+            assert_eq!(
+                Temperament::from_storage_key(temperament.storage_key()),
+                Some(temperament)
+            );
+        }
+    }
+}
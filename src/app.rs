@@ -1,19 +1,43 @@
 use crossbeam::channel;
 use egui::{Align, Align2, Color32, FontId, Layout, RichText, pos2, vec2};
 use log::error;
-use std::sync::{Arc, Mutex};
+use num_rational::Rational32;
+use shared_types::{
+    DEFAULT_POLYPHONY, DEFAULT_SYMPATHETIC_RESONANCE, Instrument, MAX_POLYPHONY, SynthSpectrum,
+    VoiceEngine, VoiceStealing,
+    effects::EffectSettings,
+    scala::{self, KeyboardMapping, Scale},
+    tuning::{Temperament, Tuning, TuningTable},
+};
+use std::collections::BTreeSet;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, Ordering},
+};
 use web_time::{Duration, Instant};
 
 use crate::{
-    interval_display,
-    midi::MidiReader,
+    dissonance_curve::{DissonanceCurve, Timbre},
+    dissonance_meter::{self, DissonanceMeter},
+    effects_panel,
+    file_picker::{self, PickedFile, PickedFiles},
+    interval::{
+        self, ConsonanceModel, HarmonicEntropy, Interval, IntervalTable, RatioTable, RationalSeries,
+    },
+    interval_display::{self, DisplayMode},
+    key_detection::{self, Key, KeyEstimator},
+    midi::{self, MidiReader, MidiWriter, PortSelection},
     piano_gui::{self, PIANO_WIDTH, PianoGui},
-    theme,
+    piano_types::{KeyboardSize, OctaveDivision, Semitone},
+    pitch_class_set::{self, SetAnalysis},
+    roughness::Spectrum,
+    spectrum_view, theme, timbre_editor,
     webaudio::{ToWorkletMessage, WebAudio},
 };
 
 /// Width threshold for determining mobile/narrow screens
 const MOBILE_BREAKPOINT_WIDTH: f32 = 480.0;
+const STATUS_FONT_SIZE: f32 = 14.0;
 
 enum AudioState {
     Uninitialized,
@@ -22,18 +46,131 @@ enum AudioState {
     Disabled, // Audio is not supported (e.g., mobile devices without AudioWorklet)
 }
 
-enum MidiState {
-    NotConnected { last_checked: Option<Instant> },
-    Connected(MidiReader),
+struct MidiState {
+    /// Names of the input ports that were available at the last check
+    available_ports: Vec<String>,
+    /// One connection per selected port that is available
+    connections: Vec<MidiReader>,
+    /// Names of the output ports that were available at the last check
+    available_output_ports: Vec<String>,
+    /// Connection to the selected output port, if it is available
+    writer: Option<MidiWriter>,
+    /// When the ports were last checked. Devices can come and go, so this is done periodically.
+    last_checked: Option<Instant>,
+}
+
+/// A tuning loaded from Scala files
+struct ScalaTuning {
+    /// Description from the scale file
+    name: String,
+    scl: String,
+    kbm: Option<String>,
+    table: TuningTable,
+}
+
+impl ScalaTuning {
+    fn new(scl: String, kbm: Option<String>) -> Result<Self, scala::Error> {
+        let scale = Scale::parse(&scl)?;
+        let mapping = kbm
+            .as_deref()
+            .map(KeyboardMapping::parse)
+            .transpose()?
+            .unwrap_or_default();
+        let table = mapping.frequency_table(&scale)?;
+        let name = if scale.description.is_empty() {
+            "unnamed scale".to_string()
+        } else {
+            scale.description
+        };
+        Ok(Self {
+            name,
+            scl,
+            kbm,
+            table,
+        })
+    }
+}
+
+/// Where and how the notes played in the app are sent to an external synth
+struct MidiOutputSettings {
+    /// Name of the output port, `None` to not send anything
+    port: Option<String>,
+    channel: wmidi::Channel,
+    /// Velocity of the notes played in the app, also used for the internal synth
+    velocity: wmidi::U7,
+    /// Only play the notes on the MIDI output while it is connected
+    mute_internal_synth: bool,
+}
+
+/// How the internal synth plays notes, sent to the audio worklet once it has loaded
+struct SynthSettings {
+    engine: VoiceEngine,
+    /// How many notes can sound at once
+    polyphony: u8,
+    voice_stealing: VoiceStealing,
+    /// How loud the strings that ring along while the sustain pedal is down are, from 0 to 1
+    sympathetic_resonance: f32,
+    /// Envelope of the notes, and the partials the spectrum is reset to
+    instrument: Instrument,
+    /// Partials of the notes, which the roughness in the GUI is calculated with as well
+    spectrum: SynthSpectrum,
+    /// Reverb and limiter
+    effects: EffectSettings,
 }
 
 pub struct DissonanceLabApp {
     audio: Arc<Mutex<AudioState>>,
+    /// Whether the notes only go to the MIDI output, shared with the MIDI input callback that plays the synth directly
+    internal_synth_muted: Arc<AtomicBool>,
     piano_gui: PianoGui,
+    dissonance_meter: DissonanceMeter,
     midi: MidiState,
+    midi_port_selection: PortSelection,
+    midi_output: MidiOutputSettings,
     midi_to_piano_gui_rx: channel::Receiver<wmidi::MidiMessage<'static>>,
     midi_to_piano_gui_tx: channel::Sender<wmidi::MidiMessage<'static>>,
     invert_sustain_pedal: bool,
+    interval_display_mode: DisplayMode,
+    /// The ways of rating the intervals that the user can choose from
+    consonance_models: Vec<Box<dyn ConsonanceModel>>,
+    /// Index into `consonance_models` of the one the interval rows use.
+    /// The dissonance meter has its own.
+    consonance_model: usize,
+    /// The just ratios the intervals are compared to
+    ratio_table: RatioTable,
+    /// Whether the interval cells show how complex their ratio is instead of the interval's name
+    show_ratio_complexity: bool,
+    /// Whether the pitch-class set analysis of the held keys is shown
+    show_set_analysis: bool,
+    /// Whether the partials of the held notes are shown
+    show_spectrum: bool,
+    dissonance_curve: DissonanceCurve,
+    /// Whether the roughness of a continuous sweep of intervals is shown
+    show_dissonance_curve: bool,
+    /// Whether the editor for the partials of the synth is shown
+    show_timbre_editor: bool,
+    /// Whether the reverb and limiter settings are shown
+    show_effects: bool,
+    /// Estimates the key from the notes that are played
+    key_estimator: KeyEstimator,
+    /// Key chosen by the user, used instead of the estimated key while set
+    locked_key: Option<Key>,
+    tuning: Tuning,
+    /// Replaces `tuning` while set
+    scala_tuning: Option<ScalaTuning>,
+    /// Scala files the user is picking
+    picked_scala_files: Option<PickedFiles>,
+    /// Why the last picked Scala files couldn't be used
+    scala_error: Option<String>,
+    /// Frequencies of the active tuning
+    tuning_table: TuningTable,
+    /// Whether the synth has been sent the current tuning
+    tuning_synced: bool,
+    synth_settings: SynthSettings,
+    /// Whether the synth has been sent all of `synth_settings`. After that only the changed settings are sent.
+    synth_settings_synced: bool,
+    /// Whether the storage has the current `synth_settings`
+    synth_settings_saved: bool,
     // Whether we already performed the automatic startup attempt
     auto_audio_attempted: bool,
     // Whether the user has explicitly attempted to enable audio (clicked the button)
@@ -42,14 +179,69 @@ pub struct DissonanceLabApp {
 
 impl Default for DissonanceLabApp {
     fn default() -> Self {
+        // Denominators up to 80 are enough for the minima at the usual just ratios to stand out
+        const FAREY_LIMIT: i32 = 80;
         let (midi_to_piano_gui_tx, midi_to_piano_gui_rx) = channel::unbounded();
         Self {
             audio: Arc::new(Mutex::new(AudioState::Uninitialized)),
+            internal_synth_muted: Arc::new(AtomicBool::new(false)),
             piano_gui: PianoGui::new(),
-            midi: MidiState::NotConnected { last_checked: None },
+            dissonance_meter: DissonanceMeter::new(),
+            midi: MidiState {
+                available_ports: Vec::new(),
+                connections: Vec::new(),
+                available_output_ports: Vec::new(),
+                writer: None,
+                last_checked: None,
+            },
+            midi_port_selection: PortSelection::First,
+            midi_output: MidiOutputSettings {
+                port: None,
+                channel: wmidi::Channel::Ch1,
+                velocity: wmidi::U7::from_u8_lossy(64),
+                mute_internal_synth: false,
+            },
             midi_to_piano_gui_rx,
             midi_to_piano_gui_tx,
             invert_sustain_pedal: false,
+            interval_display_mode: DisplayMode::RowPerHeldKey,
+            consonance_models: vec![
+                Box::new(IntervalTable),
+                Box::new(Spectrum::piano()),
+                Box::new(HarmonicEntropy::default()),
+                Box::new(HarmonicEntropy::new(
+                    RationalSeries::Farey(FAREY_LIMIT),
+                    HarmonicEntropy::DEFAULT_SPREAD_CENTS,
+                )),
+            ],
+            consonance_model: 0,
+            ratio_table: RatioTable::default(),
+            show_ratio_complexity: false,
+            show_set_analysis: false,
+            show_spectrum: false,
+            dissonance_curve: DissonanceCurve::new(),
+            show_dissonance_curve: false,
+            show_timbre_editor: false,
+            show_effects: false,
+            key_estimator: KeyEstimator::new(),
+            locked_key: None,
+            tuning: Tuning::default(),
+            scala_tuning: None,
+            picked_scala_files: None,
+            scala_error: None,
+            tuning_table: TuningTable::default(),
+            tuning_synced: false,
+            synth_settings: SynthSettings {
+                engine: VoiceEngine::default(),
+                polyphony: DEFAULT_POLYPHONY,
+                voice_stealing: VoiceStealing::default(),
+                sympathetic_resonance: DEFAULT_SYMPATHETIC_RESONANCE,
+                instrument: Instrument::default(),
+                spectrum: SynthSpectrum::default(),
+                effects: EffectSettings::default(),
+            },
+            synth_settings_synced: false,
+            synth_settings_saved: true,
             auto_audio_attempted: false,
             user_audio_attempted: false,
         }
@@ -69,6 +261,20 @@ impl DissonanceLabApp {
         let mut app = Self::default();
         // Load sustain pedal polarity setting from local storage
         app.load_sustain_pedal_setting(cc);
+        app.load_interval_display_mode(cc);
+        app.load_consonance_model(cc);
+        app.load_ratio_settings(cc);
+        app.load_set_analysis_setting(cc);
+        app.load_spectrum_setting(cc);
+        app.load_dissonance_curve_settings(cc);
+        app.load_locked_key(cc);
+        app.load_keyboard_size(cc);
+        app.load_midi_port_selection(cc);
+        app.load_midi_output_settings(cc);
+        app.load_tuning(cc);
+        app.load_synth_settings(cc);
+        app.load_timbre_editor_setting(cc);
+        app.load_effects_setting(cc);
         // Try to eagerly initialize audio once at startup in case the browser allows it without user gesture.
         // Some browsers (notably Safari / iOS) will reject or suspend AudioContext creation until a user gesture.
         // If initialization ultimately fails we will revert the state back to Uninitialized so the user can click the audio enable/unmute button in the UI.
@@ -93,6 +299,440 @@ impl DissonanceLabApp {
         }
     }
 
+    fn load_interval_display_mode(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && let Some(mode) = storage.get_string("interval_display_mode")
+        {
+            self.interval_display_mode = if mode == "next_note" {
+                DisplayMode::NextNote
+            } else {
+                DisplayMode::RowPerHeldKey
+            };
+        }
+    }
+
+    fn save_interval_display_mode(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            let mode = match self.interval_display_mode {
+                DisplayMode::RowPerHeldKey => "row_per_held_key",
+                DisplayMode::NextNote => "next_note",
+            };
+            storage.set_string("interval_display_mode", mode.to_string());
+        }
+    }
+
+    fn load_consonance_model(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && let Some(name) = storage.get_string("consonance_model")
+            && let Some(index) = self
+                .consonance_models
+                .iter()
+                .position(|model| model.name() == name)
+        {
+            self.consonance_model = index;
+        }
+        if let Some(storage) = cc.storage
+            && let Some(name) = storage.get_string("meter_model")
+            && let Some(index) = self
+                .consonance_models
+                .iter()
+                .position(|model| model.name() == name)
+        {
+            self.dissonance_meter.model = index;
+        }
+    }
+
+    fn save_consonance_model(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string(
+                "consonance_model",
+                self.consonance_models[self.consonance_model]
+                    .name()
+                    .to_string(),
+            );
+            storage.set_string(
+                "meter_model",
+                self.consonance_models[self.dissonance_meter.model]
+                    .name()
+                    .to_string(),
+            );
+        }
+    }
+
+    fn load_ratio_settings(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage {
+            if let Some(ratio_table) = storage
+                .get_string("ratio_table")
+                .and_then(|text| RatioTable::from_storage_string(&text))
+            {
+                self.ratio_table = ratio_table;
+            }
+            if let Some(show) = storage.get_string("show_ratio_complexity") {
+                self.show_ratio_complexity = show == "true";
+            }
+        }
+    }
+
+    fn save_ratio_settings(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string("ratio_table", self.ratio_table.storage_string());
+            storage.set_string(
+                "show_ratio_complexity",
+                self.show_ratio_complexity.to_string(),
+            );
+        }
+    }
+
+    fn load_set_analysis_setting(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && let Some(show) = storage.get_string("show_set_analysis")
+        {
+            self.show_set_analysis = show == "true";
+        }
+    }
+
+    fn save_set_analysis_setting(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string("show_set_analysis", self.show_set_analysis.to_string());
+        }
+    }
+
+    fn load_spectrum_setting(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && let Some(show) = storage.get_string("show_spectrum")
+        {
+            self.show_spectrum = show == "true";
+        }
+    }
+
+    fn save_spectrum_setting(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string("show_spectrum", self.show_spectrum.to_string());
+        }
+    }
+
+    fn load_timbre_editor_setting(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && let Some(show) = storage.get_string("show_timbre_editor")
+        {
+            self.show_timbre_editor = show == "true";
+        }
+    }
+
+    fn save_timbre_editor_setting(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string("show_timbre_editor", self.show_timbre_editor.to_string());
+        }
+    }
+
+    fn load_effects_setting(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && let Some(show) = storage.get_string("show_effects")
+        {
+            self.show_effects = show == "true";
+        }
+    }
+
+    fn save_effects_setting(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string("show_effects", self.show_effects.to_string());
+        }
+    }
+
+    fn load_dissonance_curve_settings(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage {
+            if let Some(show) = storage.get_string("show_dissonance_curve") {
+                self.show_dissonance_curve = show == "true";
+            }
+            if let Some(timbre) = storage.get_string("dissonance_curve_timbre")
+                && let Some(timbre) = Timbre::from_storage_key(&timbre)
+            {
+                self.dissonance_curve.timbre = timbre;
+            }
+            if let Some(spectrum) = storage.get_string("dissonance_curve_spectrum")
+                && let Some(spectrum) = SynthSpectrum::from_storage_string(&spectrum)
+            {
+                self.dissonance_curve.custom_spectrum = spectrum;
+            }
+        }
+    }
+
+    fn save_dissonance_curve_settings(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string(
+                "show_dissonance_curve",
+                self.show_dissonance_curve.to_string(),
+            );
+            storage.set_string(
+                "dissonance_curve_timbre",
+                self.dissonance_curve.timbre.storage_key().to_string(),
+            );
+            storage.set_string(
+                "dissonance_curve_spectrum",
+                self.dissonance_curve.custom_spectrum.storage_string(),
+            );
+        }
+    }
+
+    fn load_locked_key(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && let Some(locked_key) = storage.get_string("locked_key")
+        {
+            self.locked_key = Key::all().find(|key| key.to_string() == locked_key);
+        }
+    }
+
+    fn save_locked_key(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            let locked_key = self.locked_key.map(|key| key.to_string());
+            storage.set_string("locked_key", locked_key.unwrap_or_default());
+        }
+    }
+
+    fn load_keyboard_size(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && let Some(size) = storage.get_string("keyboard_size")
+            && let Some(size) = KeyboardSize::from_storage_key(&size)
+        {
+            let mut actions = Vec::new();
+            self.piano_gui.set_keyboard_size(size, &mut actions);
+            debug_assert!(actions.is_empty(), "No keys are held at startup");
+        }
+    }
+
+    fn save_keyboard_size(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string(
+                "keyboard_size",
+                self.piano_gui.keyboard_size().storage_key(),
+            );
+        }
+    }
+
+    fn load_midi_port_selection(&mut self, cc: &eframe::CreationContext<'_>) {
+        if let Some(storage) = cc.storage
+            && storage.get_string("midi_port_selection").as_deref() == Some("named")
+        {
+            // One port name per line
+            let ports = storage.get_string("midi_ports").unwrap_or_default();
+            self.midi_port_selection = PortSelection::Named(
+                ports
+                    .lines()
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            );
+        }
+    }
+
+    fn save_midi_port_selection(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            let (selection, ports) = match &self.midi_port_selection {
+                PortSelection::First => ("first", String::new()),
+                PortSelection::Named(names) => (
+                    "named",
+                    names
+                        .iter()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+            };
+            storage.set_string("midi_port_selection", selection.to_string());
+            storage.set_string("midi_ports", ports);
+        }
+    }
+
+    fn load_midi_output_settings(&mut self, cc: &eframe::CreationContext<'_>) {
+        let Some(storage) = cc.storage else {
+            return;
+        };
+        if let Some(port) = storage.get_string("midi_output_port") {
+            // An empty name means no output
+            self.midi_output.port = Some(port).filter(|port| !port.is_empty());
+        }
+        if let Some(channel) = storage.get_string("midi_output_channel")
+            && let Ok(number) = channel.parse::<u8>()
+            && let Ok(channel) = wmidi::Channel::from_index(number.wrapping_sub(1))
+        {
+            self.midi_output.channel = channel;
+        }
+        if let Some(velocity) = storage.get_string("midi_output_velocity")
+            && let Ok(velocity) = velocity.parse::<u8>()
+            && let Ok(velocity) = wmidi::U7::try_from(velocity)
+        {
+            self.midi_output.velocity = velocity;
+        }
+        if let Some(mute) = storage.get_string("mute_internal_synth") {
+            self.midi_output.mute_internal_synth = mute == "true";
+        }
+    }
+
+    fn save_midi_output_settings(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string(
+                "midi_output_port",
+                self.midi_output.port.clone().unwrap_or_default(),
+            );
+            storage.set_string(
+                "midi_output_channel",
+                self.midi_output.channel.number().to_string(),
+            );
+            storage.set_string(
+                "midi_output_velocity",
+                u8::from(self.midi_output.velocity).to_string(),
+            );
+            storage.set_string(
+                "mute_internal_synth",
+                self.midi_output.mute_internal_synth.to_string(),
+            );
+        }
+    }
+
+    fn load_tuning(&mut self, cc: &eframe::CreationContext<'_>) {
+        let Some(storage) = cc.storage else {
+            return;
+        };
+        if let Some(temperament) = storage.get_string("tuning_temperament")
+            && let Some(temperament) = Temperament::from_storage_key(&temperament)
+        {
+            self.tuning.temperament = temperament;
+        }
+        if let Some(steps_per_octave) = storage.get_string("tuning_steps_per_octave")
+            && let Ok(steps_per_octave) = steps_per_octave.parse::<u8>()
+            && Tuning::STEPS_PER_OCTAVE_CHOICES.contains(&steps_per_octave)
+        {
+            self.tuning.steps_per_octave = steps_per_octave;
+        }
+        if let Some(tonic) = storage.get_string("tuning_tonic")
+            && let Ok(tonic) = tonic.parse::<u8>()
+            && tonic < 12
+        {
+            self.tuning.tonic = tonic;
+        }
+        if let Some(a4_frequency) = storage.get_string("tuning_a4_frequency")
+            && let Ok(a4_frequency) = a4_frequency.parse::<f32>()
+            && a4_frequency.is_finite()
+            && a4_frequency > 0.0
+        {
+            self.tuning.a4_frequency = a4_frequency;
+        }
+        if let Some(scl) = storage.get_string("scala_scl")
+            && !scl.is_empty()
+        {
+            let kbm = storage
+                .get_string("scala_kbm")
+                .filter(|kbm| !kbm.is_empty());
+            match ScalaTuning::new(scl, kbm) {
+                Ok(scala_tuning) => self.scala_tuning = Some(scala_tuning),
+                Err(e) => error!("unable to load stored scala tuning: {e}"),
+            }
+        }
+        self.update_tuning_table();
+    }
+
+    fn save_tuning(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string(
+                "tuning_temperament",
+                self.tuning.temperament.storage_key().to_string(),
+            );
+            storage.set_string(
+                "tuning_steps_per_octave",
+                self.tuning.steps_per_octave.to_string(),
+            );
+            storage.set_string("tuning_tonic", self.tuning.tonic.to_string());
+            storage.set_string("tuning_a4_frequency", self.tuning.a4_frequency.to_string());
+            // Empty strings mean no Scala tuning or no keyboard mapping
+            let (scl, kbm) = match &self.scala_tuning {
+                Some(scala_tuning) => (
+                    scala_tuning.scl.clone(),
+                    scala_tuning.kbm.clone().unwrap_or_default(),
+                ),
+                None => (String::new(), String::new()),
+            };
+            storage.set_string("scala_scl", scl);
+            storage.set_string("scala_kbm", kbm);
+        }
+    }
+
+    fn load_synth_settings(&mut self, cc: &eframe::CreationContext<'_>) {
+        let Some(storage) = cc.storage else {
+            return;
+        };
+        if let Some(name) = storage.get_string("voice_engine")
+            && let Some(engine) = VoiceEngine::ALL
+                .into_iter()
+                .find(|engine| engine.name() == name)
+        {
+            self.synth_settings.engine = engine;
+        }
+        if let Some(polyphony) = storage.get_string("polyphony")
+            && let Ok(polyphony) = polyphony.parse::<u8>()
+        {
+            self.synth_settings.polyphony = polyphony.clamp(1, MAX_POLYPHONY);
+        }
+        if let Some(name) = storage.get_string("voice_stealing")
+            && let Some(strategy) = VoiceStealing::ALL
+                .into_iter()
+                .find(|strategy| strategy.name() == name)
+        {
+            self.synth_settings.voice_stealing = strategy;
+        }
+        if let Some(resonance) = storage.get_string("sympathetic_resonance")
+            && let Ok(resonance) = resonance.parse::<f32>()
+        {
+            self.synth_settings.sympathetic_resonance = resonance.clamp(0.0, 1.0);
+        }
+        if let Some(name) = storage.get_string("instrument")
+            && let Some(instrument) = Instrument::ALL
+                .into_iter()
+                .find(|instrument| instrument.name() == name)
+        {
+            self.synth_settings.instrument = instrument;
+            self.synth_settings.spectrum = instrument.spectrum();
+            self.update_roughness_model();
+        }
+        if let Some(spectrum) = storage.get_string("synth_spectrum")
+            && let Some(spectrum) = SynthSpectrum::from_storage_string(&spectrum)
+        {
+            self.synth_settings.spectrum = spectrum;
+            self.update_roughness_model();
+        }
+        if let Some(effects) = storage.get_string("effects")
+            && let Some(effects) = EffectSettings::from_storage_string(&effects)
+        {
+            self.synth_settings.effects = effects;
+        }
+    }
+
+    fn save_synth_settings(&self, frame: &mut eframe::Frame) {
+        if let Some(storage) = frame.storage_mut() {
+            storage.set_string(
+                "voice_engine",
+                self.synth_settings.engine.name().to_string(),
+            );
+            storage.set_string("polyphony", self.synth_settings.polyphony.to_string());
+            storage.set_string(
+                "voice_stealing",
+                self.synth_settings.voice_stealing.name().to_string(),
+            );
+            storage.set_string(
+                "sympathetic_resonance",
+                self.synth_settings.sympathetic_resonance.to_string(),
+            );
+            storage.set_string(
+                "instrument",
+                self.synth_settings.instrument.name().to_string(),
+            );
+            storage.set_string(
+                "synth_spectrum",
+                self.synth_settings.spectrum.storage_string(),
+            );
+            storage.set_string("effects", self.synth_settings.effects.storage_string());
+        }
+    }
+
     fn setup_audio(&mut self) {
         assert!(matches!(
             *self.audio.lock().unwrap(),
@@ -100,6 +740,8 @@ impl DissonanceLabApp {
         ));
         let web_audio = WebAudio::new();
         *self.audio.lock().unwrap() = AudioState::Playing(web_audio);
+        self.tuning_synced = false;
+        self.synth_settings_synced = false;
         self.user_audio_attempted = true;
     }
 
@@ -111,6 +753,8 @@ impl DissonanceLabApp {
             // Move into a Playing state to kick off async loading.
             let web_audio = WebAudio::new();
             *self.audio.lock().unwrap() = AudioState::Playing(web_audio);
+            self.tuning_synced = false;
+            self.synth_settings_synced = false;
             self.auto_audio_attempted = true;
         }
     }
@@ -131,64 +775,924 @@ impl DissonanceLabApp {
         }
     }
 
+    /// Send the tuning to the synth. Waits until the worklet has loaded, since messages sent before that are lost.
+    fn sync_tuning(&mut self) {
+        if !self.tuning_synced
+            && let AudioState::Playing(web_audio) = &*self.audio.lock().unwrap()
+            && web_audio.is_ready()
+        {
+            web_audio.send_message(ToWorkletMessage::SetTuning {
+                frequencies: self.tuning_table.frequencies().to_vec(),
+            });
+            self.tuning_synced = true;
+        }
+    }
+
+    /// Send the synth settings to the synth, once the worklet has loaded like [`Self::sync_tuning`]
+    fn sync_synth_settings(&mut self) {
+        if !self.synth_settings_synced
+            && let AudioState::Playing(web_audio) = &*self.audio.lock().unwrap()
+            && web_audio.is_ready()
+        {
+            web_audio.send_message(ToWorkletMessage::SetVoiceEngine {
+                engine: self.synth_settings.engine,
+            });
+            web_audio.send_message(ToWorkletMessage::SetPolyphony {
+                voices: self.synth_settings.polyphony,
+            });
+            web_audio.send_message(ToWorkletMessage::SetVoiceStealing {
+                strategy: self.synth_settings.voice_stealing,
+            });
+            web_audio.send_message(ToWorkletMessage::SetSympatheticResonance {
+                wet_level: self.synth_settings.sympathetic_resonance,
+            });
+            // The instrument comes first as it resets the spectrum
+            web_audio.send_message(ToWorkletMessage::SetInstrument {
+                instrument: self.synth_settings.instrument,
+            });
+            web_audio.send_message(ToWorkletMessage::SetSpectrum {
+                spectrum: self.synth_settings.spectrum,
+            });
+            for (param, value) in self.synth_settings.effects.iter() {
+                web_audio.send_message(ToWorkletMessage::SetEffectParam { param, value });
+            }
+            self.synth_settings_synced = true;
+        }
+    }
+
+    /// Send a synth setting that was changed. Before the synth has been sent all of the settings there is
+    /// nothing to do, as [`Self::sync_synth_settings`] sends the current ones.
+    fn send_synth_setting(&self, message: ToWorkletMessage) {
+        if self.synth_settings_synced
+            && let AudioState::Playing(web_audio) = &*self.audio.lock().unwrap()
+        {
+            web_audio.send_message(message);
+        }
+    }
+
+    /// Save the changed synth settings once no slider is being dragged, so that a drag doesn't rewrite
+    /// the storage every frame
+    fn save_synth_settings_after_drag(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if !self.synth_settings_saved && ctx.dragged_id().is_none() {
+            self.save_synth_settings(frame);
+            self.synth_settings_saved = true;
+        }
+    }
+
+    fn update_tuning_table(&mut self) {
+        self.tuning_table = match &self.scala_tuning {
+            Some(scala_tuning) => scala_tuning.table.clone(),
+            None => self.tuning.frequency_table(),
+        };
+        self.tuning_synced = false;
+    }
+
+    /// How many keys make up an octave of the piano. Scala tunings are played on a regular piano.
+    fn octave_division(&self) -> OctaveDivision {
+        if self.scala_tuning.is_none() && self.tuning.is_edo() {
+            OctaveDivision::new(self.tuning.steps_per_octave)
+        } else {
+            OctaveDivision::SEMITONES
+        }
+    }
+
+    /// Use the Scala files the user picked, once the browser has read them.
+    /// A keyboard mapping picked on its own applies to the current scale.
+    fn check_picked_scala_files(&mut self, frame: &mut eframe::Frame) {
+        let Some(picked) = self.picked_scala_files.take() else {
+            return;
+        };
+        let files = match picked.try_get() {
+            None => {
+                self.picked_scala_files = Some(picked);
+                return;
+            }
+            Some(Err(e)) => {
+                self.scala_error = Some(format!("unable to read the files: {e:?}"));
+                return;
+            }
+            Some(Ok(files)) => files,
+        };
+        let contents_with_extension = |extension: &str| {
+            files
+                .iter()
+                .find(|file| file.name.to_lowercase().ends_with(extension))
+                .map(|file: &PickedFile| file.contents.clone())
+        };
+        let kbm = contents_with_extension(".kbm");
+        let scl = contents_with_extension(".scl").or_else(|| {
+            self.scala_tuning
+                .as_ref()
+                .map(|scala_tuning| scala_tuning.scl.clone())
+        });
+        let Some(scl) = scl else {
+            self.scala_error = Some("choose a .scl file, optionally with a .kbm file".to_string());
+            return;
+        };
+        match ScalaTuning::new(scl, kbm) {
+            Ok(scala_tuning) => {
+                self.scala_tuning = Some(scala_tuning);
+                self.scala_error = None;
+                self.update_tuning_table();
+                self.save_tuning(frame);
+            }
+            Err(e) => self.scala_error = Some(e.to_string()),
+        }
+    }
+
+    /// Menu for choosing the tuning used by the synth and for the cents deviations
+    fn tuning_menu(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        let mut tuning = self.tuning;
+        let mut use_scala = self.scala_tuning.is_some();
+        let twelve_steps = Tuning::default().steps_per_octave;
+        for temperament in Temperament::ALL {
+            if ui
+                .radio(
+                    !use_scala && !tuning.is_edo() && tuning.temperament == temperament,
+                    temperament.name(),
+                )
+                .clicked()
+            {
+                tuning.temperament = temperament;
+                tuning.steps_per_octave = twelve_steps;
+                use_scala = false;
+            }
+        }
+        for steps_per_octave in Tuning::STEPS_PER_OCTAVE_CHOICES {
+            if steps_per_octave != twelve_steps
+                && ui
+                    .radio(
+                        !use_scala && tuning.steps_per_octave == steps_per_octave,
+                        format!("{steps_per_octave}-EDO"),
+                    )
+                    .on_hover_text(format!(
+                        "The octave divided into {steps_per_octave} equal steps, one key per step"
+                    ))
+                    .clicked()
+            {
+                tuning.steps_per_octave = steps_per_octave;
+                use_scala = false;
+            }
+        }
+        if let Some(scala_tuning) = &self.scala_tuning
+            && ui
+                .radio(use_scala, format!("Scala: {}", scala_tuning.name))
+                .clicked()
+        {
+            use_scala = true;
+            tuning = self.tuning;
+        }
+        ui.separator();
+        ui.add_enabled_ui(
+            !use_scala && !tuning.is_edo() && tuning.temperament != Temperament::Equal,
+            |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Tonic");
+                    egui::ComboBox::from_id_salt("tuning_tonic")
+                        .selected_text(Semitone::new(tuning.tonic).name())
+                        .show_ui(ui, |ui| {
+                            for semitone in Semitone::iter() {
+                                ui.selectable_value(
+                                    &mut tuning.tonic,
+                                    semitone.as_usize() as u8,
+                                    semitone.name(),
+                                );
+                            }
+                        });
+                })
+                .response
+                .on_hover_text("The note the temperament is built on");
+            },
+        );
+        ui.add_enabled_ui(!use_scala, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("A4");
+                ui.add(
+                    egui::DragValue::new(&mut tuning.a4_frequency)
+                        .range(400.0..=480.0)
+                        .speed(0.1)
+                        .suffix(" Hz"),
+                );
+            });
+        });
+        ui.separator();
+        if ui
+            .button("Load Scala files…")
+            .on_hover_text("Choose a .scl scale, optionally together with a .kbm keyboard mapping")
+            .clicked()
+        {
+            match file_picker::pick_text_files(".scl,.kbm") {
+                Ok(picked) => self.picked_scala_files = Some(picked),
+                Err(e) => self.scala_error = Some(format!("unable to open a file dialog: {e:?}")),
+            }
+        }
+        if let Some(error) = &self.scala_error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        let scala_deselected = !use_scala && self.scala_tuning.is_some();
+        if tuning != self.tuning || scala_deselected {
+            self.tuning = tuning;
+            if scala_deselected {
+                self.scala_tuning = None;
+            }
+            self.update_tuning_table();
+            self.save_tuning(frame);
+        }
+    }
+
+    /// Connect to the selected MIDI ports that are available, and drop connections to ports that have gone away.
+    /// Checks periodically, so that devices are reconnected when they are plugged back in.
     fn ensure_midi(&mut self, ctx: &egui::Context) {
         const MIDI_CHECK_PERIOD: Duration = Duration::from_secs(1);
-        match &mut self.midi {
-            MidiState::NotConnected { last_checked }
-                if last_checked.is_none()
-                    || last_checked.map(|t| t.elapsed()) > Some(MIDI_CHECK_PERIOD) =>
+        if self
+            .midi
+            .last_checked
+            .is_some_and(|last_checked| last_checked.elapsed() < MIDI_CHECK_PERIOD)
+        {
+            return;
+        }
+        self.midi.last_checked = Some(Instant::now());
+        self.midi.available_ports = match midi::available_ports() {
+            Ok(ports) => ports,
+            Err(e) => {
+                match e {
+                    midi::Error::NoMidiInterface | midi::Error::Init(_) => {}
+                    midi::Error::Connect(_)
+                    | midi::Error::ConnectOutput(_)
+                    | midi::Error::PortInfo(_) => {
+                        error!("unable to list midi ports: {e:?}");
+                    }
+                }
+                Vec::new()
+            }
+        };
+        let wanted_ports = self
+            .midi_port_selection
+            .wanted_ports(&self.midi.available_ports);
+        self.midi.connections.retain(|connection| {
+            wanted_ports
+                .iter()
+                .any(|port| port.as_str() == connection.get_name())
+        });
+        for port in wanted_ports {
+            if self
+                .midi
+                .connections
+                .iter()
+                .any(|connection| connection.get_name() == port.as_str())
             {
-                let to_gui_tx = self.midi_to_piano_gui_tx.clone();
-                let ctx = ctx.clone();
-                let audio = self.audio.clone();
-                match MidiReader::new(move |message| {
-                    if let AudioState::Playing(web_audio) = &*audio.lock().unwrap() {
-                        match message {
-                            wmidi::MidiMessage::NoteOff(_, note, _) => {
-                                web_audio.ensure_running();
-                                web_audio.send_message(ToWorkletMessage::NoteOff {
-                                    note: u8::from(*note),
-                                });
-                            }
-                            wmidi::MidiMessage::NoteOn(_, note, velocity) => {
-                                web_audio.ensure_running();
-                                web_audio.send_message(ToWorkletMessage::NoteOn {
-                                    note: u8::from(*note),
-                                    velocity: u8::from(*velocity),
-                                });
-                            }
-                            wmidi::MidiMessage::ControlChange(_, control, _value) => {
-                                // Check for sustain pedal (control 64)
-                                if u8::from(*control) == 64 {
-                                    // MIDI sustain pedal - values >= 64 are "on", values < 64 are "off"
-                                    // Note: Do not send sustain message directly to synth here
-                                    // This will be handled in the main event loop to combine with shift sustain
-                                }
-                            }
-                            _ => {}
-                        }
+                continue;
+            }
+            match MidiReader::new(port, self.midi_callback(ctx)) {
+                Ok(reader) => self.midi.connections.push(reader),
+                Err(e) => match e {
+                    // The port may have disappeared since it was listed, try again at the next check
+                    midi::Error::NoMidiInterface | midi::Error::Init(_) => {}
+                    midi::Error::Connect(_)
+                    | midi::Error::ConnectOutput(_)
+                    | midi::Error::PortInfo(_) => {
+                        error!("unable to set up midi: {e:?}");
                     }
+                },
+            }
+        }
+        self.ensure_midi_output();
+    }
 
-                    to_gui_tx.send(message.to_owned()).unwrap();
-                    ctx.request_repaint();
-                }) {
-                    Ok(reader) => {
-                        self.midi = MidiState::Connected(reader);
+    /// Connect to the selected MIDI output port if it is available, or disconnect if it has gone away
+    fn ensure_midi_output(&mut self) {
+        self.midi.available_output_ports = match midi::available_output_ports() {
+            Ok(ports) => ports,
+            Err(e) => {
+                match e {
+                    midi::Error::NoMidiInterface | midi::Error::Init(_) => {}
+                    midi::Error::Connect(_)
+                    | midi::Error::ConnectOutput(_)
+                    | midi::Error::PortInfo(_) => {
+                        error!("unable to list midi output ports: {e:?}");
                     }
-                    Err(e) => {
-                        match e {
-                            crate::midi::Error::NoMidiInterface | crate::midi::Error::Init(_) => {}
-                            crate::midi::Error::Connect(_) | crate::midi::Error::PortInfo(_) => {
-                                error!("unable to set up midi: {e:?}");
-                            }
-                        }
-                        self.midi = MidiState::NotConnected {
-                            last_checked: Some(Instant::now()),
+                }
+                Vec::new()
+            }
+        };
+        let wanted_port = self
+            .midi_output
+            .port
+            .as_ref()
+            .filter(|port| self.midi.available_output_ports.contains(port));
+        if self
+            .midi
+            .writer
+            .as_ref()
+            .is_some_and(|writer| wanted_port.is_none_or(|port| port != writer.get_name()))
+        {
+            self.midi.writer = None;
+        }
+        if self.midi.writer.is_none()
+            && let Some(port) = wanted_port
+        {
+            match MidiWriter::new(port, self.midi_output.channel, self.midi_output.velocity) {
+                Ok(writer) => self.midi.writer = Some(writer),
+                Err(e) => match e {
+                    // The port may have disappeared since it was listed, try again at the next check
+                    midi::Error::NoMidiInterface | midi::Error::Init(_) => {}
+                    midi::Error::Connect(_)
+                    | midi::Error::ConnectOutput(_)
+                    | midi::Error::PortInfo(_) => {
+                        error!("unable to set up midi output: {e:?}");
+                    }
+                },
+            }
+        }
+    }
+
+    /// Feed a note from the piano or a MIDI input to the key estimation
+    fn estimate_key(&mut self, note: wmidi::Note) {
+        // The notes of other divisions of the octave aren't pitch classes of the keys
+        if self.piano_gui.division().is_semitones() {
+            self.key_estimator
+                .note_on(Semitone::from_note(note), Instant::now());
+        }
+    }
+
+    /// The locked key, or the key of what has been played recently
+    fn key(&self) -> Option<Key> {
+        self.locked_key.or_else(|| self.key_estimator.estimate())
+    }
+
+    /// Menu for locking the key the chords are analyzed in
+    fn key_menu(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        let mut locked_key = self.locked_key;
+        let estimate = match self.key_estimator.estimate() {
+            Some(key) => format!("Detect from what is played ({key})"),
+            None => "Detect from what is played".to_string(),
+        };
+        ui.radio_value(&mut locked_key, None, estimate);
+        ui.separator();
+        egui::Grid::new("keys").show(ui, |ui| {
+            for tonic in Semitone::iter() {
+                for key in Key::all().filter(|key| key.tonic == tonic) {
+                    ui.radio_value(&mut locked_key, Some(key), key.to_string());
+                }
+                ui.end_row();
+            }
+        });
+        if locked_key != self.locked_key {
+            self.locked_key = locked_key;
+            self.save_locked_key(frame);
+        }
+    }
+
+    /// Menu for how the internal synth plays the notes
+    fn synth_menu(&mut self, ui: &mut egui::Ui) {
+        let SynthSettings {
+            engine: previous_engine,
+            polyphony: previous_polyphony,
+            voice_stealing: previous_voice_stealing,
+            sympathetic_resonance: previous_sympathetic_resonance,
+            ..
+        } = self.synth_settings;
+        let mut changed = false;
+        ui.label("Strings modelled as");
+        for engine in VoiceEngine::ALL {
+            let hover_text = match engine {
+                VoiceEngine::Additive => {
+                    "A sine wave for each partial, at the frequency the string's stiffness puts it"
+                }
+                VoiceEngine::Waveguide => {
+                    "A struck string simulated as a delay line loop, with filters that stretch its partials \
+                     like the stiffness of a real string does"
+                }
+            };
+            changed |= ui
+                .radio_value(&mut self.synth_settings.engine, engine, engine.name())
+                .on_hover_text(hover_text)
+                .changed();
+        }
+        ui.separator();
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut self.synth_settings.polyphony, 1..=MAX_POLYPHONY)
+                    .text("voices"),
+            )
+            .on_hover_text("How many notes can sound at once")
+            .changed();
+        ui.label("When all voices are in use, cut off");
+        for strategy in VoiceStealing::ALL {
+            let hover_text = match strategy {
+                VoiceStealing::Oldest => "The note that was started the longest ago",
+                VoiceStealing::Quietest => "The quietest note, preferring released ones",
+                VoiceStealing::SameNote => {
+                    "A note that is played again restarts its own voice, otherwise the oldest note is cut off"
+                }
+            };
+            changed |= ui
+                .radio_value(
+                    &mut self.synth_settings.voice_stealing,
+                    strategy,
+                    strategy.name(),
+                )
+                .on_hover_text(hover_text)
+                .changed();
+        }
+        ui.separator();
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut self.synth_settings.sympathetic_resonance, 0.0..=1.0)
+                    .text("sympathetic resonance"),
+            )
+            .on_hover_text(
+                "How loud the strings that share partials with the played notes ring along while the sustain pedal is down",
+            )
+            .changed();
+        if !changed {
+            return;
+        }
+        let settings = &self.synth_settings;
+        if settings.engine != previous_engine {
+            self.send_synth_setting(ToWorkletMessage::SetVoiceEngine {
+                engine: settings.engine,
+            });
+        }
+        if settings.polyphony != previous_polyphony {
+            self.send_synth_setting(ToWorkletMessage::SetPolyphony {
+                voices: settings.polyphony,
+            });
+        }
+        if settings.voice_stealing != previous_voice_stealing {
+            self.send_synth_setting(ToWorkletMessage::SetVoiceStealing {
+                strategy: settings.voice_stealing,
+            });
+        }
+        if settings.sympathetic_resonance != previous_sympathetic_resonance {
+            self.send_synth_setting(ToWorkletMessage::SetSympatheticResonance {
+                wet_level: settings.sympathetic_resonance,
+            });
+        }
+        self.synth_settings_saved = false;
+    }
+
+    /// Menu for choosing which just ratio each interval is compared to
+    fn ratio_menu(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        let mut changed = ui
+            .checkbox(
+                &mut self.show_ratio_complexity,
+                "Show prime limit, odd limit and Tenney height",
+            )
+            .on_hover_text(
+                "Label the interval cells with p, o and H: the largest prime and the largest odd number \
+                 in the ratio, and log2 of its numerator times its denominator",
+            )
+            .changed();
+        ui.separator();
+        if !self.piano_gui.division().is_semitones() {
+            ui.label(
+                RichText::new(
+                    "Other divisions of the octave are named after the closest 11-limit ratio",
+                )
+                .color(ui.visuals().weak_text_color()),
+            );
+        }
+        egui::Grid::new("ratio_table").show(ui, |ui| {
+            for semitones in 1..12 {
+                let interval = Interval::from_semitone_interval(semitones);
+                ui.label(interval.to_string());
+                for &(numerator, denominator) in RatioTable::alternatives(interval) {
+                    let ratio = Rational32::new(numerator, denominator);
+                    let response = ui
+                        .radio(self.ratio_table.ratio(interval) == ratio, ratio.to_string())
+                        .on_hover_text(format!(
+                            "{}-limit, {} odd limit, Tenney height {:.1}",
+                            interval::prime_limit(ratio),
+                            interval::odd_limit(ratio),
+                            interval::tenney_height(ratio)
+                        ));
+                    if response.clicked() {
+                        self.ratio_table.set_ratio(interval, ratio);
+                        changed = true;
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if ui.button("Reset to 5-limit").clicked() {
+            self.ratio_table = RatioTable::default();
+            changed = true;
+        }
+        if changed {
+            self.save_ratio_settings(frame);
+        }
+    }
+
+    /// Play an action from the piano on the internal synth and on the MIDI output
+    fn play_action(&mut self, action: &piano_gui::Action, ctx: &egui::Context) {
+        if let piano_gui::Action::Pressed(note) = *action {
+            self.estimate_key(note);
+        }
+        if let Some(writer) = &mut self.midi.writer {
+            writer.send_action(action);
+        }
+        let muted = self.is_internal_synth_muted();
+        if let AudioState::Playing(web_audio) = &*self.audio.lock().unwrap() {
+            match *action {
+                piano_gui::Action::Pressed(note) => {
+                    if !muted {
+                        web_audio.ensure_running();
+                        web_audio.send_message(ToWorkletMessage::NoteOn {
+                            note: u8::from(note),
+                            velocity: u8::from(self.midi_output.velocity),
+                        });
+                    }
+                }
+                // Releases are sent even when muted, the note may have started before muting
+                piano_gui::Action::Released(note) => {
+                    web_audio.ensure_running();
+                    web_audio.send_message(ToWorkletMessage::NoteOff {
+                        note: u8::from(note),
+                    });
+                }
+                piano_gui::Action::SustainPedal(active) => {
+                    web_audio.ensure_running();
+                    web_audio.send_message(ToWorkletMessage::SustainPedal { active });
+                }
+            }
+        }
+        if let piano_gui::Action::SustainPedal(_) = action {
+            // Request immediate repaint to update the sustain label color
+            ctx.request_repaint();
+        }
+    }
+
+    /// Whether the internal synth is muted, as the notes are played on the connected MIDI output instead
+    fn is_internal_synth_muted(&self) -> bool {
+        self.midi_output.mute_internal_synth && self.midi.writer.is_some()
+    }
+
+    /// Handles messages from MIDI inputs. Notes are sent straight to the synth to keep the latency down,
+    /// everything is also forwarded to the GUI thread.
+    fn midi_callback(
+        &self,
+        ctx: &egui::Context,
+    ) -> impl Fn(&wmidi::MidiMessage<'_>) + Send + 'static {
+        let to_gui_tx = self.midi_to_piano_gui_tx.clone();
+        let ctx = ctx.clone();
+        let audio = self.audio.clone();
+        let muted = self.internal_synth_muted.clone();
+        move |message| {
+            if let AudioState::Playing(web_audio) = &*audio.lock().unwrap() {
+                match message {
+                    // Releases are sent even when muted, the note may have started before muting
+                    wmidi::MidiMessage::NoteOff(_, note, _) => {
+                        web_audio.ensure_running();
+                        web_audio.send_message(ToWorkletMessage::NoteOff {
+                            note: u8::from(*note),
+                        });
+                    }
+                    // Muted notes are only played on the MIDI output
+                    wmidi::MidiMessage::NoteOn(_, note, velocity)
+                        if !muted.load(Ordering::Relaxed) =>
+                    {
+                        web_audio.ensure_running();
+                        web_audio.send_message(ToWorkletMessage::NoteOn {
+                            note: u8::from(*note),
+                            velocity: u8::from(*velocity),
+                        });
+                    }
+                    wmidi::MidiMessage::ControlChange(_, control, _value) => {
+                        // Check for sustain pedal (control 64)
+                        if u8::from(*control) == 64 {
+                            // MIDI sustain pedal - values >= 64 are "on", values < 64 are "off"
+                            // Note: Do not send sustain message directly to synth here
+                            // This will be handled in the main event loop to combine with shift sustain
                         }
                     }
+                    _ => {}
+                }
+            }
+
+            to_gui_tx.send(message.to_owned()).unwrap();
+            ctx.request_repaint();
+        }
+    }
+
+    /// Menu for choosing where to send the notes played in the app
+    fn midi_output_menu(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        let mut port = self.midi_output.port.clone();
+        ui.radio_value(&mut port, None, "None");
+        if self.midi.available_output_ports.is_empty() {
+            ui.weak("no MIDI output ports found");
+        }
+        for available in &self.midi.available_output_ports {
+            ui.radio_value(&mut port, Some(available.clone()), available);
+        }
+        if let Some(selected) = port.clone()
+            && !self.midi.available_output_ports.contains(&selected)
+        {
+            // Keep showing a selected port while it is unplugged
+            let text = format!("{selected} (unplugged)");
+            ui.radio_value(&mut port, Some(selected), text);
+        }
+        let mut changed = false;
+        if port != self.midi_output.port {
+            self.midi_output.port = port;
+            // Connect right away rather than at the next periodic check
+            self.midi.last_checked = None;
+            changed = true;
+        }
+        ui.separator();
+
+        let mut channel = self.midi_output.channel.number();
+        ui.horizontal(|ui| {
+            ui.label("Channel");
+            ui.add(egui::DragValue::new(&mut channel).range(1..=16));
+        });
+        if channel != self.midi_output.channel.number()
+            && let Ok(channel) = wmidi::Channel::from_index(channel - 1)
+        {
+            self.midi_output.channel = channel;
+            if let Some(writer) = &mut self.midi.writer {
+                writer.set_channel(channel);
+            }
+            changed = true;
+        }
+
+        let mut velocity = u8::from(self.midi_output.velocity);
+        ui.horizontal(|ui| {
+            ui.label("Velocity");
+            ui.add(egui::Slider::new(&mut velocity, 1..=127));
+        })
+        .response
+        .on_hover_text("Velocity of the notes played in the app, also used by the internal synth");
+        if velocity != u8::from(self.midi_output.velocity) {
+            self.midi_output.velocity = wmidi::U7::from_u8_lossy(velocity);
+            if let Some(writer) = &mut self.midi.writer {
+                writer.set_velocity(self.midi_output.velocity);
+            }
+            changed = true;
+        }
+
+        changed |= ui
+            .checkbox(
+                &mut self.midi_output.mute_internal_synth,
+                "Mute internal synth",
+            )
+            .on_hover_text("Only play the notes on the MIDI output while it is connected")
+            .changed();
+        if changed {
+            self.save_midi_output_settings(frame);
+        }
+    }
+
+    /// Window with the partials of the held notes
+    fn spectrum_window(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut open = self.show_spectrum;
+        let notes: Vec<(wmidi::Note, f32)> = self
+            .piano_gui
+            .held_notes()
+            .iter_ones()
+            .filter_map(|note| {
+                let note = wmidi::Note::try_from(note as u8).ok()?;
+                Some((note, self.tuning_table.frequency(u8::from(note))?))
+            })
+            .collect();
+        egui::Window::new("Spectrum")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                spectrum_view::show(ui, &notes, &Spectrum::synth(&self.synth_settings.spectrum));
+                ui.weak("Partials closer than a critical band beat against each other, which is heard as roughness");
+            });
+        if open != self.show_spectrum {
+            self.show_spectrum = open;
+            self.save_spectrum_setting(frame);
+        }
+    }
+
+    /// Window with the roughness of a continuous sweep of intervals above the lowest held note
+    fn dissonance_curve_window(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut open = self.show_dissonance_curve;
+        let root = self
+            .piano_gui
+            .held_notes()
+            .first_one()
+            .and_then(|note| wmidi::Note::try_from(note as u8).ok())
+            .unwrap_or(wmidi::Note::C4);
+        let fundamental = self
+            .tuning_table
+            .frequency(u8::from(root))
+            .unwrap_or_else(|| root.to_freq_f32());
+        let mut changed = false;
+        egui::Window::new(format!("Dissonance curve above {}", root.to_str()))
+            .id(egui::Id::new("dissonance_curve"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                changed = self.dissonance_curve.show(ui, root, fundamental);
+                ui.weak("Dots mark the least rough intervals, the vertical lines are the notes of 12-TET");
+            });
+        if changed || open != self.show_dissonance_curve {
+            self.show_dissonance_curve = open;
+            self.save_dissonance_curve_settings(frame);
+        }
+    }
+
+    /// Score the intervals by their roughness with the spectrum the synth plays
+    fn update_roughness_model(&mut self) {
+        let roughness = self
+            .consonance_models
+            .iter_mut()
+            .find(|model| model.name() == Spectrum::NAME);
+        debug_assert!(
+            roughness.is_some(),
+            "the roughness should be one of the models"
+        );
+        if let Some(roughness) = roughness {
+            *roughness = Box::new(Spectrum::synth(&self.synth_settings.spectrum));
+        }
+    }
+
+    /// Window with the partials the synth plays each note with
+    fn timbre_editor_window(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut open = self.show_timbre_editor;
+        let previous_instrument = self.synth_settings.instrument;
+        let mut changed = false;
+        egui::Window::new("Timbre")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                changed = timbre_editor::show(
+                    ui,
+                    &mut self.synth_settings.instrument,
+                    &mut self.synth_settings.spectrum,
+                );
+                ui.weak("The roughness and beat rates of the intervals, the meter and the spectrum use these partials too");
+                if self.synth_settings.engine == VoiceEngine::Waveguide {
+                    ui.weak("The waveguide strings only take the stretch, their partials and envelope come from the hammer strike");
+                }
+            });
+        if changed {
+            self.update_roughness_model();
+            // The instrument comes first as it resets the spectrum
+            if self.synth_settings.instrument != previous_instrument {
+                self.send_synth_setting(ToWorkletMessage::SetInstrument {
+                    instrument: self.synth_settings.instrument,
+                });
+            }
+            self.send_synth_setting(ToWorkletMessage::SetSpectrum {
+                spectrum: self.synth_settings.spectrum,
+            });
+        }
+        if changed {
+            self.synth_settings_saved = false;
+        }
+        if open != self.show_timbre_editor {
+            self.show_timbre_editor = open;
+            self.save_timbre_editor_setting(frame);
+        }
+    }
+
+    /// Window with the reverb and limiter settings of the synth
+    fn effects_window(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut open = self.show_effects;
+        let mut changed = Vec::new();
+        egui::Window::new("Effects")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                changed = effects_panel::show(ui, &mut self.synth_settings.effects);
+            });
+        for &param in &changed {
+            self.send_synth_setting(ToWorkletMessage::SetEffectParam {
+                param,
+                value: self.synth_settings.effects.get(param),
+            });
+        }
+        if !changed.is_empty() {
+            self.synth_settings_saved = false;
+        }
+        if open != self.show_effects {
+            self.show_effects = open;
+            self.save_effects_setting(frame);
+        }
+    }
+
+    /// Window with the pitch-class set analysis of the held keys
+    fn set_analysis_window(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let mut open = self.show_set_analysis;
+        egui::Window::new("Pitch-class set")
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if !self.piano_gui.division().is_semitones() {
+                    ui.weak("Set analysis needs a tuning with 12 notes per octave");
+                    return;
+                }
+                let Some(analysis) = SetAnalysis::new(&self.piano_gui.held_keys()) else {
+                    ui.weak("Hold some keys to analyze them");
+                    return;
+                };
+                let note_names = |notes: &mut dyn Iterator<Item = Semitone>| {
+                    notes.map(Semitone::name).collect::<Vec<_>>().join(" ")
+                };
+                egui::Grid::new("set_analysis")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("Normal form")
+                            .on_hover_text("The held notes in their most compact order");
+                        ui.monospace(note_names(&mut analysis.normal_form.iter().copied()));
+                        ui.end_row();
+                        ui.label("Prime form").on_hover_text(
+                            "Most compact form of the set or its inversion, transposed to start on 0",
+                        );
+                        ui.monospace(pitch_class_set::format_pitch_classes(
+                            &analysis.prime_form,
+                        ));
+                        ui.end_row();
+                        ui.label("Forte number");
+                        ui.monospace(&analysis.forte_number);
+                        ui.end_row();
+                        ui.label("Interval vector").on_hover_text(
+                            "Number of pairs of notes 1 to 6 semitones apart, ignoring octaves and inversions",
+                        );
+                        ui.monospace(format!(
+                            "<{}>",
+                            analysis
+                                .interval_vector
+                                .iter()
+                                .map(u8::to_string)
+                                .collect::<String>()
+                        ));
+                        ui.end_row();
+                        ui.label("Complement")
+                            .on_hover_text("The pitch classes that aren't held");
+                        ui.monospace(format!(
+                            "{} {}",
+                            analysis.complement_forte_number,
+                            note_names(&mut Semitone::iter().filter(|semitone| {
+                                analysis.complement[semitone.as_usize()]
+                            }))
+                        ));
+                        ui.end_row();
+                        ui.label("Z partner").on_hover_text(
+                            "The set class with the same interval vector that isn't related by transposition or inversion",
+                        );
+                        ui.monospace(analysis.z_partner.as_deref().unwrap_or("none"));
+                        ui.end_row();
+                    });
+            });
+        if open != self.show_set_analysis {
+            self.show_set_analysis = open;
+            self.save_set_analysis_setting(frame);
+        }
+    }
+
+    /// Menu for choosing which MIDI ports to listen to
+    fn midi_port_menu(&mut self, ui: &mut egui::Ui, frame: &mut eframe::Frame) {
+        let mut selection = self.midi_port_selection.clone();
+        let mut first = selection == PortSelection::First;
+        if ui
+            .checkbox(&mut first, "First available port")
+            .on_hover_text("Listen to whichever port is listed first")
+            .changed()
+        {
+            selection = if first {
+                PortSelection::First
+            } else {
+                PortSelection::Named(
+                    self.midi
+                        .connections
+                        .iter()
+                        .map(|connection| connection.get_name().to_string())
+                        .collect(),
+                )
+            };
+        }
+        ui.separator();
+        if self.midi.available_ports.is_empty() {
+            ui.weak("no MIDI ports found");
+        }
+        for port in &self.midi.available_ports {
+            let wanted_ports = selection.wanted_ports(&self.midi.available_ports);
+            let mut selected = wanted_ports.contains(&port);
+            if ui.checkbox(&mut selected, port).changed() {
+                let mut names: BTreeSet<String> = wanted_ports.into_iter().cloned().collect();
+                if let PortSelection::Named(named) = &selection {
+                    // Keep ports that are selected but currently unplugged
+                    names.extend(named.iter().cloned());
+                }
+                if selected {
+                    names.insert(port.clone());
+                } else {
+                    names.remove(port);
                 }
+                selection = PortSelection::Named(names);
             }
-            _ => {}
+        }
+        if selection != self.midi_port_selection {
+            self.midi_port_selection = selection;
+            self.save_midi_port_selection(frame);
+            // Connect right away rather than at the next periodic check
+            self.midi.last_checked = None;
         }
     }
 }
@@ -202,15 +1706,26 @@ impl eframe::App for DissonanceLabApp {
 
         self.ensure_midi(ctx);
         self.check_audio_status();
+        self.internal_synth_muted
+            .store(self.is_internal_synth_muted(), Ordering::Relaxed);
+        self.check_picked_scala_files(frame);
+        self.sync_tuning();
+        self.sync_synth_settings();
 
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.with_layout(Layout::bottom_up(Align::Center), |ui| {
+                // Actions from the piano and from the piano controls in the status bar
+                let mut actions = Vec::new();
+                // The piano follows the division of the octave of the tuning
+                let division = self.octave_division();
+                if division != self.piano_gui.division() {
+                    self.piano_gui.set_division(division, &mut actions);
+                }
                 const STATUS_HEIGHT: f32 = 40.0;
                 ui.allocate_ui(
                     vec2(PIANO_WIDTH.min(ui.available_width()), STATUS_HEIGHT),
                     |ui| {
                         const MUTE_FONT_SIZE: f32 = 16.0;
-                        const STATUS_FONT_SIZE: f32 = 14.0;
                         ui.horizontal(|ui| {
                             let (playing, disabled, uninitialized) = {
                                 let audio_state = &*self.audio.lock().unwrap();
@@ -350,7 +1865,7 @@ impl eframe::App for DissonanceLabApp {
                             }
 
                             ui.label("|");
-                            let is_connected = matches!(&self.midi, MidiState::Connected(_));
+                            let is_connected = !self.midi.connections.is_empty();
                             let midi_text = if is_connected {
                                 RichText::new("MIDI ☑")
                                     .size(STATUS_FONT_SIZE)
@@ -362,12 +1877,37 @@ impl eframe::App for DissonanceLabApp {
                                     .strikethrough()
                             };
 
-                            let response = ui.label(midi_text);
-                            response.on_hover_text(match &self.midi {
-                                MidiState::NotConnected { .. } => "not connected".to_string(),
-                                MidiState::Connected(midi_reader) => {
-                                    midi_reader.get_name().to_string()
-                                }
+                            let response = ui
+                                .menu_button(midi_text, |ui| self.midi_port_menu(ui, frame))
+                                .response;
+                            response.on_hover_text(if is_connected {
+                                self.midi
+                                    .connections
+                                    .iter()
+                                    .map(MidiReader::get_name)
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            } else {
+                                "not connected (click to choose ports)".to_string()
+                            });
+
+                            let output_text = match &self.midi.writer {
+                                Some(_) => RichText::new("out ☑")
+                                    .size(STATUS_FONT_SIZE)
+                                    .color(ui.visuals().text_color()),
+                                None => RichText::new("out")
+                                    .size(STATUS_FONT_SIZE)
+                                    .color(ui.visuals().weak_text_color()),
+                            };
+                            let response = ui
+                                .menu_button(output_text, |ui| self.midi_output_menu(ui, frame))
+                                .response;
+                            response.on_hover_text(match &self.midi.writer {
+                                Some(writer) => format!(
+                                    "Sending the notes played in the app to {}",
+                                    writer.get_name()
+                                ),
+                                None => "MIDI output (click to choose a port)".to_string(),
                             });
 
                             // Add discreet sustain pedal polarity toggle when MIDI is connected
@@ -393,6 +1933,221 @@ impl eframe::App for DissonanceLabApp {
                                     "Sustain pedal: normal (click to invert polarity)"
                                 });
                             }
+
+                            ui.label("|");
+                            let (mode_icon, mode_hover_text) = match self.interval_display_mode {
+                                DisplayMode::RowPerHeldKey => (
+                                    "☰",
+                                    "Showing one row per held key (click to show a single next note row)",
+                                ),
+                                DisplayMode::NextNote => (
+                                    "▭",
+                                    "Showing what adding each key would do to the held chord (click to show one row per held key)",
+                                ),
+                            };
+                            let mode_button = ui.small_button(
+                                RichText::new(mode_icon)
+                                    .size(STATUS_FONT_SIZE)
+                                    .color(ui.visuals().weak_text_color()),
+                            );
+                            if mode_button.clicked() {
+                                self.interval_display_mode = match self.interval_display_mode {
+                                    DisplayMode::RowPerHeldKey => DisplayMode::NextNote,
+                                    DisplayMode::NextNote => DisplayMode::RowPerHeldKey,
+                                };
+                                self.save_interval_display_mode(frame);
+                            }
+                            mode_button.on_hover_text(mode_hover_text);
+                            let mut consonance_model = self.consonance_model;
+                            egui::ComboBox::from_id_salt("consonance_model")
+                                .selected_text(
+                                    RichText::new(self.consonance_models[consonance_model].name())
+                                        .size(STATUS_FONT_SIZE)
+                                        .color(ui.visuals().weak_text_color()),
+                                )
+                                .show_ui(ui, |ui| {
+                                    for (index, model) in self.consonance_models.iter().enumerate() {
+                                        ui.selectable_value(&mut consonance_model, index, model.name());
+                                    }
+                                })
+                                .response
+                                .on_hover_text("How the interval rows rate the dissonance of each interval");
+                            if consonance_model != self.consonance_model {
+                                self.consonance_model = consonance_model;
+                                self.save_consonance_model(frame);
+                            }
+                            ui.menu_button(
+                                RichText::new("ratios")
+                                    .size(STATUS_FONT_SIZE)
+                                    .color(ui.visuals().weak_text_color()),
+                                |ui| self.ratio_menu(ui, frame),
+                            )
+                            .response
+                            .on_hover_text("Which just ratio each interval is compared to");
+                            if panel_toggle(
+                                ui,
+                                &mut self.show_set_analysis,
+                                "ℹ",
+                                "Show the pitch-class set analysis of the held keys",
+                                "Hide the pitch-class set analysis of the held keys",
+                            ) {
+                                self.save_set_analysis_setting(frame);
+                            }
+                            if panel_toggle(
+                                ui,
+                                &mut self.show_spectrum,
+                                "📊",
+                                "Show the partials of the held notes and which of them beat against each other",
+                                "Hide the partials of the held notes",
+                            ) {
+                                self.save_spectrum_setting(frame);
+                            }
+                            if panel_toggle(
+                                ui,
+                                &mut self.show_dissonance_curve,
+                                "📉",
+                                "Show the roughness of every interval up from the lowest held note",
+                                "Hide the dissonance curve",
+                            ) {
+                                self.save_dissonance_curve_settings(frame);
+                            }
+                            if panel_toggle(
+                                ui,
+                                &mut self.show_timbre_editor,
+                                "🎵",
+                                "Edit the partials the synth plays, which the roughness is calculated with too",
+                                "Hide the timbre editor",
+                            ) {
+                                self.save_timbre_editor_setting(frame);
+                            }
+                            if panel_toggle(
+                                ui,
+                                &mut self.show_effects,
+                                "🔊",
+                                "Set the reverb and limiter the synth plays through",
+                                "Hide the effects",
+                            ) {
+                                self.save_effects_setting(frame);
+                            }
+
+                            ui.label("|");
+                            let mut keyboard_size = self.piano_gui.keyboard_size();
+                            egui::ComboBox::from_id_salt("keyboard_size")
+                                .selected_text(
+                                    RichText::new(keyboard_size.label())
+                                        .size(STATUS_FONT_SIZE)
+                                        .color(ui.visuals().weak_text_color()),
+                                )
+                                .show_ui(ui, |ui| {
+                                    for size in KeyboardSize::PRESETS {
+                                        ui.selectable_value(&mut keyboard_size, size, size.label());
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Number of keys on the piano");
+                            if keyboard_size != self.piano_gui.keyboard_size() {
+                                self.piano_gui.set_keyboard_size(keyboard_size, &mut actions);
+                                self.save_keyboard_size(frame);
+                            }
+
+                            let octave = self.piano_gui.octave();
+                            let down_button = ui.small_button(
+                                RichText::new("◀").color(ui.visuals().weak_text_color()),
+                            );
+                            if down_button.clicked() {
+                                self.piano_gui.shift_octave(-1, &mut actions);
+                            }
+                            down_button.on_hover_text("Octave down (- or Z)");
+                            ui.label(
+                                RichText::new(format!("C{octave}"))
+                                    .size(STATUS_FONT_SIZE)
+                                    .color(ui.visuals().weak_text_color()),
+                            )
+                            .on_hover_text(
+                                "Octave of the piano and of the computer keyboard keys A W S E D F T G Y H U J K",
+                            );
+                            let up_button = ui.small_button(
+                                RichText::new("▶").color(ui.visuals().weak_text_color()),
+                            );
+                            if up_button.clicked() {
+                                self.piano_gui.shift_octave(1, &mut actions);
+                            }
+                            up_button.on_hover_text("Octave up (+ or X)");
+
+                            ui.label("|");
+                            let tuning_name = match &self.scala_tuning {
+                                Some(_) => "Scala".to_string(),
+                                None if self.tuning.is_edo() => {
+                                    format!("{}-EDO", self.tuning.steps_per_octave)
+                                }
+                                None => self.tuning.temperament.name().to_string(),
+                            };
+                            let tuning_hover_text = match &self.scala_tuning {
+                                Some(scala_tuning) => format!(
+                                    "Tuning of the synth: {}. The cents show how far each interval is from just",
+                                    scala_tuning.name
+                                ),
+                                None => "Tuning of the synth, the cents show how far each interval is from just".to_string(),
+                            };
+                            ui.menu_button(
+                                RichText::new(tuning_name)
+                                    .size(STATUS_FONT_SIZE)
+                                    .color(ui.visuals().weak_text_color()),
+                                |ui| self.tuning_menu(ui, frame),
+                            )
+                            .response
+                            .on_hover_text(tuning_hover_text);
+                            ui.menu_button(
+                                RichText::new(format!("{} voices", self.synth_settings.polyphony))
+                                    .size(STATUS_FONT_SIZE)
+                                    .color(ui.visuals().weak_text_color()),
+                                |ui| self.synth_menu(ui),
+                            )
+                            .response
+                            .on_hover_text("How the internal synth plays the notes");
+
+                            if self.piano_gui.division().is_semitones() {
+                                ui.label("|");
+                                let key = self.key();
+                                let key_name = match (key, self.locked_key) {
+                                    (Some(key), Some(_)) => format!("🔒 {key}"),
+                                    (Some(key), None) => key.to_string(),
+                                    (None, _) => "key?".to_string(),
+                                };
+                                ui.menu_button(
+                                    RichText::new(key_name)
+                                        .size(STATUS_FONT_SIZE)
+                                        .color(ui.visuals().weak_text_color()),
+                                    |ui| self.key_menu(ui, frame),
+                                )
+                                .response
+                                .on_hover_text(if self.locked_key.is_some() {
+                                    "Key the chords are analyzed in, locked (click to change)"
+                                } else {
+                                    "Key estimated from the recently played notes (click to lock it)"
+                                });
+                                if let Some(key) = key
+                                    && let Some(chord) = self.piano_gui.chord_names().first()
+                                {
+                                    ui.label(
+                                        RichText::new(key_detection::roman_numeral(key, chord))
+                                            .size(STATUS_FONT_SIZE),
+                                    )
+                                    .on_hover_text(format!("Function of {chord} in {key}"));
+                                }
+                            } else {
+                                ui.label("|");
+                                ui.label(
+                                    RichText::new("no keys or chords")
+                                        .size(STATUS_FONT_SIZE)
+                                        .color(ui.visuals().weak_text_color()),
+                                )
+                                .on_hover_text(format!(
+                                    "Keys, chord names and set analysis need 12 notes per octave, \
+                                     the tuning has {}",
+                                    self.piano_gui.division().steps()
+                                ));
+                            }
                         });
                         ui.painter().text(
                             ui.max_rect().center_bottom(),
@@ -419,25 +2174,38 @@ impl eframe::App for DissonanceLabApp {
                                 );
                             }
                         } else {
-                            ui.painter().text(
+                            let chord_rect = ui.painter().text(
                                 ui.max_rect().right_bottom(),
                                 Align2::RIGHT_BOTTOM,
                                 self.piano_gui.selected_chord_name().unwrap(),
                                 FontId::monospace(STATUS_FONT_SIZE),
                                 ui.visuals().text_color(),
                             );
+                            // The next most likely name, such as C6 for Amin7 without a bass note
+                            if let Some(alternative) = self.piano_gui.chord_names().get(1) {
+                                const ALTERNATIVE_SPACING: f32 = 8.0;
+                                ui.painter().text(
+                                    chord_rect.left_bottom() - vec2(ALTERNATIVE_SPACING, 0.0),
+                                    Align2::RIGHT_BOTTOM,
+                                    format!("or {alternative}"),
+                                    FontId::monospace(STATUS_FONT_SIZE),
+                                    ui.visuals().weak_text_color(),
+                                );
+                            }
                         }
                     },
                 );
 
                 // Process MIDI messages
-                for message in self.midi_to_piano_gui_rx.try_iter() {
+                let messages: Vec<_> = self.midi_to_piano_gui_rx.try_iter().collect();
+                for message in messages {
                     match message {
                         wmidi::MidiMessage::NoteOff(_channel, note, _) => {
                             self.piano_gui.external_note_off(note);
                         }
                         wmidi::MidiMessage::NoteOn(_channel, note, _) => {
                             self.piano_gui.external_note_on(note);
+                            self.estimate_key(note);
                         }
                         wmidi::MidiMessage::ControlChange(_, control, value) => {
                             // Check for sustain pedal (control 64)
@@ -455,31 +2223,11 @@ impl eframe::App for DissonanceLabApp {
 
                                 // Process the sustain actions
                                 for action in sustain_actions {
-                                    match action {
-                                        piano_gui::Action::SustainPedal(active) => {
-                                            if let AudioState::Playing(web_audio) =
-                                                &*self.audio.lock().unwrap()
-                                            {
-                                                web_audio.ensure_running();
-                                                web_audio.send_message(
-                                                    ToWorkletMessage::SustainPedal { active },
-                                                );
-                                            }
-                                            // Request immediate repaint to update the sustain label color
-                                            ctx.request_repaint();
-                                        }
-                                        piano_gui::Action::Released(note) => {
-                                            if let AudioState::Playing(web_audio) = &*self.audio.lock().unwrap() {
-                                                web_audio.ensure_running();
-                                                web_audio.send_message(ToWorkletMessage::NoteOff {
-                                                    note: u8::from(note),
-                                                });
-                                            }
-                                        }
-                                        _ => {
-                                            debug_assert!(false, "Unexpected action from set_external_sustain: {action:?}");
-                                        }
-                                    }
+                                    debug_assert!(
+                                        !matches!(action, piano_gui::Action::Pressed(_)),
+                                        "Unexpected action from set_external_sustain: {action:?}"
+                                    );
+                                    self.play_action(&action, ctx);
                                 }
                             }
                         }
@@ -488,39 +2236,70 @@ impl eframe::App for DissonanceLabApp {
                 }
 
                 // Show piano GUI and process actions
-                let actions = interval_display::show(&mut self.piano_gui, ui);
-                for action in actions {
-                    match action {
-                        piano_gui::Action::Pressed(note) => {
-                            if let AudioState::Playing(web_audio) = &*self.audio.lock().unwrap() {
-                                web_audio.ensure_running();
-                                web_audio.send_message(ToWorkletMessage::NoteOn {
-                                    note: u8::from(note),
-                                    velocity: 64,
-                                });
-                            }
-                        }
-                        piano_gui::Action::Released(note) => {
-                            if let AudioState::Playing(web_audio) = &*self.audio.lock().unwrap() {
-                                web_audio.ensure_running();
-                                web_audio.send_message(ToWorkletMessage::NoteOff {
-                                    note: u8::from(note),
-                                });
-                            }
-                        }
-                        piano_gui::Action::SustainPedal(active) => {
-                            if let AudioState::Playing(web_audio) = &*self.audio.lock().unwrap() {
-                                web_audio.ensure_running();
-                                web_audio.send_message(ToWorkletMessage::SustainPedal { active });
-                            }
-                            // Request immediate repaint to update the sustain label color
-                            ctx.request_repaint();
+                actions.extend(interval_display::show(
+                    &mut self.piano_gui,
+                    self.interval_display_mode,
+                    &interval_display::CellSettings {
+                        tuning: &self.tuning_table,
+                        model: self.consonance_models[self.consonance_model].as_ref(),
+                        spectrum: &self.synth_settings.spectrum,
+                        ratios: &self.ratio_table,
+                        show_ratio_complexity: self.show_ratio_complexity,
+                    },
+                    ui,
+                ));
+                ui.allocate_ui(
+                    vec2(
+                        PIANO_WIDTH.min(ui.available_width()),
+                        dissonance_meter::METER_HEIGHT,
+                    ),
+                    |ui| {
+                        if self.dissonance_meter.show(
+                            ui,
+                            &self.tuning_table,
+                            &self.piano_gui.held_notes(),
+                            &self.consonance_models,
+                        ) {
+                            self.save_consonance_model(frame);
                         }
-                    }
+                    },
+                );
+                for action in actions {
+                    self.play_action(&action, ctx);
                 }
             });
         });
+        self.set_analysis_window(ctx, frame);
+        self.spectrum_window(ctx, frame);
+        self.dissonance_curve_window(ctx, frame);
+        self.timbre_editor_window(ctx, frame);
+        self.effects_window(ctx, frame);
+        self.save_synth_settings_after_drag(ctx, frame);
         const REPAINT_PERIOD: Duration = Duration::from_millis(500); // 2 times per second
         ctx.request_repaint_after(REPAINT_PERIOD);
     }
 }
+
+/// Status bar button with `icon` that shows or hides a panel, bright while the panel is `shown`.
+/// Returns whether it was toggled.
+fn panel_toggle(
+    ui: &mut egui::Ui,
+    shown: &mut bool,
+    icon: &str,
+    show_hint: &str,
+    hide_hint: &str,
+) -> bool {
+    let color = if *shown {
+        ui.visuals().text_color()
+    } else {
+        ui.visuals().weak_text_color()
+    };
+    let clicked = ui
+        .small_button(RichText::new(icon).size(STATUS_FONT_SIZE).color(color))
+        .on_hover_text(if *shown { hide_hint } else { show_hint })
+        .clicked();
+    if clicked {
+        *shown = !*shown;
+    }
+    clicked
+}
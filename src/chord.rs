@@ -0,0 +1,432 @@
+use std::fmt::{Display, Formatter, Result};
+
+use crate::piano_types::{KeySet, Semitone};
+
+/// A kind of chord, described by the pitch classes above its root
+struct ChordType {
+    /// Appended to the root to name the chord
+    suffix: &'static str,
+    /// Semitones above the root, including the root itself
+    intervals: &'static [u8],
+    /// How likely a listener is to hear the notes as this chord rather than as another chord with the same notes.
+    /// Plain triads and sevenths are more common than extended and altered chords.
+    weight: f32,
+}
+
+const COMMON: f32 = 1.0;
+const EXTENDED: f32 = 0.8;
+const ALTERED: f32 = 0.6;
+
+/// The chords that can be recognized.
+/// Chords with a seventh are also recognized without their perfect fifth, which is often left out.
+const CHORD_TYPES: [ChordType; 33] = [
+    ChordType {
+        suffix: "5",
+        intervals: &[0, 7],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "maj",
+        intervals: &[0, 4, 7],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "min",
+        intervals: &[0, 3, 7],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "dim",
+        intervals: &[0, 3, 6],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "aug",
+        intervals: &[0, 4, 8],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "sus2",
+        intervals: &[0, 2, 7],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "sus4",
+        intervals: &[0, 5, 7],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "maj7",
+        intervals: &[0, 4, 7, 11],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "min7",
+        intervals: &[0, 3, 7, 10],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "7",
+        intervals: &[0, 4, 7, 10],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "dim7",
+        intervals: &[0, 3, 6, 9],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "m7b5",
+        intervals: &[0, 3, 6, 10],
+        weight: COMMON,
+    },
+    ChordType {
+        suffix: "minmaj7",
+        intervals: &[0, 3, 7, 11],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "7sus4",
+        intervals: &[0, 5, 7, 10],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "6",
+        intervals: &[0, 4, 7, 9],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "min6",
+        intervals: &[0, 3, 7, 9],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "6/9",
+        intervals: &[0, 2, 4, 7, 9],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "add9",
+        intervals: &[0, 2, 4, 7],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "minadd9",
+        intervals: &[0, 2, 3, 7],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "add11",
+        intervals: &[0, 4, 5, 7],
+        weight: ALTERED,
+    },
+    ChordType {
+        suffix: "9",
+        intervals: &[0, 2, 4, 7, 10],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "maj9",
+        intervals: &[0, 2, 4, 7, 11],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "min9",
+        intervals: &[0, 2, 3, 7, 10],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "11",
+        intervals: &[0, 2, 4, 5, 7, 10],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "min11",
+        intervals: &[0, 2, 3, 5, 7, 10],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "13",
+        intervals: &[0, 2, 4, 7, 9, 10],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "maj13",
+        intervals: &[0, 2, 4, 7, 9, 11],
+        weight: EXTENDED,
+    },
+    ChordType {
+        suffix: "7b5",
+        intervals: &[0, 4, 6, 10],
+        weight: ALTERED,
+    },
+    ChordType {
+        suffix: "7#5",
+        intervals: &[0, 4, 8, 10],
+        weight: ALTERED,
+    },
+    ChordType {
+        suffix: "7b9",
+        intervals: &[0, 1, 4, 7, 10],
+        weight: ALTERED,
+    },
+    ChordType {
+        suffix: "7#9",
+        intervals: &[0, 3, 4, 7, 10],
+        weight: ALTERED,
+    },
+    ChordType {
+        suffix: "7#11",
+        intervals: &[0, 4, 6, 7, 10],
+        weight: ALTERED,
+    },
+    ChordType {
+        suffix: "7b13",
+        intervals: &[0, 4, 7, 8, 10],
+        weight: ALTERED,
+    },
+];
+
+/// A way of naming the held keys as a chord
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChordName {
+    pub root: Semitone,
+    pub suffix: &'static str,
+    /// Semitones above the root of the notes of the chord, including the root itself
+    pub intervals: &'static [u8],
+    /// The lowest note, when it isn't the root. The chord is then an inversion, written as a slash chord.
+    pub bass: Option<Semitone>,
+    /// How likely this name is compared to the other names of the same keys, the confidences of all names add up to 1
+    pub confidence: f32,
+}
+
+impl Display for ChordName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}{}", self.root.name(), self.suffix)?;
+        if let Some(bass) = self.bass {
+            write!(f, "/{}", bass.name())?;
+        }
+        Ok(())
+    }
+}
+
+/// The held keys as a bit mask of pitch classes above `root`
+fn intervals_above(held_keys: &KeySet, root: usize) -> u16 {
+    held_keys
+        .iter_ones()
+        .map(|semitone| (semitone + 12 - root) % 12)
+        .fold(0, |mask, interval| mask | 1 << interval)
+}
+
+fn mask(intervals: &[u8]) -> u16 {
+    intervals
+        .iter()
+        .fold(0, |mask, &interval| mask | 1 << interval)
+}
+
+/// All the ways the held keys can be named as a chord, most likely first.
+/// `bass` is the pitch class of the lowest held note, if it is known. Chords built on it are preferred,
+/// and chords built on other notes are named as slash chords over it.
+pub fn recognize(held_keys: &KeySet, bass: Option<Semitone>) -> Vec<ChordName> {
+    // Taking the chord's root as the bass is by far the most common voicing
+    const ROOT_POSITION_WEIGHT: f32 = 2.0;
+    const OMITTED_FIFTH_WEIGHT: f32 = 0.8;
+    const PERFECT_FIFTH: u8 = 7;
+    const SEVENTHS: [u8; 2] = [10, 11];
+    debug_assert!(bass.is_none_or(|bass| held_keys[bass.as_index()]));
+
+    let mut names: Vec<(ChordName, f32)> = Vec::new();
+    for root in held_keys.iter_ones() {
+        let intervals = intervals_above(held_keys, root);
+        for chord_type in &CHORD_TYPES {
+            let chord_mask = mask(chord_type.intervals);
+            let weight = if intervals == chord_mask {
+                chord_type.weight
+            } else if chord_type.intervals.contains(&PERFECT_FIFTH)
+                && SEVENTHS
+                    .iter()
+                    .any(|seventh| chord_type.intervals.contains(seventh))
+                && intervals == chord_mask & !(1 << PERFECT_FIFTH)
+            {
+                chord_type.weight * OMITTED_FIFTH_WEIGHT
+            } else {
+                continue;
+            };
+            let root = Semitone::from_usize(root);
+            let (bass, weight) = match bass {
+                Some(bass) if bass == root => (None, weight * ROOT_POSITION_WEIGHT),
+                bass => (bass, weight),
+            };
+            names.push((
+                ChordName {
+                    root,
+                    suffix: chord_type.suffix,
+                    intervals: chord_type.intervals,
+                    bass,
+                    confidence: 0.0,
+                },
+                weight,
+            ));
+        }
+    }
+    let total_weight: f32 = names.iter().map(|(_, weight)| weight).sum();
+    // The sort is stable, so equally likely names stay ordered by root
+    names.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    names
+        .into_iter()
+        .map(|(name, weight)| ChordName {
+            confidence: weight / total_weight,
+            ..name
+        })
+        .collect()
+}
+
+/// Name of the held keys: the most likely chord name if they form a chord, otherwise the names of the notes
+pub fn describe(held_keys: &KeySet, bass: Option<Semitone>) -> Option<String> {
+    if held_keys.not_any() {
+        return None;
+    }
+    if let Some(name) = recognize(held_keys, bass).first() {
+        return Some(name.to_string());
+    }
+    let notes: Vec<&str> = held_keys
+        .iter_ones()
+        .map(|semitone| Semitone::from_usize(semitone).name())
+        .collect();
+    Some(notes.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(semitones: &[u8]) -> KeySet {
+        let mut keys = KeySet::default();
+        for &semitone in semitones {
+            keys.set(usize::from(semitone), true);
+        }
+        keys
+    }
+
+    const C: u8 = 0;
+    const C_SHARP: u8 = 1;
+    const D: u8 = 2;
+    const D_SHARP: u8 = 3;
+    const E: u8 = 4;
+    const F: u8 = 5;
+    const F_SHARP: u8 = 6;
+    const G: u8 = 7;
+    const G_SHARP: u8 = 8;
+    const A: u8 = 9;
+    const A_SHARP: u8 = 10;
+    const B: u8 = 11;
+
+    #[test]
+    fn test_chord_names() {
+        // (held pitch classes, lowest note, expected name)
+        let cases: &[(&[u8], Option<u8>, &str)] = &[
+            (&[C, E, G], None, "Cmaj"),
+            (&[C, E, G], Some(C), "Cmaj"),
+            (&[C, D_SHARP, G], Some(C), "Cmin"),
+            (&[B, D, F], Some(B), "Bdim"),
+            (&[C, E, G_SHARP], Some(C), "Caug"),
+            (&[C, G], Some(C), "C5"),
+            (&[C, D, G], Some(C), "Csus2"),
+            (&[C, F, G], Some(C), "Csus4"),
+            (&[C, E, G, B], Some(C), "Cmaj7"),
+            (&[C, D_SHARP, G, A_SHARP], Some(C), "Cmin7"),
+            (&[G, B, D, F], Some(G), "G7"),
+            (&[B, D, F, G_SHARP], Some(B), "Bdim7"),
+            (&[B, D, F, A], Some(B), "Bm7b5"),
+            (&[C, D_SHARP, G, B], Some(C), "Cminmaj7"),
+            (&[G, C, D, F], Some(G), "G7sus4"),
+            (&[C, E, G, A], Some(C), "C6"),
+            (&[C, D_SHARP, G, A], Some(C), "Cmin6"),
+            (&[C, D, E, G, A], Some(C), "C6/9"),
+            (&[C, D, E, G], Some(C), "Cadd9"),
+            (&[C, D, D_SHARP, G], Some(C), "Cminadd9"),
+            (&[C, E, F, G], Some(C), "Cadd11"),
+            (&[C, D, E, G, A_SHARP], Some(C), "C9"),
+            (&[C, D, E, G, B], Some(C), "Cmaj9"),
+            (&[C, D, D_SHARP, G, A_SHARP], Some(C), "Cmin9"),
+            (&[C, D, E, F, G, A_SHARP], Some(C), "C11"),
+            (&[C, D, D_SHARP, F, G, A_SHARP], Some(C), "Cmin11"),
+            (&[C, D, E, G, A, A_SHARP], Some(C), "C13"),
+            (&[C, D, E, G, A, B], Some(C), "Cmaj13"),
+            (&[C, E, F_SHARP, A_SHARP], Some(C), "C7b5"),
+            (&[C, E, G_SHARP, A_SHARP], Some(C), "C7#5"),
+            (&[C, C_SHARP, E, G, A_SHARP], Some(C), "C7b9"),
+            (&[C, D_SHARP, E, G, A_SHARP], Some(C), "C7#9"),
+            (&[C, E, F_SHARP, G, A_SHARP], Some(C), "C7#11"),
+            (&[C, E, G, G_SHARP, A_SHARP], Some(C), "C7b13"),
+            // Extended chords are often played without the fifth
+            (&[C, D, E, A_SHARP], Some(C), "C9"),
+            (&[C, E, B], Some(C), "Cmaj7"),
+            // Inversions are slash chords over the lowest note
+            (&[C, E, G], Some(E), "Cmaj/E"),
+            (&[C, E, G], Some(G), "Cmaj/G"),
+            (&[G, B, D, F], Some(F), "G7/F"),
+            (&[C, F], Some(C), "F5/C"),
+            // Without a bass the more common chord wins, with one the chord built on it
+            (&[C, E, G, A], None, "Amin7"),
+            (&[C, E, G, A], Some(A), "Amin7"),
+            (&[C, E, G, A], Some(E), "Amin7/E"),
+        ];
+        for &(semitones, bass, expected) in cases {
+            let bass = bass.map(Semitone::new);
+            assert_eq!(
+                describe(&keys(semitones), bass).as_deref(),
+                Some(expected),
+                "keys {semitones:?} over {bass:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unrecognized_keys_are_named_by_their_notes() {
+        let cases: &[(&[u8], Option<&str>)] = &[
+            (&[], None),
+            (&[D], Some("D")),
+            (&[C, E], Some("C E")),
+            (&[C, C_SHARP, D], Some("C C# D")),
+        ];
+        for &(semitones, expected) in cases {
+            assert!(recognize(&keys(semitones), None).is_empty());
+            assert_eq!(describe(&keys(semitones), None).as_deref(), expected);
+        }
+    }
+
+    #[test]
+    fn test_confidence_ranking() {
+        let names = recognize(&keys(&[C, E, G, A]), None);
+        let names_and_confidences: Vec<(String, f32)> = names
+            .iter()
+            .map(|name| (name.to_string(), name.confidence))
+            .collect();
+        assert_eq!(names.len(), 2, "{names_and_confidences:?}");
+        assert_eq!(names[0].to_string(), "Amin7");
+        assert_eq!(names[1].to_string(), "C6");
+        assert!(names[0].confidence > names[1].confidence);
+        let total: f32 = names.iter().map(|name| name.confidence).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+
+        // The bass decides between the names
+        let names = recognize(&keys(&[C, E, G, A]), Some(Semitone::new(C)));
+        assert_eq!(names[0].to_string(), "C6");
+        assert_eq!(names[1].to_string(), "Amin7/C");
+
+        // A diminished seventh chord is symmetric, so only the bass tells the roots apart
+        let names = recognize(&keys(&[C, D_SHARP, F_SHARP, A]), None);
+        assert_eq!(names.len(), 4);
+        assert!(
+            names
+                .iter()
+                .all(|name| name.suffix == "dim7" && (name.confidence - 0.25).abs() < 1e-6)
+        );
+        let names = recognize(
+            &keys(&[C, D_SHARP, F_SHARP, A]),
+            Some(Semitone::new(F_SHARP)),
+        );
+        assert_eq!(names[0].to_string(), "F#dim7");
+    }
+}
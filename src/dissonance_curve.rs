@@ -0,0 +1,293 @@
+use crate::{
+    interval,
+    roughness::{self, Spectrum},
+    theme, timbre_editor,
+};
+use egui::{Align2, Color32, FontId, Rect, Sense, Shape, Stroke, Ui, pos2, vec2};
+use shared_types::SynthSpectrum;
+use wmidi::Note;
+
+const CENTS_PER_OCTAVE: u32 = 1200;
+const CENTS_PER_SEMITONE: u32 = 100;
+
+/// Partials of the sawtooth timbre
+const SAWTOOTH_PARTIALS: usize = 8;
+
+/// Which spectrum the dissonance curve is calculated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timbre {
+    /// The inharmonic spectrum of the synth's piano
+    Piano,
+    /// Harmonic partials falling off as 1/n
+    Sawtooth,
+    /// Partials set by the user
+    Custom,
+}
+
+impl Timbre {
+    const ALL: [Self; 3] = [Self::Piano, Self::Sawtooth, Self::Custom];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Piano => "piano",
+            Self::Sawtooth => "sawtooth",
+            Self::Custom => "custom",
+        }
+    }
+
+    /// Short identifier used when persisting the setting
+    pub fn storage_key(self) -> &'static str {
+        self.label()
+    }
+
+    pub fn from_storage_key(key: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|timbre| timbre.storage_key() == key)
+    }
+}
+
+/// Roughness of a dyad for every cent from unison up to `octaves` octaves above a root at `fundamental` Hz
+fn sweep(spectrum: &Spectrum, root: Note, fundamental: f32, octaves: u32) -> Vec<f32> {
+    (0..=octaves * CENTS_PER_OCTAVE)
+        .map(|cents| {
+            let ratio = 2f32.powf(cents as f32 / CENTS_PER_OCTAVE as f32);
+            roughness::dyad_roughness(spectrum, root, fundamental, ratio)
+        })
+        .collect()
+}
+
+/// The intervals in cents where the roughness is lower than anywhere else within an eighth tone,
+/// leaving out unison and the end of the sweep. A flat bottom counts once, at its lower end.
+fn minima(curve: &[f32]) -> Vec<u32> {
+    const WINDOW_CENTS: usize = 25;
+    (1..curve.len().saturating_sub(1))
+        .filter(|&cents| {
+            let below = cents.saturating_sub(WINDOW_CENTS)..cents;
+            let above = cents + 1..(cents + WINDOW_CENTS + 1).min(curve.len());
+            curve[below].iter().all(|&other| curve[cents] < other)
+                && curve[above].iter().all(|&other| curve[cents] <= other)
+        })
+        .map(|cents| cents as u32)
+        .collect()
+}
+
+/// What the cached curve was calculated for
+#[derive(Debug, Clone, PartialEq)]
+struct CurveKey {
+    spectrum: Spectrum,
+    root: Note,
+    fundamental: f32,
+    octaves: u32,
+}
+
+/// Panel plotting the roughness of an interval as its upper note sweeps continuously away from a fixed root
+pub struct DissonanceCurve {
+    pub timbre: Timbre,
+    /// Partials of the custom timbre, edited like the synth's own
+    pub custom_spectrum: SynthSpectrum,
+    octaves: u32,
+    /// The sweep is too slow to repeat every frame, so it is only recalculated when its inputs change
+    cache: Option<(CurveKey, Vec<f32>)>,
+}
+
+impl DissonanceCurve {
+    pub fn new() -> Self {
+        Self {
+            timbre: Timbre::Piano,
+            custom_spectrum: Self::default_custom_spectrum(),
+            octaves: 1,
+            cache: None,
+        }
+    }
+
+    /// Odd harmonics only, like a clarinet or a square wave
+    fn default_custom_spectrum() -> SynthSpectrum {
+        const ODD_HARMONICS: [f32; 7] = [1.0, 0.0, 0.33, 0.0, 0.2, 0.0, 0.14];
+        let mut amplitudes = [0.0; SynthSpectrum::NUM_PARTIALS];
+        amplitudes[..ODD_HARMONICS.len()].copy_from_slice(&ODD_HARMONICS);
+        SynthSpectrum {
+            amplitudes,
+            ratios: SynthSpectrum::HARMONIC_RATIOS,
+            stretch: 0.0,
+        }
+    }
+
+    fn spectrum(&self) -> Spectrum {
+        match self.timbre {
+            Timbre::Piano => Spectrum::piano(),
+            Timbre::Sawtooth => Spectrum::sawtooth(SAWTOOTH_PARTIALS),
+            Timbre::Custom => Spectrum::synth(&self.custom_spectrum),
+        }
+    }
+
+    /// Shows the curve above `root`, played at `fundamental` Hz. Returns whether the timbre was changed.
+    pub fn show(&mut self, ui: &mut Ui, root: Note, fundamental: f32) -> bool {
+        const MAX_OCTAVES: u32 = 3;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            for timbre in Timbre::ALL {
+                changed |= ui
+                    .selectable_value(&mut self.timbre, timbre, timbre.label())
+                    .changed();
+            }
+            ui.separator();
+            ui.add(
+                egui::Slider::new(&mut self.octaves, 1..=MAX_OCTAVES)
+                    .suffix(" oct")
+                    .clamping(egui::SliderClamping::Always),
+            )
+            .on_hover_text("How far above the root the sweep goes");
+        });
+        if self.timbre == Timbre::Custom {
+            changed |= timbre_editor::partial_sliders(ui, &mut self.custom_spectrum);
+        }
+
+        let key = CurveKey {
+            spectrum: self.spectrum(),
+            root,
+            fundamental,
+            octaves: self.octaves,
+        };
+        if self.cache.as_ref().is_none_or(|(cached, _)| *cached != key) {
+            let curve = sweep(&key.spectrum, root, fundamental, self.octaves);
+            self.cache = Some((key, curve));
+        }
+        let (_, curve) = self.cache.as_ref().unwrap();
+        paint_curve(ui, curve, root);
+        changed
+    }
+}
+
+/// Plot of the roughness against the interval in cents, with the 12-TET steps and labelled minima
+fn paint_curve(ui: &mut Ui, curve: &[f32], root: Note) {
+    const WIDTH: f32 = 480.0;
+    const HEIGHT: f32 = 180.0;
+    const AXIS_HEIGHT: f32 = 14.0;
+    const FONT_SIZE: f32 = 10.0;
+    const LINE_WIDTH: f32 = 1.5;
+    const GRID_WIDTH: f32 = 1.0;
+    const GRID_OPACITY: f32 = 0.5;
+    // Leave room above the curve for the labels of the minima
+    const HEADROOM: f32 = 0.85;
+
+    let (rect, response) = ui.allocate_exact_size(vec2(WIDTH, HEIGHT), Sense::hover());
+    let plot_rect = Rect::from_min_max(rect.min, pos2(rect.right(), rect.bottom() - AXIS_HEIGHT));
+    let painter = ui.painter_at(rect);
+    const BACKGROUND_ALPHA: u8 = 40;
+    painter.rect_filled(plot_rect, 0.0, Color32::from_black_alpha(BACKGROUND_ALPHA));
+
+    let max_cents = (curve.len() - 1) as f32;
+    let max_roughness = curve.iter().copied().fold(f32::EPSILON, f32::max);
+    let to_x = |cents: f32| plot_rect.left() + plot_rect.width() * cents / max_cents;
+    let to_y = |roughness: f32| {
+        plot_rect.bottom() - plot_rect.height() * HEADROOM * roughness / max_roughness
+    };
+
+    // Where 12-TET puts its notes, labelled at the octaves
+    for semitones in 0..=(curve.len() as u32 - 1) / CENTS_PER_SEMITONE {
+        let cents = semitones * CENTS_PER_SEMITONE;
+        let x = to_x(cents as f32);
+        painter.vline(
+            x,
+            plot_rect.y_range(),
+            Stroke::new(
+                GRID_WIDTH,
+                theme::TEXT_TERTIARY.gamma_multiply(GRID_OPACITY),
+            ),
+        );
+        if cents % CENTS_PER_OCTAVE == 0 {
+            painter.text(
+                pos2(x, rect.bottom()),
+                if cents == 0 {
+                    Align2::LEFT_BOTTOM
+                } else if cents as f32 == max_cents {
+                    Align2::RIGHT_BOTTOM
+                } else {
+                    Align2::CENTER_BOTTOM
+                },
+                format!("{cents}¢"),
+                FontId::proportional(FONT_SIZE),
+                theme::TEXT_TERTIARY,
+            );
+        }
+    }
+
+    let points = curve
+        .iter()
+        .enumerate()
+        .map(|(cents, &roughness)| pos2(to_x(cents as f32), to_y(roughness)))
+        .collect();
+    painter.add(Shape::line(
+        points,
+        Stroke::new(LINE_WIDTH, ui.visuals().text_color()),
+    ));
+
+    const MINIMUM_RADIUS: f32 = 3.0;
+    const LABEL_GAP: f32 = 4.0;
+    for cents in minima(curve) {
+        let position = pos2(to_x(cents as f32), to_y(curve[cents as usize]));
+        painter.circle_filled(position, MINIMUM_RADIUS, theme::ATTENTION_TEXT);
+        let (ratio, _) = interval::nearest_just_ratio(cents as f32);
+        painter.text(
+            position - vec2(0.0, LABEL_GAP),
+            Align2::CENTER_BOTTOM,
+            ratio.to_string(),
+            FontId::monospace(FONT_SIZE),
+            theme::ATTENTION_TEXT,
+        );
+    }
+
+    if let Some(pointer) = response.hover_pos() {
+        let cents = ((pointer.x - plot_rect.left()) / plot_rect.width() * max_cents).round();
+        let (ratio, error) = interval::nearest_just_ratio(cents.max(0.0));
+        response.on_hover_text(format!(
+            "{cents}¢ above {}, {:+.0}¢ from {ratio}",
+            root.to_str(),
+            -error
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roughness::Inharmonicity;
+
+    fn minima_of(spectrum: &Spectrum, octaves: u32) -> Vec<u32> {
+        let root = Note::C4;
+        minima(&sweep(spectrum, root, root.to_freq_f32(), octaves))
+    }
+
+    #[test]
+    fn test_harmonic_minima_are_at_just_ratios() {
+        let minima = minima_of(&Spectrum::sawtooth(SAWTOOTH_PARTIALS), 2);
+        // Minor third, major third, fourth, fifth, major sixth and octave, to within a few cents
+        for just_cents in [316, 386, 498, 702, 884, 1200] {
+            assert!(
+                minima.iter().any(|&cents| cents.abs_diff(just_cents) <= 3),
+                "no minimum near {just_cents} in {minima:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_inharmonic_minima_are_stretched() {
+        const PARTIALS: usize = 8;
+        let stretched = Spectrum::new(vec![1.0; PARTIALS], Inharmonicity::Coefficient(0.002));
+        let octave = minima_of(&stretched, 2)
+            .into_iter()
+            .filter(|&cents| cents > 1100)
+            .min_by_key(|&cents| cents.abs_diff(CENTS_PER_OCTAVE));
+        assert!(octave.is_some_and(|cents| cents > CENTS_PER_OCTAVE));
+    }
+
+    #[test]
+    fn test_flat_curve_has_no_minima() {
+        assert!(minima(&[0.0; 1201]).is_empty());
+        // A flat bottom is a single minimum
+        let mut curve = [1.0; 101];
+        curve[40..60].fill(0.5);
+        assert_eq!(minima(&curve), [40]);
+    }
+}
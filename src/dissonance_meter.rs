@@ -0,0 +1,317 @@
+use crate::{
+    interval::{ConsonanceModel, DissonanceScale},
+    piano_types::NoteSet,
+    theme,
+    utils::colorgrad_to_egui,
+};
+use colorgrad::Gradient as _;
+use egui::{Align2, Color32, FontId, Mesh, Rect, Sense, Shape, Stroke, Ui, pos2, vec2};
+use shared_types::tuning::TuningTable;
+use std::collections::VecDeque;
+use web_time::{Duration, Instant};
+use wmidi::Note;
+
+/// Dissonance of the `notes` played in `tuning` according to `model`, as the mean normalized dissonance of every
+/// pair of them. Each pair is clamped to 0-1 like the colors of the interval rows, so that a single very rough pair
+/// doesn't outweigh the rest of the chord. Notes that the tuning leaves unmapped are left out. Returns `None` if
+/// fewer than two notes are left, or if the model can't tell a fifth from a tritone, such as the roughness of a
+/// silent spectrum.
+pub fn chord_score(
+    model: &dyn ConsonanceModel,
+    tuning: &TuningTable,
+    notes: &NoteSet,
+) -> Option<f32> {
+    let notes = notes
+        .iter_ones()
+        .map(|note| note as u8)
+        .filter(|&note| tuning.frequency(note).is_some());
+    let num_notes = notes.clone().count();
+    if num_notes < 2 {
+        return None;
+    }
+    let mut sum = 0.0;
+    let mut count = 0;
+    // Every note but the highest is the lower note of some pairs, which share its scale
+    for (index, lower) in notes.clone().enumerate().take(num_notes - 1) {
+        let scale =
+            DissonanceScale::new(model, Note::from_u8_lossy(lower), tuning.frequency(lower)?)?;
+        for upper in notes.clone().skip(index + 1) {
+            let cents = tuning.interval_cents(lower, upper - lower)?;
+            sum += scale.normalized(cents).clamp(0.0, 1.0);
+            count += 1;
+        }
+    }
+    Some(sum / count as f32)
+}
+
+/// Panel showing how dissonant the currently held keys are, with a short history
+pub struct DissonanceMeter {
+    /// Index of the model the meter uses, out of the ones passed to [`Self::show`]
+    pub model: usize,
+    /// Score changes within the history window, oldest first.
+    /// Past scores can't be derived from the current state, so they have to be stored.
+    history: VecDeque<(Instant, f32)>,
+}
+
+pub const METER_HEIGHT: f32 = 24.0;
+const HISTORY_DURATION: Duration = Duration::from_secs(10);
+
+impl DissonanceMeter {
+    pub fn new() -> Self {
+        Self {
+            model: 0,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Shows the score of the `held_notes` played in `tuning` with the chosen one of `models`. Clicking the label picks the next model.
+    /// Returns whether the model was changed.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        tuning: &TuningTable,
+        held_notes: &NoteSet,
+        models: &[Box<dyn ConsonanceModel>],
+    ) -> bool {
+        debug_assert!(self.model < models.len());
+        let score = chord_score(models[self.model].as_ref(), tuning, held_notes);
+        self.record(score.unwrap_or(0.0));
+
+        const LABEL_WIDTH: f32 = 110.0;
+        const SPARKLINE_WIDTH_RATIO: f32 = 0.25;
+        const SPACING: f32 = 6.0;
+        const FONT_SIZE: f32 = 12.0;
+        let (rect, _) =
+            ui.allocate_exact_size(vec2(ui.available_width(), METER_HEIGHT), Sense::empty());
+        let label_rect = Rect::from_min_size(rect.min, vec2(LABEL_WIDTH, rect.height()));
+        let sparkline_width = rect.width() * SPARKLINE_WIDTH_RATIO;
+        let sparkline_rect =
+            Rect::from_min_max(pos2(rect.right() - sparkline_width, rect.top()), rect.max);
+        let bar_rect = Rect::from_min_max(
+            pos2(label_rect.right() + SPACING, rect.top()),
+            pos2(sparkline_rect.left() - SPACING, rect.bottom()),
+        );
+
+        let label = ui.interact(
+            label_rect,
+            ui.id().with("dissonance_meter_model"),
+            Sense::click(),
+        );
+        let model_changed = label.clicked();
+        if model_changed {
+            self.model = (self.model + 1) % models.len();
+        }
+        label.on_hover_text(format!(
+            "Dissonance of the held keys using the {} model (click to change)",
+            models[self.model].name()
+        ));
+        let painter = ui.painter();
+        let text = match score {
+            Some(score) => format!("dissonance {score:.2}"),
+            None => "dissonance -".to_string(),
+        };
+        painter.text(
+            label_rect.left_center(),
+            Align2::LEFT_CENTER,
+            text,
+            FontId::monospace(FONT_SIZE),
+            ui.visuals().text_color(),
+        );
+
+        // Gradient bar, dimmed beyond the current value
+        const GRADIENT_SEGMENTS: usize = 32;
+        let mut mesh = Mesh::default();
+        for segment in 0..GRADIENT_SEGMENTS {
+            let t0 = segment as f32 / GRADIENT_SEGMENTS as f32;
+            let t1 = (segment + 1) as f32 / GRADIENT_SEGMENTS as f32;
+            mesh.add_colored_rect(
+                Rect::from_x_y_ranges(
+                    bar_rect.lerp_inside(vec2(t0, 0.0)).x..=bar_rect.lerp_inside(vec2(t1, 0.0)).x,
+                    bar_rect.y_range(),
+                ),
+                colorgrad_to_egui(&theme::DISSONANCE_GRADIENT.at((t0 + t1) / 2.0)),
+            );
+        }
+        painter.add(Shape::mesh(mesh));
+        const DIM_ALPHA: u8 = 200;
+        let value_x = bar_rect.lerp_inside(vec2(score.unwrap_or(0.0), 0.0)).x;
+        painter.rect_filled(
+            Rect::from_min_max(pos2(value_x, bar_rect.top()), bar_rect.max),
+            0.0,
+            ui.visuals().panel_fill.gamma_multiply_u8(DIM_ALPHA),
+        );
+        const OUTLINE_WIDTH: f32 = 1.0;
+        painter.rect_stroke(
+            bar_rect,
+            0.0,
+            Stroke::new(OUTLINE_WIDTH, theme::outlines()),
+            egui::StrokeKind::Inside,
+        );
+
+        self.paint_sparkline(ui, sparkline_rect);
+        model_changed
+    }
+
+    fn record(&mut self, score: f32) {
+        let now = Instant::now();
+        if self.history.back().is_none_or(|&(_, last)| last != score) {
+            self.history.push_back((now, score));
+        }
+        // Keep the newest entry before the window so the line can start at the window's left edge
+        while self
+            .history
+            .get(1)
+            .is_some_and(|&(time, _)| now.duration_since(time) > HISTORY_DURATION)
+        {
+            self.history.pop_front();
+        }
+    }
+
+    fn paint_sparkline(&self, ui: &Ui, rect: Rect) {
+        let painter = ui.painter();
+        const BACKGROUND_ALPHA: u8 = 40;
+        painter.rect_filled(rect, 0.0, Color32::from_black_alpha(BACKGROUND_ALPHA));
+        let now = Instant::now();
+        let to_x = |time: Instant| {
+            let age = now.duration_since(time).min(HISTORY_DURATION);
+            rect.right() - rect.width() * age.as_secs_f32() / HISTORY_DURATION.as_secs_f32()
+        };
+        let to_y = |score: f32| rect.bottom() - rect.height() * score;
+        // Draw the history as steps, since the score only changes when keys are pressed or released
+        let mut points = Vec::with_capacity(self.history.len() * 2 + 1);
+        for (&(time, score), next) in self
+            .history
+            .iter()
+            .zip(self.history.iter().skip(1).map(Some).chain([None]))
+        {
+            let end = next.map_or(now, |&(next_time, _)| next_time);
+            points.push(pos2(to_x(time), to_y(score)));
+            points.push(pos2(to_x(end), to_y(score)));
+        }
+        if let Some(&(_, score)) = self.history.back() {
+            const LINE_WIDTH: f32 = 1.5;
+            painter.add(Shape::line(
+                points,
+                Stroke::new(
+                    LINE_WIDTH,
+                    colorgrad_to_egui(&theme::DISSONANCE_GRADIENT.at(score)),
+                ),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        interval::{HarmonicEntropy, IntervalTable},
+        roughness::Spectrum,
+    };
+    use shared_types::tuning::Tuning;
+
+    const C4: u8 = 60;
+
+    fn notes(notes: &[u8]) -> NoteSet {
+        let mut set = NoteSet::default();
+        for &note in notes {
+            set.set(usize::from(note), true);
+        }
+        set
+    }
+
+    fn models() -> [Box<dyn ConsonanceModel>; 3] {
+        [
+            Box::new(IntervalTable),
+            Box::new(Spectrum::piano()),
+            Box::new(HarmonicEntropy::default()),
+        ]
+    }
+
+    fn score(model: &dyn ConsonanceModel, held: &[u8]) -> Option<f32> {
+        chord_score(model, &TuningTable::default(), &notes(held))
+    }
+
+    #[test]
+    fn test_no_score_for_fewer_than_two_notes() {
+        for model in models() {
+            assert_eq!(score(model.as_ref(), &[]), None);
+            assert_eq!(score(model.as_ref(), &[C4]), None);
+        }
+    }
+
+    #[test]
+    fn test_scores_are_normalized() {
+        let octave: Vec<u8> = (C4..C4 + 12).collect();
+        for model in models() {
+            let score = score(model.as_ref(), &octave).unwrap();
+            assert!(
+                (0.0..=1.0).contains(&score),
+                "{} scored {score}",
+                model.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_triad_is_less_dissonant_than_cluster() {
+        let major = [C4, C4 + 4, C4 + 7];
+        let cluster = [C4, C4 + 1, C4 + 2];
+        for model in models() {
+            assert!(
+                score(model.as_ref(), &major).unwrap() < score(model.as_ref(), &cluster).unwrap(),
+                "{} should rate a major triad below a cluster",
+                model.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_fifth_is_less_dissonant_than_tritone() {
+        for model in models() {
+            assert!(
+                score(model.as_ref(), &[C4, C4 + 7]).unwrap()
+                    < score(model.as_ref(), &[C4, C4 + 6]).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_octaves_of_the_notes_count() {
+        // A note and its octave are an octave rather than a single key
+        for model in models() {
+            let octave = score(model.as_ref(), &[C4, C4 + 12]).unwrap();
+            assert!(octave < score(model.as_ref(), &[C4, C4 + 6]).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_notes_are_scored_in_the_tuning() {
+        // In 19-EDO the 11th step is close to a just fifth and the 9th to a tritone
+        const EDO_19: u8 = 19;
+        let tuning = Tuning {
+            steps_per_octave: EDO_19,
+            ..Tuning::default()
+        }
+        .frequency_table();
+        for model in models() {
+            let fifth = chord_score(model.as_ref(), &tuning, &notes(&[C4, C4 + 11])).unwrap();
+            let tritone = chord_score(model.as_ref(), &tuning, &notes(&[C4, C4 + 9])).unwrap();
+            assert!(fifth < tritone, "{}", model.name());
+        }
+        // Unmapped notes are left out
+        let mut frequencies = *TuningTable::default().frequencies();
+        frequencies[usize::from(C4 + 7)] = TuningTable::UNMAPPED;
+        let tuning = TuningTable::new(frequencies);
+        assert_eq!(
+            chord_score(&IntervalTable, &tuning, &notes(&[C4, C4 + 7])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_no_score_for_a_silent_spectrum() {
+        let silent = Spectrum::new(vec![0.0; 8], crate::roughness::Inharmonicity::None);
+        assert_eq!(score(&silent, &[C4, C4 + 7]), None);
+    }
+}
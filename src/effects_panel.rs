@@ -0,0 +1,59 @@
+use egui::Ui;
+use shared_types::effects::{EffectParam, EffectSettings, LimiterParam, ReverbParam};
+
+/// Sliders for the reverb and limiter the synth plays through. Returns the parameters that were changed.
+pub fn show(ui: &mut Ui, settings: &mut EffectSettings) -> Vec<EffectParam> {
+    let mut changed = Vec::new();
+    ui.strong("Reverb");
+    for param in ReverbParam::ALL {
+        // The synth plays in mono, where the width can't be heard
+        if param != ReverbParam::Width {
+            slider(ui, settings, EffectParam::Reverb(param), &mut changed);
+        }
+    }
+    ui.separator();
+    ui.strong("Limiter");
+    for param in LimiterParam::ALL {
+        slider(ui, settings, EffectParam::Limiter(param), &mut changed);
+    }
+    ui.separator();
+    if ui
+        .button("Reset")
+        .on_hover_text("The effect settings the synth starts with")
+        .clicked()
+    {
+        let defaults = EffectSettings::default();
+        for (param, value) in defaults.iter() {
+            if settings.get(param) != value {
+                changed.push(param);
+            }
+        }
+        *settings = defaults;
+    }
+    changed
+}
+
+fn slider(
+    ui: &mut Ui,
+    settings: &mut EffectSettings,
+    param: EffectParam,
+    changed: &mut Vec<EffectParam>,
+) {
+    let mut value = settings.get(param);
+    let logarithmic = matches!(
+        param,
+        EffectParam::Limiter(LimiterParam::Attack | LimiterParam::Release)
+    );
+    if ui
+        .add(
+            egui::Slider::new(&mut value, param.range())
+                .text(param.name())
+                .suffix(param.unit())
+                .logarithmic(logarithmic),
+        )
+        .changed()
+    {
+        settings.set(param, value);
+        changed.push(param);
+    }
+}
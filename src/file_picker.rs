@@ -0,0 +1,44 @@
+use crate::utils::FutureData;
+use wasm_bindgen::{JsCast as _, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+/// A text file the user picked in the browser
+#[derive(Debug)]
+pub struct PickedFile {
+    pub name: String,
+    pub contents: String,
+}
+
+/// Files picked in the browser's file dialog, once the user has chosen them
+pub type PickedFiles = FutureData<Result<Vec<PickedFile>, JsValue>>;
+
+/// Open the browser's file dialog for text files matching `accept`, such as ".scl,.kbm".
+/// The result never arrives if the user cancels the dialog.
+pub fn pick_text_files(accept: &str) -> Result<PickedFiles, JsValue> {
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("no document to open a file dialog from"))?;
+    let input: web_sys::HtmlInputElement = document.create_element("input")?.dyn_into()?;
+    input.set_type("file");
+    input.set_accept(accept);
+    input.set_multiple(true);
+    let changed = js_sys::Promise::new(&mut |resolve, _reject| {
+        input.set_onchange(Some(&resolve));
+    });
+    input.click();
+    Ok(FutureData::spawn(async move {
+        JsFuture::from(changed).await?;
+        let Some(files) = input.files() else {
+            return Ok(Vec::new());
+        };
+        let mut picked = Vec::new();
+        for file in (0..files.length()).filter_map(|index| files.get(index)) {
+            let contents = JsFuture::from(file.text()).await?;
+            picked.push(PickedFile {
+                name: file.name(),
+                contents: contents.as_string().unwrap_or_default(),
+            });
+        }
+        Ok(picked)
+    }))
+}
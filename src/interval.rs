@@ -1,12 +1,16 @@
 use num_rational::Rational32;
 use num_traits::ToPrimitive;
+use shared_types::{SynthSpectrum, tuning::TuningTable};
 use std::fmt::{Display, Formatter, Result};
 use std::ops::Div;
+use wmidi::Note;
 
 // Musical constants
 const OCTAVE_RATIO: f32 = 2.0; // The octave ratio - frequency doubles every octave in equal temperament
 const SEMITONES_PER_OCTAVE: f32 = 12.0;
 const SEMITONES_PER_OCTAVE_I8: i8 = 12;
+const SEMITONES_PER_OCTAVE_U8: u8 = 12;
+const CENTS_PER_OCTAVE: f32 = 1200.0;
 
 /// Musical intervals that define the distance between two notes
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,45 +51,52 @@ impl Interval {
         }
     }
 
-    pub fn from_semitone_wrapping(semitone_interval: i8) -> Self {
-        Self::from_semitone_interval(semitone_interval.rem_euclid(12) as u8)
+    /// The interval between two notes, with the lower note as the root.
+    /// Compound intervals are reduced to a single octave, except that whole octaves stay octaves.
+    pub fn between(a: Note, b: Note) -> Self {
+        let distance = u8::from(a).abs_diff(u8::from(b));
+        if distance > 0 && distance % SEMITONES_PER_OCTAVE_U8 == 0 {
+            Self::Octave
+        } else {
+            Self::from_semitone_interval(distance % SEMITONES_PER_OCTAVE_U8)
+        }
     }
 
-    /// Returns the just intonation ratio for this interval
-    pub fn just_ratio(&self) -> Rational32 {
-        match self {
-            Self::Unison => Rational32::new(1, 1),
-            Self::MinorSecond => Rational32::new(16, 15),
-            Self::MajorSecond => Rational32::new(9, 8),
-            Self::MinorThird => Rational32::new(6, 5),
-            Self::MajorThird => Rational32::new(5, 4),
-            Self::PerfectFourth => Rational32::new(4, 3),
-            Self::Tritone => Rational32::new(45, 32),
-            Self::PerfectFifth => Rational32::new(3, 2),
-            Self::MinorSixth => Rational32::new(8, 5),
-            Self::MajorSixth => Rational32::new(5, 3),
-            Self::MinorSeventh => Rational32::new(9, 5),
-            Self::MajorSeventh => Rational32::new(15, 8),
-            Self::Octave => Rational32::new(2, 1),
+    /// The interval closest to `cents` in 12-TET, with compound intervals reduced like in [`Interval::between`]
+    pub fn from_cents(cents: f32) -> Self {
+        const CENTS_PER_SEMITONE: f32 = CENTS_PER_OCTAVE / SEMITONES_PER_OCTAVE;
+        let semitones = (cents.abs() / CENTS_PER_SEMITONE).round() as u8;
+        if semitones > 0 && semitones % SEMITONES_PER_OCTAVE_U8 == 0 {
+            Self::Octave
+        } else {
+            Self::from_semitone_interval(semitones % SEMITONES_PER_OCTAVE_U8)
         }
     }
 
+    /// Returns the just intonation ratio `ratios` takes this interval to be
+    pub fn just_ratio(&self, ratios: &RatioTable) -> Rational32 {
+        ratios.ratio(*self)
+    }
+
     /// Returns the equal temperament ratio for this interval
     #[expect(dead_code)]
     pub fn tempered_ratio(&self) -> f32 {
         OCTAVE_RATIO.powf(self.semitones() as f32 / SEMITONES_PER_OCTAVE)
     }
 
-    /// Returns the difference in cents between just intonation and equal temperament
-    /// Positive values mean just intonation is sharper than equal temperament
-    pub fn tempered_just_error_cents(&self) -> f32 {
-        const CENTS_PER_OCTAVE: f32 = 1200.0;
-        const CENTS_PER_SEMITONE: f32 = 100.0;
-
+    /// Returns the difference in cents between just intonation and the interval above `root` as played in `tuning`
+    /// Positive values mean just intonation is sharper than the tuning.
+    /// `None` if the tuning doesn't map one of the notes.
+    pub fn tempered_just_error_cents(
+        &self,
+        ratios: &RatioTable,
+        tuning: &TuningTable,
+        root: Note,
+    ) -> Option<f32> {
         let just_cents =
-            CENTS_PER_OCTAVE * (self.just_ratio().to_f32().unwrap().ln() / OCTAVE_RATIO.ln());
-        let tempered_cents = CENTS_PER_SEMITONE * self.semitones() as f32;
-        just_cents - tempered_cents
+            CENTS_PER_OCTAVE * (self.just_ratio(ratios).to_f32().unwrap().ln() / OCTAVE_RATIO.ln());
+        let tempered_cents = tuning.interval_cents(u8::from(root), self.semitones())?;
+        Some(just_cents - tempered_cents)
     }
 
     /// Get the number of semitones in this interval
@@ -130,6 +141,430 @@ impl Interval {
     }
 }
 
+/// Which just ratio each interval of 12-TET is taken to be, like 9/5 or 7/4 for the minor seventh
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RatioTable {
+    /// Indexed by the semitones of the interval, from the unison up to the octave
+    ratios: [Rational32; 13],
+}
+
+impl Default for RatioTable {
+    /// The 5-limit ratios of just intonation
+    fn default() -> Self {
+        Self {
+            ratios: std::array::from_fn(|semitones| {
+                let (numerator, denominator) =
+                    Self::alternatives(Interval::from_semitone_interval(semitones as u8))[0];
+                Rational32::new(numerator, denominator)
+            }),
+        }
+    }
+}
+
+impl RatioTable {
+    /// The just ratios an interval is commonly heard as, starting with the 5-limit ratio of just intonation
+    pub fn alternatives(interval: Interval) -> &'static [(i32, i32)] {
+        match interval {
+            Interval::Unison => &[(1, 1)],
+            Interval::MinorSecond => &[(16, 15), (15, 14), (17, 16), (25, 24), (256, 243)],
+            Interval::MajorSecond => &[(9, 8), (10, 9), (8, 7)],
+            Interval::MinorThird => &[(6, 5), (7, 6), (19, 16), (32, 27)],
+            Interval::MajorThird => &[(5, 4), (9, 7), (81, 64)],
+            Interval::PerfectFourth => &[(4, 3), (21, 16), (27, 20)],
+            Interval::Tritone => &[(45, 32), (7, 5), (10, 7), (11, 8), (64, 45), (729, 512)],
+            Interval::PerfectFifth => &[(3, 2)],
+            Interval::MinorSixth => &[(8, 5), (14, 9), (11, 7), (128, 81)],
+            Interval::MajorSixth => &[(5, 3), (12, 7), (27, 16)],
+            Interval::MinorSeventh => &[(9, 5), (7, 4), (16, 9)],
+            Interval::MajorSeventh => &[(15, 8), (13, 7), (28, 15), (243, 128)],
+            Interval::Octave => &[(2, 1)],
+        }
+    }
+
+    pub fn ratio(&self, interval: Interval) -> Rational32 {
+        self.ratios[usize::from(interval.semitones())]
+    }
+
+    pub fn set_ratio(&mut self, interval: Interval, ratio: Rational32) {
+        self.ratios[usize::from(interval.semitones())] = ratio;
+    }
+
+    pub fn storage_string(&self) -> String {
+        self.ratios
+            .iter()
+            .map(Rational32::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn from_storage_string(text: &str) -> Option<Self> {
+        let ratios: Vec<Rational32> = text
+            .split(',')
+            .map(|ratio| ratio.parse().ok())
+            .collect::<Option<_>>()?;
+        Some(Self {
+            ratios: ratios.try_into().ok()?,
+        })
+    }
+}
+
+/// Tenney height of a ratio n/d in lowest terms, log2(n·d). Simpler ratios have lower heights.
+pub fn tenney_height(ratio: Rational32) -> f32 {
+    (*ratio.numer() as f32 * *ratio.denom() as f32).log2()
+}
+
+/// The largest odd factor of the numerator or denominator, so 8/5 is in the 5 odd limit and 16/15 in the 15 odd limit
+pub fn odd_limit(ratio: Rational32) -> i32 {
+    let odd = |n: i32| n >> n.trailing_zeros();
+    odd(*ratio.numer()).max(odd(*ratio.denom()))
+}
+
+/// The largest prime factor of the numerator or denominator, like 7 for 7/4. 1 for the unison.
+pub fn prime_limit(ratio: Rational32) -> i32 {
+    let largest_prime_factor = |mut n: i32| {
+        let mut largest = 1;
+        let mut factor = 2;
+        while n > 1 {
+            if n % factor == 0 {
+                largest = factor;
+                n /= factor;
+            } else {
+                factor += 1;
+            }
+        }
+        largest
+    };
+    largest_prime_factor(*ratio.numer()).max(largest_prime_factor(*ratio.denom()))
+}
+
+/// Just ratios within an octave that steps of an equal division of the octave are named after.
+/// The ratios of the 11 odd limit, along with the 5-limit ratios of [`RatioTable::default`].
+const JUST_RATIOS: [(i32, i32); 35] = [
+    (1, 1),
+    (33, 32),
+    (25, 24),
+    (16, 15),
+    (12, 11),
+    (11, 10),
+    (10, 9),
+    (9, 8),
+    (8, 7),
+    (7, 6),
+    (6, 5),
+    (11, 9),
+    (5, 4),
+    (14, 11),
+    (9, 7),
+    (4, 3),
+    (11, 8),
+    (7, 5),
+    (45, 32),
+    (10, 7),
+    (16, 11),
+    (3, 2),
+    (14, 9),
+    (11, 7),
+    (8, 5),
+    (18, 11),
+    (5, 3),
+    (12, 7),
+    (7, 4),
+    (16, 9),
+    (9, 5),
+    (20, 11),
+    (11, 6),
+    (15, 8),
+    (2, 1),
+];
+
+fn ratio_cents(ratio: Rational32) -> f32 {
+    CENTS_PER_OCTAVE * ratio.to_f32().unwrap().log2()
+}
+
+/// The just ratio closest to an interval of `cents`, and the difference in cents between the two.
+/// Like [`Interval::tempered_just_error_cents`], positive values mean the just ratio is sharper.
+/// Intervals larger than an octave are matched within their octave, so a twelfth of 1902 cents gives 3/1.
+pub fn nearest_just_ratio(cents: f32) -> (Rational32, f32) {
+    debug_assert!(cents >= 0.0, "Intervals are measured upwards from the root");
+    let octaves = (cents / CENTS_PER_OCTAVE).floor();
+    let within_octave = cents - octaves * CENTS_PER_OCTAVE;
+    let (ratio, error) = JUST_RATIOS
+        .iter()
+        .map(|&(numerator, denominator)| {
+            let ratio = Rational32::new(numerator, denominator);
+            (ratio, ratio_cents(ratio) - within_octave)
+        })
+        .min_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+        .unwrap();
+    (ratio * Rational32::from_integer(1 << octaves as i32), error)
+}
+
+/// Beats per second between two notes played with `spectrum` in `tuning`, for an interval meant to be the just `ratio`.
+/// The beating is heard between the lowest partials that coincide in just intonation, such as the 3rd partial
+/// of the root and the 2nd partial of a fifth, and includes the stretch of the spectrum for both notes.
+/// The ratio can be given within a single octave, it is moved to the octave the notes are in.
+/// `None` if the tuning doesn't map one of the notes, if the spectrum isn't harmonic so no partials coincide,
+/// or if the partials are silent or too high to hear the beats.
+pub fn beat_rate(
+    tuning: &TuningTable,
+    spectrum: &SynthSpectrum,
+    a: Note,
+    b: Note,
+    ratio: Rational32,
+) -> Option<f32> {
+    // Beats between higher partials are too faint to count
+    const MAX_BEATING_PARTIAL: i32 = 8;
+    if spectrum.ratios != SynthSpectrum::HARMONIC_RATIOS {
+        return None;
+    }
+    let (low, high) = (a.min(b), a.max(b));
+    let low_frequency = tuning.frequency(u8::from(low))?;
+    let high_frequency = tuning.frequency(u8::from(high))?;
+    let octaves = ((high_frequency / low_frequency).log2() - ratio.to_f32()?.log2()).round() as i32;
+    let octave_shift = Rational32::from_integer(1 << octaves.unsigned_abs());
+    let ratio = if octaves >= 0 {
+        ratio * octave_shift
+    } else {
+        ratio / octave_shift
+    };
+    // The partial numbers of the low and high note that coincide
+    let (low_partial, high_partial) = (*ratio.numer(), *ratio.denom());
+    if low_partial > MAX_BEATING_PARTIAL || high_partial > MAX_BEATING_PARTIAL {
+        return None;
+    }
+    // Harmonic ratios make the partial at index n - 1 the nth harmonic
+    let index = |partial: i32| partial as usize - 1;
+    if spectrum.amplitudes[index(low_partial)] <= 0.0
+        || spectrum.amplitudes[index(high_partial)] <= 0.0
+    {
+        return None;
+    }
+    let partial_frequency = |note: Note, frequency: f32, partial: i32| {
+        spectrum.partial_frequency(
+            &spectrum.inharmonicity(u8::from(note)),
+            frequency,
+            index(partial),
+        )
+    };
+    Some(
+        (partial_frequency(low, low_frequency, low_partial)
+            - partial_frequency(high, high_frequency, high_partial))
+        .abs(),
+    )
+}
+
+/// A way of rating how dissonant an interval sounds
+pub trait ConsonanceModel {
+    /// Short name to choose the model by
+    fn name(&self) -> &'static str;
+
+    /// Dissonance of the interval of `cents` above `root`, with `root` played at `fundamental` Hz.
+    /// Every model has its own scale, use [`DissonanceScale`] to compare them.
+    fn dissonance(&self, root: Note, fundamental: f32, cents: f32) -> f32;
+}
+
+/// The dissonance of a [`ConsonanceModel`] for the intervals above one root, scaled so that a perfect fifth
+/// is 0 and a tritone is 1. The fifth and tritone are rated once, so build one per root and reuse it.
+#[derive(Clone, Copy)]
+pub struct DissonanceScale<'a> {
+    model: &'a dyn ConsonanceModel,
+    root: Note,
+    fundamental: f32,
+    fifth: f32,
+    /// Dissonance of the tritone minus that of the fifth
+    range: f32,
+}
+
+impl<'a> DissonanceScale<'a> {
+    /// Scale for the intervals above `root` played at `fundamental` Hz.
+    /// `None` if the model rates the fifth and tritone the same, such as the roughness of a silent spectrum.
+    pub fn new(model: &'a dyn ConsonanceModel, root: Note, fundamental: f32) -> Option<Self> {
+        const FIFTH_CENTS: f32 = 700.0;
+        const TRITONE_CENTS: f32 = 600.0;
+        let fifth = model.dissonance(root, fundamental, FIFTH_CENTS);
+        let range = model.dissonance(root, fundamental, TRITONE_CENTS) - fifth;
+        range.is_normal().then_some(Self {
+            model,
+            root,
+            fundamental,
+            fifth,
+            range,
+        })
+    }
+
+    /// Normalized dissonance of the interval of `cents` above the root
+    pub fn normalized(&self, cents: f32) -> f32 {
+        (self.model.dissonance(self.root, self.fundamental, cents) - self.fifth) / self.range
+    }
+}
+
+/// The hand-made table of [`Interval::dissonance`], for the nearest interval of 12-TET
+#[derive(Debug, Clone, Copy)]
+pub struct IntervalTable;
+
+impl ConsonanceModel for IntervalTable {
+    fn name(&self) -> &'static str {
+        "interval table"
+    }
+
+    fn dissonance(&self, _root: Note, _fundamental: f32, cents: f32) -> f32 {
+        Interval::from_cents(cents).dissonance()
+    }
+}
+
+/// Which rationals harmonic entropy considers, and how much each of them counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RationalSeries {
+    /// Ratios with a denominator of at most the limit, each counting as much as the range of intervals
+    /// between its mediants with the neighbouring ratios
+    Farey(i32),
+    /// Ratios whose numerator times denominator is at most the limit, each counting 1/sqrt(n*d)
+    Tenney(i32),
+}
+
+/// Erlich's harmonic entropy: how uncertain it is which simple ratio a listener hears an interval as.
+/// The interval is blurred by a Gaussian of `spread` cents, and the entropy is that of the probabilities
+/// of the nearby ratios. Intervals near simple ratios have a low entropy.
+#[derive(Debug, Clone)]
+pub struct HarmonicEntropy {
+    series: RationalSeries,
+    spread_cents: f32,
+    /// Size in cents of each ratio and how much it counts, sorted by size
+    ratios: Vec<(f32, f32)>,
+}
+
+impl Default for HarmonicEntropy {
+    /// Tenney-weighted ratios
+    fn default() -> Self {
+        const TENNEY_LIMIT: i32 = 10_000;
+        Self::new(
+            RationalSeries::Tenney(TENNEY_LIMIT),
+            Self::DEFAULT_SPREAD_CENTS,
+        )
+    }
+}
+
+impl HarmonicEntropy {
+    /// Erlich's usual spread of 1% of the frequency
+    pub const DEFAULT_SPREAD_CENTS: f32 = 17.0;
+    /// Widest interval with its own ratios, wider intervals are moved down by octaves
+    const MAX_CENTS: f32 = 2.0 * CENTS_PER_OCTAVE;
+    /// Ratios further away than this many spreads are too unlikely to matter
+    const WINDOW_SPREADS: f32 = 4.0;
+
+    pub fn new(series: RationalSeries, spread_cents: f32) -> Self {
+        fn gcd(a: i32, b: i32) -> i32 {
+            if b == 0 { a } else { gcd(b, a % b) }
+        }
+        let window = Self::WINDOW_SPREADS * spread_cents;
+        // Include the ratios a window beyond unison and the widest interval, so both ends see all their neighbours
+        let min_ratio = 2f32.powf(-window / CENTS_PER_OCTAVE);
+        let max_ratio = 2f32.powf((Self::MAX_CENTS + window) / CENTS_PER_OCTAVE);
+        let max_denominator = match series {
+            RationalSeries::Farey(limit) => limit,
+            RationalSeries::Tenney(limit) => (limit as f32 / min_ratio).sqrt() as i32,
+        };
+        let fits = |numerator: i32, denominator: i32| match series {
+            RationalSeries::Farey(_) => true,
+            RationalSeries::Tenney(limit) => numerator * denominator <= limit,
+        };
+        let mut ratios: Vec<(i32, i32)> = (1..=max_denominator)
+            .flat_map(|denominator| {
+                let lowest = (denominator as f32 * min_ratio).ceil() as i32;
+                let highest = (denominator as f32 * max_ratio).floor() as i32;
+                (lowest..=highest).map(move |numerator| (numerator, denominator))
+            })
+            .filter(|&(numerator, denominator)| {
+                gcd(numerator, denominator) == 1 && fits(numerator, denominator)
+            })
+            .collect();
+        let cents = |numerator: i32, denominator: i32| {
+            CENTS_PER_OCTAVE * (numerator as f32 / denominator as f32).log2()
+        };
+        ratios.sort_by(|&(a, b), &(c, d)| cents(a, b).total_cmp(&cents(c, d)));
+        let ratios = match series {
+            RationalSeries::Farey(_) => {
+                // The mediants between neighbouring ratios bound the intervals heard as each ratio
+                let mediants: Vec<f32> = ratios
+                    .windows(2)
+                    .map(|pair| cents(pair[0].0 + pair[1].0, pair[0].1 + pair[1].1))
+                    .collect();
+                ratios
+                    .iter()
+                    .enumerate()
+                    .map(|(index, &(numerator, denominator))| {
+                        let own = cents(numerator, denominator);
+                        let below = index.checked_sub(1).map_or(own, |below| mediants[below]);
+                        let above = mediants.get(index).copied().unwrap_or(own);
+                        (own, above - below)
+                    })
+                    .collect()
+            }
+            RationalSeries::Tenney(_) => ratios
+                .into_iter()
+                .map(|(numerator, denominator)| {
+                    (
+                        cents(numerator, denominator),
+                        1.0 / ((numerator * denominator) as f32).sqrt(),
+                    )
+                })
+                .collect(),
+        };
+        Self {
+            series,
+            spread_cents,
+            ratios,
+        }
+    }
+
+    /// Harmonic entropy in nats of an interval of `cents`
+    pub fn entropy(&self, cents: f32) -> f32 {
+        let mut cents = cents.abs();
+        while cents > Self::MAX_CENTS {
+            cents -= CENTS_PER_OCTAVE;
+        }
+        let window = Self::WINDOW_SPREADS * self.spread_cents;
+        let start = self
+            .ratios
+            .partition_point(|&(ratio_cents, _)| ratio_cents < cents - window);
+        let end = self
+            .ratios
+            .partition_point(|&(ratio_cents, _)| ratio_cents <= cents + window);
+        // With the probabilities l / total of the likelihoods l, the entropy -Σ p ln p is
+        // ln(total) - Σ l ln l / total, which one pass over the ratios can sum up
+        let (total, likelihood_logs) = self.ratios[start..end]
+            .iter()
+            .map(|&(ratio_cents, weight)| {
+                weight * (-(cents - ratio_cents).powi(2) / (2.0 * self.spread_cents.powi(2))).exp()
+            })
+            .filter(|&likelihood| likelihood > 0.0)
+            .fold((0.0, 0.0), |(total, logs), likelihood: f32| {
+                (
+                    total + likelihood,
+                    likelihood.mul_add(likelihood.ln(), logs),
+                )
+            });
+        // Without any ratio nearby there is no uncertainty about one
+        if total == 0.0 {
+            return 0.0;
+        }
+        total.ln() - likelihood_logs / total
+    }
+}
+
+impl ConsonanceModel for HarmonicEntropy {
+    fn name(&self) -> &'static str {
+        match self.series {
+            RationalSeries::Farey(_) => "harmonic entropy (Farey)",
+            RationalSeries::Tenney(_) => "harmonic entropy (Tenney)",
+        }
+    }
+
+    fn dissonance(&self, _root: Note, _fundamental: f32, cents: f32) -> f32 {
+        self.entropy(cents)
+    }
+}
+
 impl Div for Interval {
     type Output = Self;
 
@@ -173,6 +608,28 @@ impl Display for Interval {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_between_notes() {
+        assert_eq!(Interval::between(Note::C4, Note::C4), Interval::Unison);
+        assert_eq!(
+            Interval::between(Note::C4, Note::G4),
+            Interval::PerfectFifth
+        );
+        // The lower note is the root regardless of argument order
+        assert_eq!(
+            Interval::between(Note::G4, Note::C4),
+            Interval::PerfectFifth
+        );
+        assert_eq!(Interval::between(Note::C4, Note::C5), Interval::Octave);
+        assert_eq!(Interval::between(Note::C2, Note::C5), Interval::Octave);
+        // Compound intervals are reduced to a single octave
+        assert_eq!(Interval::between(Note::C4, Note::E5), Interval::MajorThird);
+        assert_eq!(
+            Interval::between(Note::B5, Note::C3),
+            Interval::MajorSeventh
+        );
+    }
+
     #[test]
     fn test_just_tempered_error_cents() {
         // Test cases for different intervals
@@ -203,78 +660,471 @@ mod tests {
 
         // Test perfect intervals
         assert_approx_eq(
-            Interval::Unison.tempered_just_error_cents(),
+            Interval::Unison
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             UNISON_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::Octave.tempered_just_error_cents(),
+            Interval::Octave
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             OCTAVE_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::PerfectFifth.tempered_just_error_cents(),
+            Interval::PerfectFifth
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             PERFECT_FIFTH_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::PerfectFourth.tempered_just_error_cents(),
+            Interval::PerfectFourth
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             PERFECT_FOURTH_CENTS_ERROR,
             TOLERANCE,
         );
 
         // Test major intervals
         assert_approx_eq(
-            Interval::MajorSecond.tempered_just_error_cents(),
+            Interval::MajorSecond
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             MAJOR_SECOND_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::MajorThird.tempered_just_error_cents(),
+            Interval::MajorThird
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             MAJOR_THIRD_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::MajorSixth.tempered_just_error_cents(),
+            Interval::MajorSixth
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             MAJOR_SIXTH_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::MajorSeventh.tempered_just_error_cents(),
+            Interval::MajorSeventh
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             MAJOR_SEVENTH_CENTS_ERROR,
             TOLERANCE,
         );
 
         // Test minor intervals
         assert_approx_eq(
-            Interval::MinorSecond.tempered_just_error_cents(),
+            Interval::MinorSecond
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             MINOR_SECOND_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::MinorThird.tempered_just_error_cents(),
+            Interval::MinorThird
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             MINOR_THIRD_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::MinorSixth.tempered_just_error_cents(),
+            Interval::MinorSixth
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             MINOR_SIXTH_CENTS_ERROR,
             TOLERANCE,
         );
         assert_approx_eq(
-            Interval::MinorSeventh.tempered_just_error_cents(),
+            Interval::MinorSeventh
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             MINOR_SEVENTH_CENTS_ERROR,
             TOLERANCE,
         );
 
         // Test tritone
         assert_approx_eq(
-            Interval::Tritone.tempered_just_error_cents(),
+            Interval::Tritone
+                .tempered_just_error_cents(
+                    &RatioTable::default(),
+                    &TuningTable::default(),
+                    Note::C4,
+                )
+                .unwrap(),
             TRITONE_CENTS_ERROR,
             TOLERANCE,
         );
     }
 
+    #[test]
+    fn test_nearest_just_ratio() {
+        const TOLERANCE: f32 = 0.01;
+        let (ratio, error) = nearest_just_ratio(0.0);
+        assert_eq!(ratio, Rational32::new(1, 1));
+        assert_approx_eq(error, 0.0, TOLERANCE);
+
+        // The 12-TET fifth is two cents flat of 3/2
+        let (ratio, error) = nearest_just_ratio(700.0);
+        assert_eq!(ratio, Rational32::new(3, 2));
+        assert_approx_eq(error, 1.955, TOLERANCE);
+
+        // 19 steps have a major third 7 cents flat, and 31 steps a pure 7/4
+        let (ratio, error) = nearest_just_ratio(6.0 * 1200.0 / 19.0);
+        assert_eq!(ratio, Rational32::new(5, 4));
+        assert_approx_eq(error, 7.37, TOLERANCE);
+        let (ratio, error) = nearest_just_ratio(25.0 * 1200.0 / 31.0);
+        assert_eq!(ratio, Rational32::new(7, 4));
+        assert!(error.abs() < 1.2);
+
+        // Quarter tones are 11-limit neutral intervals
+        let (ratio, _) = nearest_just_ratio(350.0);
+        assert_eq!(ratio, Rational32::new(11, 9));
+
+        // Compound intervals keep their octaves
+        let (ratio, error) = nearest_just_ratio(1900.0);
+        assert_eq!(ratio, Rational32::new(3, 1));
+        assert_approx_eq(error, 1.955, TOLERANCE);
+        let (ratio, _) = nearest_just_ratio(2400.0);
+        assert_eq!(ratio, Rational32::new(4, 1));
+    }
+
+    #[test]
+    fn test_ratio_table() {
+        let mut ratios = RatioTable::default();
+        assert_eq!(
+            Interval::MinorSeventh.just_ratio(&ratios),
+            Rational32::new(9, 5)
+        );
+        assert_eq!(
+            Interval::Tritone.just_ratio(&ratios),
+            Rational32::new(45, 32)
+        );
+        // Every alternative is close to the interval in 12-TET
+        for semitones in 0..=12 {
+            let interval = Interval::from_semitone_interval(semitones);
+            for &(numerator, denominator) in RatioTable::alternatives(interval) {
+                let cents = ratio_cents(Rational32::new(numerator, denominator));
+                assert!(
+                    (cents - f32::from(semitones) * 100.0).abs() < 50.0,
+                    "{numerator}/{denominator} as {interval}"
+                );
+            }
+        }
+
+        // The harmonic seventh is a third of a semitone flat of 12-TET
+        ratios.set_ratio(Interval::MinorSeventh, Rational32::new(7, 4));
+        assert_approx_eq(
+            Interval::MinorSeventh
+                .tempered_just_error_cents(&ratios, &TuningTable::default(), Note::C4)
+                .unwrap(),
+            -31.17,
+            0.01,
+        );
+
+        assert_eq!(
+            RatioTable::from_storage_string(&ratios.storage_string()),
+            Some(ratios)
+        );
+        assert_eq!(RatioTable::from_storage_string("1/1,9/8"), None);
+        assert_eq!(RatioTable::from_storage_string("not a table"), None);
+    }
+
+    #[test]
+    fn test_ratio_complexity() {
+        const TOLERANCE: f32 = 0.001;
+        // (ratio, Tenney height, odd limit, prime limit)
+        let cases = [
+            ((1, 1), 0.0, 1, 1),
+            ((2, 1), 1.0, 1, 2),
+            ((3, 2), 6f32.log2(), 3, 3),
+            ((5, 4), 20f32.log2(), 5, 5),
+            ((9, 8), 72f32.log2(), 9, 3),
+            ((16, 15), 240f32.log2(), 15, 5),
+            ((7, 4), 28f32.log2(), 7, 7),
+            ((45, 32), 1440f32.log2(), 45, 5),
+            ((11, 8), 88f32.log2(), 11, 11),
+        ];
+        for ((numerator, denominator), height, odd, prime) in cases {
+            let ratio = Rational32::new(numerator, denominator);
+            assert_approx_eq(tenney_height(ratio), height, TOLERANCE);
+            assert_eq!(odd_limit(ratio), odd, "{ratio}");
+            assert_eq!(prime_limit(ratio), prime, "{ratio}");
+        }
+    }
+
+    #[test]
+    fn test_from_cents() {
+        assert_eq!(Interval::from_cents(0.0), Interval::Unison);
+        assert_eq!(Interval::from_cents(702.0), Interval::PerfectFifth);
+        assert_eq!(Interval::from_cents(386.3), Interval::MajorThird);
+        assert_eq!(Interval::from_cents(1200.0), Interval::Octave);
+        assert_eq!(Interval::from_cents(1902.0), Interval::PerfectFifth);
+        assert_eq!(Interval::from_cents(2400.0), Interval::Octave);
+    }
+
+    #[test]
+    fn test_interval_table_model() {
+        let model = IntervalTable;
+        let scale = DissonanceScale::new(&model, Note::C4, 261.63).unwrap();
+        let normalized = |cents| scale.normalized(cents);
+        assert_approx_eq(normalized(700.0), 0.0, 1e-6);
+        assert_approx_eq(normalized(600.0), 1.0, 1e-6);
+        // Just intervals are rated like their tempered neighbours
+        assert_approx_eq(
+            model.dissonance(Note::C4, 261.63, 386.3),
+            Interval::MajorThird.dissonance(),
+            1e-6,
+        );
+    }
+
+    #[test]
+    fn test_harmonic_entropy() {
+        for model in [
+            HarmonicEntropy::default(),
+            HarmonicEntropy::new(
+                RationalSeries::Farey(80),
+                HarmonicEntropy::DEFAULT_SPREAD_CENTS,
+            ),
+        ] {
+            let entropy = |cents| model.entropy(cents);
+            // Simple ratios are local minima
+            for just_cents in [0.0, 701.955, 1200.0, 1901.955] {
+                for offset in [-40.0, 40.0] {
+                    assert!(
+                        entropy(just_cents) < entropy(just_cents + offset),
+                        "{just_cents} vs {offset} away"
+                    );
+                }
+            }
+            // Consonances are more certain than dissonances
+            assert!(entropy(700.0) < entropy(400.0));
+            assert!(entropy(400.0) < entropy(600.0));
+            assert!(entropy(500.0) < entropy(100.0));
+            // Wide intervals are rated like the interval two octaves down
+            assert_approx_eq(
+                entropy(3600.0 + 700.0),
+                entropy(2400.0 + 700.0 - 1200.0),
+                1e-4,
+            );
+        }
+    }
+
+    #[test]
+    fn test_beat_rate() {
+        use shared_types::{
+            Instrument,
+            tuning::{Temperament, Tuning},
+        };
+        const TOLERANCE: f32 = 0.01;
+        let tempered = TuningTable::default();
+        let just = Tuning {
+            temperament: Temperament::Just,
+            ..Default::default()
+        }
+        .frequency_table();
+        let fifth = Rational32::new(3, 2);
+        let major_third = Rational32::new(5, 4);
+        let piano = SynthSpectrum::default();
+        let rate = |tuning: &TuningTable, root: Note, upper: Note, ratio| {
+            beat_rate(tuning, &piano, root, upper, ratio).unwrap()
+        };
+        // Tuners count about one beat per second on a tempered middle C fifth, and about ten on the third
+        assert_approx_eq(rate(&tempered, Note::C4, Note::G4, fifth), 1.26, TOLERANCE);
+        assert_approx_eq(
+            rate(&tempered, Note::C4, Note::E4, major_third),
+            9.28,
+            TOLERANCE,
+        );
+        // Beats get twice as fast an octave higher
+        let ratio = rate(&tempered, Note::C5, Note::E5, major_third)
+            / rate(&tempered, Note::C4, Note::E4, major_third);
+        assert_approx_eq(ratio, 2.0, 0.05);
+        // A twelfth beats between the 3rd partial of the root and the fundamental of the upper note
+        assert!(beat_rate(&tempered, &piano, Note::C4, Note::G5, fifth).is_some());
+        // Just intervals only beat because of the inharmonicity of the strings
+        assert!(
+            rate(&just, Note::C4, Note::G4, fifth) < rate(&tempered, Note::C4, Note::G4, fifth)
+        );
+        assert!(
+            rate(&just, Note::C4, Note::E4, major_third)
+                < rate(&tempered, Note::C4, Note::E4, major_third) / 5.0
+        );
+        // Nobody listens to the 45th partial
+        assert_eq!(
+            beat_rate(
+                &tempered,
+                &piano,
+                Note::C4,
+                Note::Gb4,
+                Rational32::new(45, 32)
+            ),
+            None
+        );
+        // The partials of an unstretched spectrum only beat when the tuning is off just
+        let organ = Instrument::Organ.spectrum();
+        assert!(beat_rate(&just, &organ, Note::C4, Note::G4, fifth).unwrap() < TOLERANCE);
+        // A sine has no 3rd partial to beat against the 2nd partial of the fifth
+        let sine = Instrument::Sine.spectrum();
+        assert_eq!(beat_rate(&tempered, &sine, Note::C4, Note::G4, fifth), None);
+        // Nor does a bell have the harmonics whose beats tuners count
+        let bell = Instrument::Bell.spectrum();
+        assert_eq!(beat_rate(&tempered, &bell, Note::C4, Note::G4, fifth), None);
+    }
+
+    #[test]
+    fn test_just_error_cents_for_active_tuning() {
+        use shared_types::tuning::{Temperament, Tuning};
+
+        const TOLERANCE: f32 = 0.01;
+        let just_on_c = Tuning {
+            temperament: Temperament::Just,
+            ..Tuning::default()
+        }
+        .frequency_table();
+        // Intervals from the tonic of a just tuning are pure
+        for interval in [
+            Interval::MajorThird,
+            Interval::PerfectFifth,
+            Interval::MinorSeventh,
+        ] {
+            assert_approx_eq(
+                interval
+                    .tempered_just_error_cents(&RatioTable::default(), &just_on_c, Note::C4)
+                    .unwrap(),
+                0.0,
+                TOLERANCE,
+            );
+        }
+        // but not from other notes, D to A is a wolf fifth a syntonic comma narrow
+        const SYNTONIC_COMMA_CENTS: f32 = 21.51;
+        assert_approx_eq(
+            Interval::PerfectFifth
+                .tempered_just_error_cents(&RatioTable::default(), &just_on_c, Note::D4)
+                .unwrap(),
+            SYNTONIC_COMMA_CENTS,
+            TOLERANCE,
+        );
+
+        // Pythagorean fifths are pure but its major thirds are a comma wide
+        let pythagorean = Tuning {
+            temperament: Temperament::Pythagorean,
+            ..Tuning::default()
+        }
+        .frequency_table();
+        assert_approx_eq(
+            Interval::PerfectFifth
+                .tempered_just_error_cents(&RatioTable::default(), &pythagorean, Note::F3)
+                .unwrap(),
+            0.0,
+            TOLERANCE,
+        );
+        assert_approx_eq(
+            Interval::MajorThird
+                .tempered_just_error_cents(&RatioTable::default(), &pythagorean, Note::C4)
+                .unwrap(),
+            -SYNTONIC_COMMA_CENTS,
+            TOLERANCE,
+        );
+
+        // Only the pitch class of the root matters, and octaves are always pure
+        let vallotti = Tuning {
+            temperament: Temperament::Vallotti,
+            ..Tuning::default()
+        }
+        .frequency_table();
+        assert_approx_eq(
+            Interval::MajorThird
+                .tempered_just_error_cents(&RatioTable::default(), &vallotti, Note::E2)
+                .unwrap(),
+            Interval::MajorThird
+                .tempered_just_error_cents(&RatioTable::default(), &vallotti, Note::E5)
+                .unwrap(),
+            TOLERANCE,
+        );
+        assert_approx_eq(
+            Interval::Octave
+                .tempered_just_error_cents(&RatioTable::default(), &vallotti, Note::Ab3)
+                .unwrap(),
+            0.0,
+            TOLERANCE,
+        );
+
+        // Intervals to notes that the tuning doesn't map have no error
+        let mut frequencies = *TuningTable::default().frequencies();
+        frequencies[usize::from(u8::from(Note::E4))] = TuningTable::UNMAPPED;
+        let unmapped_e = TuningTable::new(frequencies);
+        assert_eq!(
+            Interval::MajorThird.tempered_just_error_cents(
+                &RatioTable::default(),
+                &unmapped_e,
+                Note::C4
+            ),
+            None
+        );
+        assert_eq!(
+            Interval::Octave.tempered_just_error_cents(
+                &RatioTable::default(),
+                &unmapped_e,
+                Note::G9
+            ),
+            None
+        );
+    }
+
     // Helper function to compare floating point values with tolerance
     fn assert_approx_eq(actual: f32, expected: f32, epsilon: f32) {
         assert!(
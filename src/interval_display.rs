@@ -1,141 +1,538 @@
 use crate::{
-    interval::{self, Interval},
-    piano_gui::{self, PIANO_WIDTH},
+    chord, dissonance_meter,
+    interval::{self, ConsonanceModel, DissonanceScale, Interval, RatioTable},
+    piano_gui::{self, KeyboardLayout, PIANO_WIDTH},
+    piano_types::{KeySet, NoteSet, OctaveDivision, Semitone},
     theme,
     utils::colorgrad_to_egui,
 };
 use colorgrad::Gradient;
 use egui::{
-    Align2, Color32, FontId, Rect, Sense, Stroke, StrokeKind, Ui, Vec2, epaint::PathShape, pos2,
-    vec2,
+    Align2, Color32, FontId, Painter, Pos2, Rect, ScrollArea, Sense, Stroke, StrokeKind, Ui, Vec2,
+    epaint::PathShape, pos2, vec2,
 };
+use num_rational::Rational32;
+use shared_types::{SynthSpectrum, tuning::TuningTable};
+use wmidi::Note;
 
-pub fn show(piano: &mut piano_gui::PianoGui, ui: &mut Ui) -> Vec<piano_gui::Action> {
-    let (actions, piano_rect) = piano.show(ui);
+/// How the dissonance rows above the piano are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayMode {
+    /// One row per held key, showing the interval from that key to every other key
+    RowPerHeldKey,
+    /// A single row showing how dissonant the held chord would become if each key was added to it
+    NextNote,
+}
+
+const TEXT_Y_OFFSET: f32 = 4.0;
+const KEY_RECT_CORNER_RADIUS: f32 = 0.0;
+const OUTLINE_STROKE_WIDTH: f32 = 2.0;
+const SEMITONES_IN_OCTAVE: f32 = 12.0;
+
+/// Geometry shared by all rows of the interval display
+struct RowLayout {
+    /// The bottom of the rows, right above the piano keys
+    bottom: f32,
+    keyboard: KeyboardLayout,
+    key_width: f32,
+    font_scale: f32,
+}
+
+impl RowLayout {
+    fn cell_center(&self, row: usize, note: Note) -> Pos2 {
+        const SCORE_CENTER_POS_ADJUSTMENT: f32 = 4.0;
+        const SCORE_CENTER_POS_OFFSET: f32 = 10.0;
+        let pos = pos2(self.keyboard.column_center_x(note), self.bottom);
+        pos - Vec2::Y
+            * ((row as f32 + 0.5) * (self.key_width + SCORE_CENTER_POS_ADJUSTMENT)
+                + SCORE_CENTER_POS_OFFSET)
+    }
+
+    fn cell_rect(&self, center: Pos2) -> Rect {
+        Rect::from_center_size(center, Vec2::splat(self.key_width))
+    }
+
+    /// Whether the piano shows a single octave, in which case intervals wrap around within it
+    fn is_single_octave(&self) -> bool {
+        let range = self.keyboard.range();
+        range.len() == usize::from(range.division().steps())
+    }
+
+    /// The note of the cell to the right of `note`'s cell, wrapping around for a single octave
+    fn next_column(&self, note: Note) -> Option<Note> {
+        let range = self.keyboard.range();
+        if self.is_single_octave() && note == range.highest() {
+            Some(range.lowest())
+        } else {
+            Some(note.step(1).ok()?).filter(|&next| range.contains(next))
+        }
+    }
+}
+
+/// How the cells of the interval rows rate and label the intervals
+pub struct CellSettings<'a> {
+    pub tuning: &'a TuningTable,
+    pub model: &'a dyn ConsonanceModel,
+    /// Partials the synth plays, whose coinciding partials give the beat rates
+    pub spectrum: &'a SynthSpectrum,
+    /// The just ratios the intervals of 12-TET are compared to
+    pub ratios: &'a RatioTable,
+    /// Whether the cells show the prime limit, odd limit and Tenney height of their ratio instead of its name
+    pub show_ratio_complexity: bool,
+}
+
+/// Show the piano with the interval rows above it
+pub fn show(
+    piano: &mut piano_gui::PianoGui,
+    mode: DisplayMode,
+    settings: &CellSettings<'_>,
+    ui: &mut Ui,
+) -> Vec<piano_gui::Action> {
     const INTERVAL_DISPLAY_HEIGHT: f32 = 200.0;
-    const TEXT_Y_OFFSET: f32 = 4.0;
-    const KEY_RECT_CORNER_RADIUS: f32 = 0.0;
-    let interval_rect = Rect::from_min_max(
-        pos2(
-            piano_rect.left(),
-            piano_rect.top() - INTERVAL_DISPLAY_HEIGHT,
-        ),
-        pos2(piano_rect.right(), piano_rect.top()),
-    );
-    ui.allocate_rect(interval_rect, Sense::empty());
+    let piano_size = piano.desired_size(ui.available_width());
+    let size = piano_size + vec2(0.0, INTERVAL_DISPLAY_HEIGHT);
+    if size.x <= ui.available_width() {
+        let (rect, _) = ui.allocate_exact_size(size, Sense::empty());
+        show_in_rect(piano, mode, settings, ui, rect, piano_size)
+    } else {
+        // Wide pianos don't fit, so let the user scroll to the keys they want
+        let scroll_bar_height =
+            ui.spacing().scroll.bar_width + ui.spacing().scroll.bar_outer_margin;
+        ui.allocate_ui(
+            vec2(ui.available_width(), size.y + scroll_bar_height),
+            |ui| {
+                ScrollArea::horizontal()
+                    // Dragging should play the keys rather than scroll
+                    .scroll_source(egui::scroll_area::ScrollSource {
+                        drag: false,
+                        ..egui::scroll_area::ScrollSource::ALL
+                    })
+                    .show(ui, |ui| {
+                        let rect = Rect::from_min_size(ui.max_rect().min, size);
+                        ui.allocate_rect(
+                            rect.with_max_y(rect.max.y + scroll_bar_height),
+                            Sense::empty(),
+                        );
+                        show_in_rect(piano, mode, settings, ui, rect, piano_size)
+                    })
+                    .inner
+            },
+        )
+        .inner
+    }
+}
+
+/// Show the piano at the bottom of `rect`, with the interval rows above it
+fn show_in_rect(
+    piano: &mut piano_gui::PianoGui,
+    mode: DisplayMode,
+    settings: &CellSettings<'_>,
+    ui: &mut Ui,
+    rect: Rect,
+    piano_size: Vec2,
+) -> Vec<piano_gui::Action> {
+    let piano_rect = Rect::from_min_size(rect.left_bottom() - vec2(0.0, piano_size.y), piano_size);
+    let (actions, keyboard) = piano.show(ui, piano_rect);
     let painter = ui.painter();
-    const SEMITONES_IN_OCTAVE: f32 = 12.0;
-    let key_width = interval_rect.width() / SEMITONES_IN_OCTAVE;
     const PIANO_WIDTH_ADJUSTMENT: f32 = 4.0;
-    let font_scale = interval_rect.width() / (PIANO_WIDTH - PIANO_WIDTH_ADJUSTMENT);
-    for (row, selected_semi) in piano
-        .held_keys()
-        .iter_ones()
-        .map(|i| i8::try_from(i).unwrap())
-        .enumerate()
-    {
-        for semi in 0..12i8 {
+    let key_width = keyboard.semitone_width();
+    let layout = RowLayout {
+        bottom: keyboard.keys_rect().top(),
+        key_width,
+        font_scale: key_width * SEMITONES_IN_OCTAVE / (PIANO_WIDTH - PIANO_WIDTH_ADJUSTMENT),
+        keyboard,
+    };
+    let range = layout.keyboard.range();
+    // Notes outside of the piano are shown on the key they fold onto
+    let mut held_notes = NoteSet::default();
+    for note in piano.held_notes().iter_ones() {
+        held_notes.set(
+            u8::from(range.fold(Note::from_u8_lossy(note as u8))) as usize,
+            true,
+        );
+    }
+    let mut held_notes_iter = held_notes.iter_ones().map(|i| Note::from_u8_lossy(i as u8));
+    let held_keys = piano.held_keys();
+    match mode {
+        DisplayMode::RowPerHeldKey => {
+            for (row, selected) in held_notes_iter.enumerate() {
+                paint_interval_row(painter, &layout, settings, row, selected);
+            }
+        }
+        DisplayMode::NextNote => {
+            // Chords are only scored for semitones, with other divisions the held keys are all empty
+            let single_key = if range.division().is_semitones() {
+                held_keys.count_ones() == 1
+            } else {
+                held_notes.count_ones() == 1
+            };
+            if single_key {
+                // With a single held key the next note forms a plain interval with it
+                let selected = held_notes_iter.next().unwrap();
+                paint_interval_row(painter, &layout, settings, 0, selected);
+            } else if held_keys.count_ones() > 1 {
+                paint_next_note_row(painter, &layout, settings, &piano.held_notes(), &held_notes);
+            }
+        }
+    }
+    actions
+}
+
+/// What a cell of an interval row shows
+struct IntervalCell {
+    /// 0 for the most consonant intervals, 1 for the most dissonant.
+    /// Models that can't score the interval, like the roughness of a silent spectrum, show it as consonant.
+    dissonance: f32,
+    just_ratio: Rational32,
+    /// Deviation of the interval from `just_ratio`, `None` if the tuning doesn't map one of the notes
+    error_cents: Option<f32>,
+    /// Beats per second between the partials of the two notes that coincide at `just_ratio`,
+    /// `None` if they are too high to hear or the tuning doesn't map one of the notes
+    beat_rate: Option<f32>,
+    name: String,
+}
+
+impl IntervalCell {
+    /// `interval` from `root` up to `upper`, which can be octaves above `interval`,
+    /// with how far `tuning` plays it from just
+    fn interval(
+        settings: &CellSettings<'_>,
+        scale: Option<&DissonanceScale<'_>>,
+        root: Note,
+        upper: Note,
+        interval: Interval,
+    ) -> Self {
+        const CENTS_PER_SEMITONE: f32 = 100.0;
+        let tuning = settings.tuning;
+        let just_ratio = interval.just_ratio(settings.ratios);
+        let semitones = u8::from(upper).abs_diff(u8::from(root));
+        let cents = tuning
+            .interval_cents(u8::from(root), semitones)
+            .unwrap_or(f32::from(semitones) * CENTS_PER_SEMITONE);
+        Self {
+            dissonance: scale.map_or(0.0, |scale| scale.normalized(cents)),
+            just_ratio,
+            error_cents: interval.tempered_just_error_cents(settings.ratios, tuning, root),
+            beat_rate: interval::beat_rate(tuning, settings.spectrum, root, upper, just_ratio),
+            name: interval.to_string(),
+        }
+    }
+
+    /// The interval of `steps` above `root` in an equal division of the octave, named after the closest just ratio
+    fn steps(
+        settings: &CellSettings<'_>,
+        scale: Option<&DissonanceScale<'_>>,
+        division: OctaveDivision,
+        root: Note,
+        steps: u8,
+    ) -> Self {
+        const CENTS_PER_OCTAVE: f32 = 1200.0;
+        let cents = CENTS_PER_OCTAVE * f32::from(steps) / f32::from(division.steps());
+        let tuning = settings.tuning;
+        let (just_ratio, error_cents) = interval::nearest_just_ratio(cents);
+        let is_mapped = tuning.frequency(u8::from(root)).is_some();
+        Self {
+            dissonance: scale.map_or(0.0, |scale| scale.normalized(cents)),
+            just_ratio,
+            error_cents: is_mapped.then_some(error_cents),
+            beat_rate: Note::try_from(u8::from(root) + steps)
+                .ok()
+                .and_then(|upper| {
+                    interval::beat_rate(tuning, settings.spectrum, root, upper, just_ratio)
+                }),
+            name: format!("{steps}\\{}", division.steps()),
+        }
+    }
+}
+
+/// Frequency `tuning` plays `root` at, or its 12-TET frequency if the tuning leaves it unmapped
+fn root_frequency(tuning: &TuningTable, root: Note) -> f32 {
+    tuning
+        .frequency(u8::from(root))
+        .unwrap_or_else(|| root.to_freq_f32())
+}
+
+/// Scale of the model's dissonance for the intervals above `root`
+fn dissonance_scale<'a>(settings: &CellSettings<'a>, root: Note) -> Option<DissonanceScale<'a>> {
+    DissonanceScale::new(settings.model, root, root_frequency(settings.tuning, root))
+}
+
+/// Row showing the interval from `selected` to every other key, with its deviation from just in `tuning`
+fn paint_interval_row(
+    painter: &Painter,
+    layout: &RowLayout,
+    settings: &CellSettings<'_>,
+    row: usize,
+    selected: Note,
+) {
+    let key_width = layout.key_width;
+    let font_scale = layout.font_scale;
+    let single_octave = layout.is_single_octave();
+    let division = layout.keyboard.range().division();
+    // The selected key is the root of most cells, so its scale is shared by them
+    let selected_scale = dissonance_scale(settings, selected);
+    for note in layout.keyboard.range().notes() {
+        let score_center_pos = layout.cell_center(row, note);
+        if note == selected {
+            paint_selected_cell(painter, layout, score_center_pos);
+            continue;
+        }
+        let (root, steps, selected_is_root) = if single_octave {
             // always consider the pressed key as the base
-            // TODO: if we show more than one octave we show the actual base as the root
-            let interval = interval::Interval::from_semitone_wrapping(semi - selected_semi);
-            let pos = pos2(
-                interval_rect.left() + key_width * (semi as f32 + 0.5),
-                interval_rect.bottom(),
+            let steps = (i16::from(u8::from(note)) - i16::from(u8::from(selected)))
+                .rem_euclid(i16::from(division.steps()));
+            (selected, steps as u8, true)
+        } else {
+            // the lower of the two notes is the root
+            let root = selected.min(note);
+            (
+                root,
+                u8::from(selected).abs_diff(u8::from(note)),
+                root == selected,
+            )
+        };
+        // In a single octave the interval can wrap around, so the upper note isn't always a key that is shown
+        let upper = Note::try_from(u8::from(root) + steps).unwrap_or(note);
+        let root_scale;
+        let scale = if selected_is_root {
+            selected_scale.as_ref()
+        } else {
+            root_scale = dissonance_scale(settings, root);
+            root_scale.as_ref()
+        };
+        let cell = if !division.is_semitones() {
+            IntervalCell::steps(settings, scale, division, root, steps)
+        } else if single_octave {
+            IntervalCell::interval(
+                settings,
+                scale,
+                root,
+                upper,
+                Interval::from_semitone_interval(steps),
+            )
+        } else {
+            IntervalCell::interval(
+                settings,
+                scale,
+                root,
+                upper,
+                Interval::between(selected, note),
+            )
+        };
+        painter.rect_filled(
+            layout.cell_rect(score_center_pos),
+            KEY_RECT_CORNER_RADIUS,
+            colorgrad_to_egui(&theme::DISSONANCE_GRADIENT.at(cell.dissonance)),
+        );
+        if selected_is_root {
+            // draw triangles to indicate that the pressed key is considered the root
+            const TRIANGLE_SIZE: f32 = 1.0 / 6.0;
+            painter.add(PathShape::convex_polygon(
+                vec![
+                    score_center_pos + vec2(-key_width / 2.0, key_width / 2.0),
+                    score_center_pos + vec2(-key_width / 2.0, key_width * (0.5 - TRIANGLE_SIZE)),
+                    score_center_pos + vec2(key_width * (-0.5 + TRIANGLE_SIZE), key_width / 2.0),
+                ],
+                theme::outlines(),
+                Stroke::NONE,
+            ));
+        }
+        if layout.next_column(note) != Some(selected) {
+            paint_cell_bottom_line(painter, layout, score_center_pos);
+        }
+        const RATIO_FONT_SIZE: f32 = 14.0;
+        let ratio_rect = painter.text(
+            score_center_pos - vec2(0.0, key_width / 2.0 - TEXT_Y_OFFSET),
+            Align2::CENTER_TOP,
+            cell.just_ratio.to_string(),
+            FontId::monospace(RATIO_FONT_SIZE * font_scale),
+            Color32::BLACK,
+        );
+        const CENTS_ERROR_Y_OFFSET: f32 = 2.0;
+        const CENTS_ERROR_FONT_SIZE: f32 = 12.0;
+        const CENTS_ERROR_ALPHA: u8 = 180;
+        // Notes that the tuning leaves unmapped have no error to show
+        if let Some(error) = cell.error_cents {
+            let color = Color32::from_black_alpha(CENTS_ERROR_ALPHA);
+            let cents_galley = painter.layout_no_wrap(
+                format!("{:+}¢", error as i32),
+                FontId::monospace(CENTS_ERROR_FONT_SIZE * font_scale),
+                color,
             );
-            let this_selected = semi == selected_semi;
-            const SCORE_CENTER_POS_ADJUSTMENT: f32 = 4.0;
-            const SCORE_CENTER_POS_OFFSET: f32 = 10.0;
-            let score_center_pos = pos
-                - Vec2::Y
-                    * ((row as f32 + 0.5) * (key_width + SCORE_CENTER_POS_ADJUSTMENT)
-                        + SCORE_CENTER_POS_OFFSET);
-            const OUTLINE_STROKE_WIDTH: f32 = 2.0;
-            if this_selected {
-                painter.rect_stroke(
-                    Rect::from_center_size(score_center_pos, Vec2::splat(key_width)),
-                    KEY_RECT_CORNER_RADIUS,
-                    Stroke::new(OUTLINE_STROKE_WIDTH, theme::outlines()),
-                    StrokeKind::Inside,
-                );
-                const NOTE_FONT_SIZE: f32 = 20.0;
-                painter.text(
-                    score_center_pos,
-                    Align2::CENTER_CENTER,
-                    "♪",
-                    FontId::monospace(NOTE_FONT_SIZE * font_scale),
-                    Color32::WHITE,
-                );
-            } else {
-                let normalized_dissonance = (interval.dissonance()
-                    - Interval::PerfectFifth.dissonance())
-                    / (Interval::Tritone.dissonance() - Interval::PerfectFifth.dissonance());
-                painter.rect_filled(
-                    Rect::from_center_size(score_center_pos, Vec2::splat(key_width)),
-                    KEY_RECT_CORNER_RADIUS,
-                    colorgrad_to_egui(&theme::DISSONANCE_GRADIENT.at(normalized_dissonance)),
-                );
-                // draw triangles to indicate that the pressed key is considered the root
-                const TRIANGLE_SIZE: f32 = 1.0 / 6.0;
-                painter.add(PathShape::convex_polygon(
-                    vec![
-                        score_center_pos + vec2(-key_width / 2.0, key_width / 2.0),
-                        score_center_pos
-                            + vec2(-key_width / 2.0, key_width * (0.5 - TRIANGLE_SIZE)),
-                        score_center_pos
-                            + vec2(key_width * (-0.5 + TRIANGLE_SIZE), key_width / 2.0),
-                    ],
-                    theme::outlines(),
-                    Stroke::NONE,
-                ));
-                if (semi + 1).rem_euclid(12) != selected_semi {
-                    painter.line_segment(
-                        [
-                            score_center_pos
-                                + vec2(
-                                    -key_width / 2.0,
-                                    key_width / 2.0 - OUTLINE_STROKE_WIDTH / 2.0,
-                                ),
-                            score_center_pos
-                                + vec2(
-                                    key_width / 2.0,
-                                    key_width / 2.0 - OUTLINE_STROKE_WIDTH / 2.0,
-                                ),
-                        ],
-                        Stroke::new(OUTLINE_STROKE_WIDTH, theme::outlines()),
-                    );
-                }
-                const RATIO_FONT_SIZE: f32 = 14.0;
-                let ratio_rect = painter.text(
-                    score_center_pos - vec2(0.0, key_width / 2.0 - TEXT_Y_OFFSET),
-                    Align2::CENTER_TOP,
-                    interval.just_ratio().to_string(),
-                    FontId::monospace(RATIO_FONT_SIZE * font_scale),
-                    Color32::BLACK,
-                );
-                const CENTS_ERROR_Y_OFFSET: f32 = 2.0;
-                const CENTS_ERROR_FONT_SIZE: f32 = 12.0;
-                const CENTS_ERROR_ALPHA: u8 = 180;
-                painter.text(
-                    ratio_rect.center_bottom() + vec2(0.0, CENTS_ERROR_Y_OFFSET),
-                    Align2::CENTER_TOP,
-                    format!("{:+}¢", interval.tempered_just_error_cents() as i32),
-                    FontId::monospace(CENTS_ERROR_FONT_SIZE * font_scale),
-                    Color32::from_black_alpha(CENTS_ERROR_ALPHA),
+            // Beats per second beside the error, which is what piano tuners listen for
+            const BEAT_RATE_FONT_SIZE: f32 = 7.0;
+            const MIN_BEAT_RATE_FONT_SCALE: f32 = 0.7;
+            const BEAT_RATE_SPACING: f32 = 2.0;
+            let beat_rate_galley = cell
+                .beat_rate
+                .filter(|_| font_scale > MIN_BEAT_RATE_FONT_SCALE)
+                .map(|beat_rate| {
+                    // Slow beats are counted more precisely than fast ones
+                    const PRECISE_BEAT_RATE: f32 = 10.0;
+                    let text = if beat_rate < PRECISE_BEAT_RATE {
+                        format!("{beat_rate:.1}bps")
+                    } else {
+                        format!("{beat_rate:.0}bps")
+                    };
+                    painter.layout_no_wrap(
+                        text,
+                        FontId::monospace(BEAT_RATE_FONT_SIZE * font_scale),
+                        color,
+                    )
+                });
+            let spacing = BEAT_RATE_SPACING * font_scale;
+            // The error and the beat rate are centered together
+            let width = cents_galley.size().x
+                + beat_rate_galley
+                    .as_ref()
+                    .map_or(0.0, |galley| spacing + galley.size().x);
+            let cents_rect = Rect::from_min_size(
+                ratio_rect.center_bottom() + vec2(-width / 2.0, CENTS_ERROR_Y_OFFSET),
+                cents_galley.size(),
+            );
+            painter.galley(cents_rect.min, cents_galley, color);
+            if let Some(galley) = beat_rate_galley {
+                // Bottom aligned with the error, so that both sit on the same line
+                let pos = pos2(
+                    cents_rect.right() + spacing,
+                    cents_rect.bottom() - galley.size().y,
                 );
-                const MIN_FONT_SCALE: f32 = 0.7;
-                if font_scale > MIN_FONT_SCALE {
-                    const INTERVAL_NAME_FONT_SIZE: f32 = 7.0;
-                    const INTERVAL_NAME_ALPHA: u8 = 150;
-                    painter.text(
-                        score_center_pos + vec2(0.0, key_width / 2.0 - TEXT_Y_OFFSET),
-                        Align2::CENTER_BOTTOM,
-                        interval.to_string(),
-                        FontId::proportional(INTERVAL_NAME_FONT_SIZE * font_scale),
-                        Color32::from_black_alpha(INTERVAL_NAME_ALPHA),
-                    );
-                }
+                painter.galley(pos, galley, color);
             }
         }
+        let footer = if settings.show_ratio_complexity {
+            format!(
+                "p{} o{} H{:.1}",
+                interval::prime_limit(cell.just_ratio),
+                interval::odd_limit(cell.just_ratio),
+                interval::tenney_height(cell.just_ratio)
+            )
+        } else {
+            cell.name
+        };
+        paint_cell_footer(painter, layout, score_center_pos, &footer);
+    }
+}
+
+/// Row showing, for every key that isn't held, the dissonance and name of the chord that adding it would form.
+/// `sounding_notes` are the held notes where they are played, `held_notes` are folded onto the keys shown.
+fn paint_next_note_row(
+    painter: &Painter,
+    layout: &RowLayout,
+    settings: &CellSettings<'_>,
+    sounding_notes: &NoteSet,
+    held_notes: &NoteSet,
+) {
+    const ROW: usize = 0;
+    let key_width = layout.key_width;
+    let is_held = |note: Note| held_notes[u8::from(note) as usize];
+    for note in layout.keyboard.range().notes() {
+        let score_center_pos = layout.cell_center(ROW, note);
+        if is_held(note) {
+            paint_selected_cell(painter, layout, score_center_pos);
+            continue;
+        }
+        let semitone = Semitone::from_note(note);
+        // The chord with the note added where it is on the piano, so it can be a new bass or double a held key
+        let mut notes = *sounding_notes;
+        notes.set(usize::from(u8::from(note)), true);
+        let mut chord = KeySet::default();
+        let semitones = notes
+            .iter_ones()
+            .map(|note| Semitone::from_note(Note::from_u8_lossy(note as u8)));
+        let bass = semitones.clone().next();
+        for key in semitones {
+            chord.set(key.as_index(), true);
+        }
+        // Models that can't score the chord, like the roughness of a silent spectrum, show it as consonant
+        let dissonance =
+            dissonance_meter::chord_score(settings.model, settings.tuning, &notes).unwrap_or(0.0);
+        painter.rect_filled(
+            layout.cell_rect(score_center_pos),
+            KEY_RECT_CORNER_RADIUS,
+            colorgrad_to_egui(&theme::DISSONANCE_GRADIENT.at(dissonance)),
+        );
+        if !layout.next_column(note).is_some_and(is_held) {
+            paint_cell_bottom_line(painter, layout, score_center_pos);
+        }
+        if let Some(chord_name) = chord::recognize(&chord, bass).first() {
+            const CHORD_NAME_FONT_SIZE: f32 = 12.0;
+            painter.text(
+                score_center_pos - vec2(0.0, key_width / 2.0 - TEXT_Y_OFFSET),
+                Align2::CENTER_TOP,
+                chord_name.to_string(),
+                FontId::monospace(CHORD_NAME_FONT_SIZE * layout.font_scale),
+                Color32::BLACK,
+            );
+        }
+        const SCORE_FONT_SIZE: f32 = 10.0;
+        const SCORE_ALPHA: u8 = 180;
+        painter.text(
+            score_center_pos,
+            Align2::CENTER_CENTER,
+            format!("{dissonance:.2}"),
+            FontId::monospace(SCORE_FONT_SIZE * layout.font_scale),
+            Color32::from_black_alpha(SCORE_ALPHA),
+        );
+        paint_cell_footer(
+            painter,
+            layout,
+            score_center_pos,
+            &format!("+{}", semitone.name()),
+        );
+    }
+}
+
+/// Outlined cell marking a held key
+fn paint_selected_cell(painter: &Painter, layout: &RowLayout, center: Pos2) {
+    painter.rect_stroke(
+        layout.cell_rect(center),
+        KEY_RECT_CORNER_RADIUS,
+        Stroke::new(OUTLINE_STROKE_WIDTH, theme::outlines()),
+        StrokeKind::Inside,
+    );
+    const NOTE_FONT_SIZE: f32 = 20.0;
+    painter.text(
+        center,
+        Align2::CENTER_CENTER,
+        "♪",
+        FontId::monospace(NOTE_FONT_SIZE * layout.font_scale),
+        Color32::WHITE,
+    );
+}
+
+/// Line separating a cell from the cell to its right
+fn paint_cell_bottom_line(painter: &Painter, layout: &RowLayout, center: Pos2) {
+    let key_width = layout.key_width;
+    painter.line_segment(
+        [
+            center
+                + vec2(
+                    -key_width / 2.0,
+                    key_width / 2.0 - OUTLINE_STROKE_WIDTH / 2.0,
+                ),
+            center
+                + vec2(
+                    key_width / 2.0,
+                    key_width / 2.0 - OUTLINE_STROKE_WIDTH / 2.0,
+                ),
+        ],
+        Stroke::new(OUTLINE_STROKE_WIDTH, theme::outlines()),
+    );
+}
+
+/// Small caption at the bottom of a cell, hidden when the display is too small to read it
+fn paint_cell_footer(painter: &Painter, layout: &RowLayout, center: Pos2, text: &str) {
+    const MIN_FONT_SCALE: f32 = 0.7;
+    if layout.font_scale > MIN_FONT_SCALE {
+        const FOOTER_FONT_SIZE: f32 = 7.0;
+        const FOOTER_ALPHA: u8 = 150;
+        painter.text(
+            center + vec2(0.0, layout.key_width / 2.0 - TEXT_Y_OFFSET),
+            Align2::CENTER_BOTTOM,
+            text,
+            FontId::proportional(FOOTER_FONT_SIZE * layout.font_scale),
+            Color32::from_black_alpha(FOOTER_ALPHA),
+        );
     }
-    actions
 }
@@ -0,0 +1,335 @@
+//! Estimating the key of what is being played, and naming chords by their function in it
+
+use std::fmt::{Display, Formatter, Result};
+
+use web_time::{Duration, Instant};
+
+use crate::{chord::ChordName, piano_types::Semitone};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+impl Mode {
+    /// Semitones above the tonic of the notes that belong to the key.
+    /// Minor keys also include the raised seventh of the harmonic minor scale, as in the dominant chord.
+    fn scale(self) -> &'static [u8] {
+        match self {
+            Mode::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Mode::Minor => &[0, 2, 3, 5, 7, 8, 10, 11],
+        }
+    }
+
+    /// Krumhansl and Kessler's ratings of how well each pitch class above the tonic fits the key
+    fn profile(self) -> [f32; 12] {
+        match self {
+            Mode::Major => [
+                6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+            ],
+            Mode::Minor => [
+                6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+            ],
+        }
+    }
+
+    /// Roman numerals of the roots above the tonic, in upper case. Chromatic roots are written
+    /// relative to the scale degrees, like the Neapolitan bII.
+    fn numerals(self) -> [&'static str; 12] {
+        match self {
+            Mode::Major => [
+                "I", "bII", "II", "bIII", "III", "IV", "#IV", "V", "bVI", "VI", "bVII", "VII",
+            ],
+            Mode::Minor => [
+                "I", "bII", "II", "III", "#III", "IV", "#IV", "V", "VI", "#VI", "VII", "#VII",
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+    pub tonic: Semitone,
+    pub mode: Mode,
+}
+
+impl Key {
+    /// All 24 major and minor keys
+    pub fn all() -> impl Iterator<Item = Key> {
+        [Mode::Major, Mode::Minor]
+            .into_iter()
+            .flat_map(|mode| Semitone::iter().map(move |tonic| Key { tonic, mode }))
+    }
+
+    /// Semitones from the tonic up to `semitone`
+    fn degree(self, semitone: Semitone) -> usize {
+        (semitone.as_usize() + 12 - self.tonic.as_usize()) % 12
+    }
+
+    fn contains(self, degree: usize) -> bool {
+        self.mode.scale().contains(&(degree as u8))
+    }
+
+    /// Whether the triad the scale builds on `degree` is minor or diminished
+    fn has_minor_triad(self, degree: usize) -> bool {
+        let third = (degree + 3) % 12;
+        self.contains(third) && !self.contains((degree + 4) % 12)
+    }
+
+    /// Whether the scale builds a diminished triad on `degree`, ignoring the raised seventh of minor keys
+    fn has_diminished_triad(self, degree: usize) -> bool {
+        let natural =
+            |degree: usize| self.contains(degree) && !(self.mode == Mode::Minor && degree == 11);
+        !natural((degree + 7) % 12) && natural((degree + 6) % 12)
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let mode = match self.mode {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        };
+        write!(f, "{} {mode}", self.tonic.name())
+    }
+}
+
+/// Keeps track of how much each pitch class has been played recently, and finds the key that fits it best
+/// with the Krumhansl-Schmuckler algorithm.
+#[derive(Debug, Clone, Default)]
+pub struct KeyEstimator {
+    /// Number of times each pitch class has been played, with older notes counting less
+    weights: [f32; 12],
+    last_note: Option<Instant>,
+}
+
+impl KeyEstimator {
+    /// How long it takes for a note to count half as much, long enough to cover a few chords of a progression
+    const HALF_LIFE: Duration = Duration::from_secs(8);
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn note_on(&mut self, semitone: Semitone, time: Instant) {
+        if let Some(last_note) = self.last_note {
+            let elapsed = time.saturating_duration_since(last_note);
+            let decay = 0.5f32.powf(elapsed.as_secs_f32() / Self::HALF_LIFE.as_secs_f32());
+            for weight in &mut self.weights {
+                *weight *= decay;
+            }
+        }
+        self.weights[semitone.as_index()] += 1.0;
+        self.last_note = Some(time);
+    }
+
+    /// The key whose profile correlates best with the recently played pitch classes,
+    /// `None` until enough different notes have been played to tell keys apart
+    pub fn estimate(&self) -> Option<Key> {
+        Key::all()
+            .map(|key| {
+                let profile = key.mode.profile();
+                let rotated: [f32; 12] = std::array::from_fn(|pitch_class| {
+                    profile[key.degree(Semitone::from_usize(pitch_class))]
+                });
+                (key, correlation(&self.weights, &rotated))
+            })
+            .filter(|(_, correlation)| correlation.is_finite())
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(key, _)| key)
+    }
+}
+
+/// Pearson correlation coefficient, NaN if either has no variance
+fn correlation(a: &[f32; 12], b: &[f32; 12]) -> f32 {
+    let mean = |values: &[f32; 12]| values.iter().sum::<f32>() / values.len() as f32;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (a, b) in a.iter().zip(b) {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+    covariance / (variance_a * variance_b).sqrt()
+}
+
+/// The chord's roman numeral in `key`, such as vi7 or V7/ii.
+/// Upper case numerals are major chords and lower case minor or diminished ones.
+pub fn roman_numeral(key: Key, chord: &ChordName) -> String {
+    let degree = key.degree(chord.root);
+    let figure = figure(chord);
+    let is_diatonic = chord
+        .intervals
+        .iter()
+        .all(|&interval| key.contains((degree + usize::from(interval)) % 12));
+    // A chromatic major or dominant chord usually leads to the chord a fifth below, which it is the dominant of
+    let is_dominant =
+        chord.suffix == "maj" || (chord.intervals.contains(&4) && chord.intervals.contains(&10));
+    let target = (degree + 5) % 12;
+    if !is_diatonic
+        && is_dominant
+        && target != 0
+        && key.contains(target)
+        && !key.has_diminished_triad(target)
+    {
+        let target_numeral = key.mode.numerals()[target];
+        let target_numeral = if key.has_minor_triad(target) {
+            target_numeral.to_lowercase()
+        } else {
+            target_numeral.to_string()
+        };
+        return format!("V{figure}/{target_numeral}");
+    }
+    let numeral = key.mode.numerals()[degree];
+    if is_minor(chord) {
+        format!("{}{figure}", numeral.to_lowercase())
+    } else {
+        format!("{numeral}{figure}")
+    }
+}
+
+/// Whether the chord has a minor third and no major third, making its numeral lower case
+fn is_minor(chord: &ChordName) -> bool {
+    chord.intervals.contains(&3) && !chord.intervals.contains(&4)
+}
+
+/// What is written after the numeral, the numeral itself already tells major and minor triads apart
+fn figure(chord: &ChordName) -> &'static str {
+    match chord.suffix {
+        "maj" | "min" => "",
+        "dim" => "°",
+        "dim7" => "°7",
+        "m7b5" => "ø7",
+        "aug" => "+",
+        suffix if is_minor(chord) => suffix.strip_prefix("min").unwrap_or(suffix),
+        suffix => suffix,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chord, piano_types::KeySet};
+
+    const C: u8 = 0;
+    const D: u8 = 2;
+    const E: u8 = 4;
+    const F: u8 = 5;
+    const G: u8 = 7;
+    const A: u8 = 9;
+    const B: u8 = 11;
+
+    fn play(estimator: &mut KeyEstimator, start: Instant, semitones: &[u8]) -> Instant {
+        let mut time = start;
+        for &semitone in semitones {
+            estimator.note_on(Semitone::new(semitone), time);
+            time += Duration::from_millis(250);
+        }
+        time
+    }
+
+    fn key(tonic: u8, mode: Mode) -> Key {
+        Key {
+            tonic: Semitone::new(tonic),
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_key_estimate() {
+        let cases: &[(&[u8], Key)] = &[
+            // I IV V I in C major
+            (&[C, E, G, F, A, C, G, B, D, C, E, G], key(C, Mode::Major)),
+            // i iv V i in A minor, with the raised seventh
+            (&[A, C, E, D, F, A, E, 8, B, A, C, E], key(A, Mode::Minor)),
+            // I IV V I in E major
+            (&[E, 8, B, A, 1, E, B, 3, 6, E, 8, B], key(E, Mode::Major)),
+        ];
+        for &(semitones, expected) in cases {
+            let mut estimator = KeyEstimator::new();
+            play(&mut estimator, Instant::now(), semitones);
+            assert_eq!(estimator.estimate(), Some(expected), "{semitones:?}");
+        }
+        assert_eq!(KeyEstimator::new().estimate(), None);
+    }
+
+    #[test]
+    fn test_key_estimate_follows_modulations() {
+        let mut estimator = KeyEstimator::new();
+        let time = play(
+            &mut estimator,
+            Instant::now(),
+            &[C, E, G, F, A, C, G, B, D, C, E, G],
+        );
+        assert_eq!(estimator.estimate(), Some(key(C, Mode::Major)));
+        // The same progression in D major a while later
+        play(
+            &mut estimator,
+            time + KeyEstimator::HALF_LIFE * 2,
+            &[D, 6, A, G, B, D, A, 1, E, D, 6, A],
+        );
+        assert_eq!(estimator.estimate(), Some(key(D, Mode::Major)));
+    }
+
+    #[test]
+    fn test_numerals_are_distinct() {
+        for mode in [Mode::Major, Mode::Minor] {
+            let numerals = mode.numerals();
+            for (i, numeral) in numerals.iter().enumerate() {
+                assert!(!numerals[..i].contains(numeral), "{numeral} in {mode:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_roman_numerals() {
+        let c_major = key(C, Mode::Major);
+        let a_minor = key(A, Mode::Minor);
+        // (key, held pitch classes, root of the chord, expected numeral)
+        let cases: &[(Key, &[u8], u8, &str)] = &[
+            (c_major, &[C, E, G], C, "I"),
+            (c_major, &[D, F, A], D, "ii"),
+            (c_major, &[E, G, B], E, "iii"),
+            (c_major, &[G, B, D, F], G, "V7"),
+            (c_major, &[A, C, E, G], A, "vi7"),
+            (c_major, &[B, D, F], B, "vii°"),
+            (c_major, &[B, D, F, A], B, "viiø7"),
+            (c_major, &[C, E, G, B], C, "Imaj7"),
+            // Secondary dominants
+            (c_major, &[A, 1, E, G], A, "V7/ii"),
+            (c_major, &[D, 6, A], D, "V/V"),
+            (c_major, &[E, 8, B, D], E, "V7/vi"),
+            (c_major, &[C, E, G, 10], C, "V7/IV"),
+            // Borrowed and chromatic chords
+            (c_major, &[8, C, 3], 8, "bVI"),
+            (c_major, &[F, 8, C], F, "iv"),
+            (c_major, &[1, F, 8], 1, "bII"),
+            (c_major, &[3, 6, 10], 3, "biii"),
+            (a_minor, &[A, C, E], A, "i"),
+            (a_minor, &[E, 8, B, D], E, "V7"),
+            (a_minor, &[C, E, G], C, "III"),
+            (a_minor, &[F, A, C], F, "VI"),
+            (a_minor, &[B, D, F], B, "ii°"),
+            (a_minor, &[D, F, A, C], D, "iv7"),
+            (a_minor, &[G, B, D], G, "VII"),
+            // The leading-tone chord on the raised seventh
+            (a_minor, &[8, B, D], 8, "#vii°"),
+            (a_minor, &[A, 1, E], A, "V/iv"),
+        ];
+        for &(key, semitones, root, expected) in cases {
+            let mut keys = KeySet::default();
+            for &semitone in semitones {
+                keys.set(usize::from(semitone), true);
+            }
+            let root = Semitone::new(root);
+            let chord = chord::recognize(&keys, Some(root))
+                .into_iter()
+                .find(|chord| chord.root == root)
+                .unwrap();
+            assert_eq!(roman_numeral(key, &chord), expected, "{chord} in {key}");
+        }
+    }
+}
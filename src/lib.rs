@@ -2,12 +2,22 @@
 
 mod app;
 pub use app::DissonanceLabApp;
+mod chord;
+mod dissonance_curve;
+mod dissonance_meter;
+mod effects_panel;
+mod file_picker;
 mod interval;
 mod interval_display;
+mod key_detection;
 mod midi;
 mod piano_gui;
 mod piano_state;
 mod piano_types;
+mod pitch_class_set;
+pub mod roughness;
+mod spectrum_view;
 mod theme;
+mod timbre_editor;
 mod utils;
 pub mod webaudio;
@@ -1,7 +1,13 @@
+use itertools::Itertools as _;
 use log::error;
-use midir::{MidiInput, MidiInputConnection};
+use midir::{MidiIO, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use std::collections::BTreeSet;
 use std::convert::TryFrom;
-use wmidi::MidiMessage;
+use wmidi::{Channel, ControlFunction, MidiMessage, U7};
+
+use crate::{piano_state::Action, piano_types::NoteSet};
+
+const CLIENT_NAME: &str = "dissonance-lab";
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -11,44 +17,269 @@ pub enum Error {
     Init(#[from] midir::InitError),
     #[error("Failed to connect to MIDI device: {0}")]
     Connect(#[from] midir::ConnectError<midir::MidiInput>),
+    #[error("Failed to connect to MIDI output device: {0}")]
+    ConnectOutput(#[from] midir::ConnectError<midir::MidiOutput>),
     #[error("Failed to get port info: {0}")]
     PortInfo(#[from] midir::PortInfoError),
 }
 
+/// Which MIDI input ports to listen to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortSelection {
+    /// Whichever port is listed first, which is what you want with a single controller
+    First,
+    /// The ports with these names, whenever they are available
+    Named(BTreeSet<String>),
+}
+
+impl PortSelection {
+    /// The ports out of `available_ports` that should be connected
+    pub fn wanted_ports<'a>(&self, available_ports: &'a [String]) -> Vec<&'a String> {
+        match self {
+            Self::First => available_ports.first().into_iter().collect(),
+            Self::Named(names) => available_ports
+                .iter()
+                .filter(|port| names.contains(*port))
+                .collect(),
+        }
+    }
+}
+
+/// Names of the MIDI input ports that are currently available
+pub fn available_ports() -> Result<Vec<String>, Error> {
+    port_names(&MidiInput::new(CLIENT_NAME)?)
+}
+
+/// Names of the MIDI output ports that are currently available
+pub fn available_output_ports() -> Result<Vec<String>, Error> {
+    port_names(&MidiOutput::new(CLIENT_NAME)?)
+}
+
+fn port_names(midi: &impl MidiIO) -> Result<Vec<String>, Error> {
+    let names = midi
+        .ports()
+        .iter()
+        .map(|port| midi.port_name(port))
+        .collect::<Result<Vec<_>, _>>()?;
+    // Ports are identified by name, so of several identical devices only the first can be used
+    let names: Vec<String> = names.into_iter().unique().collect();
+    if names.is_empty() {
+        Err(Error::NoMidiInterface)
+    } else {
+        Ok(names)
+    }
+}
+
+fn find_port<T: MidiIO>(midi: &T, port_name: &str) -> Result<T::Port, Error> {
+    for port in midi.ports() {
+        if midi.port_name(&port)? == port_name {
+            return Ok(port);
+        }
+    }
+    Err(Error::NoMidiInterface)
+}
+
 pub struct MidiReader {
     _connection: MidiInputConnection<()>,
     name: String,
 }
 
 impl MidiReader {
-    pub fn new(callback: impl Fn(&MidiMessage<'_>) + Send + 'static) -> Result<Self, Error> {
-        let midi = MidiInput::new("dissonance-lab")?;
-        let ports = midi.ports();
-        if let Some(port) = ports.first() {
-            let name = midi.port_name(port)?;
-            let connection = midi.connect(
-                port,
-                &name,
-                move |_time_ms, message, _| match wmidi::MidiMessage::try_from(message) {
-                    Ok(message) => {
-                        callback(&message);
-                    }
-                    Err(e) => {
-                        error!("error parsing midi event {e}");
-                    }
-                },
-                (),
-            )?;
-            Ok(Self {
-                _connection: connection,
-                name,
-            })
-        } else {
-            Err(Error::NoMidiInterface)
-        }
+    /// Connect to the input port called `port_name`
+    pub fn new(
+        port_name: &str,
+        callback: impl Fn(&MidiMessage<'_>) + Send + 'static,
+    ) -> Result<Self, Error> {
+        let midi = MidiInput::new(CLIENT_NAME)?;
+        let port = find_port(&midi, port_name)?;
+        let connection = midi.connect(
+            &port,
+            port_name,
+            move |_time_ms, message, _| match wmidi::MidiMessage::try_from(message) {
+                Ok(message) => {
+                    callback(&message);
+                }
+                Err(e) => {
+                    error!("error parsing midi event {e}");
+                }
+            },
+            (),
+        )?;
+        Ok(Self {
+            _connection: connection,
+            name: port_name.to_string(),
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The MIDI message that plays `action` on an external synth
+pub fn action_message(action: &Action, channel: Channel, velocity: U7) -> MidiMessage<'static> {
+    // Release velocity for devices that care about it, 64 is the default according to the MIDI spec
+    const NOTE_OFF_VELOCITY: U7 = U7::from_u8_lossy(64);
+    match *action {
+        Action::Pressed(note) => MidiMessage::NoteOn(channel, note, velocity),
+        Action::Released(note) => MidiMessage::NoteOff(channel, note, NOTE_OFF_VELOCITY),
+        Action::SustainPedal(active) => MidiMessage::ControlChange(
+            channel,
+            ControlFunction::DAMPER_PEDAL,
+            if active { U7::MAX } else { U7::MIN },
+        ),
+    }
+}
+
+/// Sends the notes played on the piano to a MIDI output port
+pub struct MidiWriter {
+    connection: MidiOutputConnection,
+    name: String,
+    channel: Channel,
+    velocity: U7,
+    /// Notes that were turned on and not yet off, so that they can be released when the channel changes or
+    /// the writer goes away. Otherwise they would keep playing on the external synth.
+    sounding_notes: NoteSet,
+    sustain_active: bool,
+}
+
+impl MidiWriter {
+    /// Connect to the output port called `port_name`
+    pub fn new(port_name: &str, channel: Channel, velocity: U7) -> Result<Self, Error> {
+        let midi = MidiOutput::new(CLIENT_NAME)?;
+        let port = find_port(&midi, port_name)?;
+        let connection = midi.connect(&port, port_name)?;
+        Ok(Self {
+            connection,
+            name: port_name.to_string(),
+            channel,
+            velocity,
+            sounding_notes: NoteSet::default(),
+            sustain_active: false,
+        })
     }
 
     pub fn get_name(&self) -> &str {
         &self.name
     }
+
+    pub fn send_action(&mut self, action: &Action) {
+        match *action {
+            Action::Pressed(note) => self.sounding_notes.set(u8::from(note) as usize, true),
+            Action::Released(note) => self.sounding_notes.set(u8::from(note) as usize, false),
+            Action::SustainPedal(active) => self.sustain_active = active,
+        }
+        self.send(&action_message(action, self.channel, self.velocity));
+    }
+
+    /// Velocity of the notes played from now on
+    pub fn set_velocity(&mut self, velocity: U7) {
+        self.velocity = velocity;
+    }
+
+    /// Move to another channel. Sounding notes are released since they can't be released on the new channel.
+    pub fn set_channel(&mut self, channel: Channel) {
+        if channel != self.channel {
+            self.release_all();
+            self.channel = channel;
+        }
+    }
+
+    fn release_all(&mut self) {
+        let sounding_notes = self.sounding_notes;
+        self.sounding_notes.fill(false);
+        for note in sounding_notes.iter_ones() {
+            self.send(&action_message(
+                &Action::Released(wmidi::Note::from_u8_lossy(note as u8)),
+                self.channel,
+                self.velocity,
+            ));
+        }
+        if self.sustain_active {
+            self.send(&action_message(
+                &Action::SustainPedal(false),
+                self.channel,
+                self.velocity,
+            ));
+            self.sustain_active = false;
+        }
+    }
+
+    fn send(&mut self, message: &MidiMessage<'_>) {
+        const MAX_MESSAGE_SIZE: usize = 3;
+        let mut bytes = [0; MAX_MESSAGE_SIZE];
+        debug_assert!(message.bytes_size() <= MAX_MESSAGE_SIZE);
+        match message.copy_to_slice(&mut bytes) {
+            Ok(size) => {
+                if let Err(e) = self.connection.send(&bytes[..size]) {
+                    error!("unable to send midi message to {}: {e}", self.name);
+                }
+            }
+            Err(e) => {
+                error!("unable to encode midi message {message:?}: {e}");
+            }
+        }
+    }
+}
+
+impl Drop for MidiWriter {
+    fn drop(&mut self) {
+        self.release_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ports(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_first_port_selection() {
+        let available = ports(&["Keyboard", "Virtual"]);
+        assert_eq!(
+            PortSelection::First.wanted_ports(&available),
+            vec!["Keyboard"]
+        );
+        assert!(PortSelection::First.wanted_ports(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_named_port_selection() {
+        let selection = PortSelection::Named(["Virtual", "Unplugged"].map(String::from).into());
+        let available = ports(&["Keyboard", "Virtual"]);
+        // Only the selected ports that are available are wanted
+        assert_eq!(selection.wanted_ports(&available), vec!["Virtual"]);
+
+        // A device that is plugged back in is wanted again
+        let available = ports(&["Keyboard", "Unplugged", "Virtual"]);
+        assert_eq!(
+            selection.wanted_ports(&available),
+            vec!["Unplugged", "Virtual"]
+        );
+    }
+
+    #[test]
+    fn test_action_messages() {
+        let channel = Channel::Ch3;
+        let velocity = U7::from_u8_lossy(100);
+        assert_eq!(
+            action_message(&Action::Pressed(wmidi::Note::C4), channel, velocity),
+            MidiMessage::NoteOn(channel, wmidi::Note::C4, velocity)
+        );
+        assert!(matches!(
+            action_message(&Action::Released(wmidi::Note::C4), channel, velocity),
+            MidiMessage::NoteOff(Channel::Ch3, wmidi::Note::C4, _)
+        ));
+        assert_eq!(
+            action_message(&Action::SustainPedal(true), channel, velocity),
+            MidiMessage::ControlChange(channel, ControlFunction::DAMPER_PEDAL, U7::MAX)
+        );
+        assert_eq!(
+            action_message(&Action::SustainPedal(false), channel, velocity),
+            MidiMessage::ControlChange(channel, ControlFunction::DAMPER_PEDAL, U7::MIN)
+        );
+    }
 }
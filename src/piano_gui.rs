@@ -1,14 +1,36 @@
-use egui::{Event, Rect, Sense, TouchPhase, Ui, pos2, vec2};
+use egui::{Align2, Event, FontId, Key, Pos2, Rect, TouchPhase, Ui, Vec2, pos2, vec2};
 use std::collections::{HashMap, HashSet};
 use wmidi::Note;
 
+use crate::chord::{self, ChordName};
 use crate::piano_state::PianoState;
-use crate::piano_types::{KeySet, PointerId, Semitone};
+use crate::piano_types::{
+    KeyRange, KeySet, KeyboardSize, NoteSet, OctaveDivision, PointerId, Semitone,
+};
 use crate::theme;
 
 // Re-export Action for backward compatibility
 pub use crate::piano_state::Action;
 
+/// Computer keyboard keys that play the notes from C of the current octave up to C of the next,
+/// laid out like a piano with the black keys on the row above.
+/// When the octave has more steps than semitones they play the first 13 steps.
+const COMPUTER_KEYBOARD_KEYS: [Key; 13] = [
+    Key::A,
+    Key::W,
+    Key::S,
+    Key::E,
+    Key::D,
+    Key::F,
+    Key::T,
+    Key::G,
+    Key::Y,
+    Key::H,
+    Key::U,
+    Key::J,
+    Key::K,
+];
+
 pub const PIANO_WIDTH: f32 = 600.0;
 pub const PIANO_HEIGHT: f32 = 200.0;
 
@@ -52,21 +74,104 @@ impl PianoGui {
         self.state.is_sustain_active()
     }
 
-    pub fn show(&mut self, ui: &mut Ui) -> (Vec<Action>, Rect) {
+    pub fn keyboard_size(&self) -> KeyboardSize {
+        self.state.keyboard_size()
+    }
+
+    /// Change how many keys the piano shows
+    pub fn set_keyboard_size(&mut self, keyboard_size: KeyboardSize, actions: &mut Vec<Action>) {
+        let previous_range = self.key_range();
+        self.state.set_keyboard_size(keyboard_size, actions);
+        self.forget_pointers_if_moved(previous_range);
+    }
+
+    pub fn division(&self) -> OctaveDivision {
+        self.state.division()
+    }
+
+    /// Change how many keys make up an octave
+    pub fn set_division(&mut self, division: OctaveDivision, actions: &mut Vec<Action>) {
+        let previous_range = self.key_range();
+        self.state.set_division(division, actions);
+        self.forget_pointers_if_moved(previous_range);
+    }
+
+    /// The lowest octave shown by the piano
+    pub fn octave(&self) -> u8 {
+        self.state.octave()
+    }
+
+    /// Move the piano up or down by `octaves`, releasing any keys held via GUI
+    pub fn shift_octave(&mut self, octaves: i8, actions: &mut Vec<Action>) {
+        let previous_range = self.key_range();
+        self.state
+            .set_octave(self.state.octave().saturating_add_signed(octaves), actions);
+        self.forget_pointers_if_moved(previous_range);
+    }
+
+    /// Pointers that are still down will press whatever key they are on in the next frame,
+    /// which after a move is a different note
+    fn forget_pointers_if_moved(&mut self, previous_range: KeyRange) {
+        if self.key_range() != previous_range {
+            self.key_held_by_pointer.clear();
+            self.pointers_holding_key.clear();
+        }
+    }
+
+    /// The notes shown on the piano
+    pub fn key_range(&self) -> KeyRange {
+        self.state.key_range()
+    }
+
+    /// Size of the piano when `available_width` is available.
+    /// The piano shrinks to fit, but not further than what is still playable, so wide key ranges may need scrolling.
+    pub fn desired_size(&self, available_width: f32) -> Vec2 {
+        const MIN_PIANO_SCALE: f32 = 0.5;
+        // Octaves with many steps get wider than the regular piano so that the keys can still be hit
+        const MIN_STEP_WIDTH: f32 = PIANO_WIDTH / 24.0;
+        let range = self.key_range();
+        let step_width = if range.division().is_semitones() {
+            PIANO_WIDTH / SEMITONES_IN_OCTAVE
+        } else {
+            (PIANO_WIDTH / f32::from(range.division().steps())).max(MIN_STEP_WIDTH)
+        };
+        let full_size = vec2(key_span(range) * step_width, PIANO_HEIGHT);
+        full_size * (available_width / full_size.x).clamp(MIN_PIANO_SCALE, 1.0)
+    }
+
+    /// Show the piano in `rect`, which should have the size from [`Self::desired_size`]
+    pub fn show(&mut self, ui: &mut Ui, rect: Rect) -> (Vec<Action>, KeyboardLayout) {
         let mut actions = Vec::new();
-        let mut piano_size = vec2(PIANO_WIDTH, PIANO_HEIGHT);
-        if piano_size.x > ui.available_width() {
-            const MIN_PIANO_SCALE: f32 = 0.5;
-            piano_size *= (ui.available_width() / piano_size.x).max(MIN_PIANO_SCALE);
+        // Typing into a text field or drag value shouldn't play notes or move the piano
+        let typing = ui.ctx().wants_keyboard_input();
+        // Octave shortcuts. Plus may need shift depending on the keyboard layout, so accept equals as well.
+        let octave_shift = ui.input(|i| {
+            if typing {
+                0
+            } else if i.key_pressed(Key::Minus) || i.key_pressed(Key::Z) {
+                -1
+            } else if i.key_pressed(Key::Plus)
+                || i.key_pressed(Key::Equals)
+                || i.key_pressed(Key::X)
+            {
+                1
+            } else {
+                0
+            }
+        });
+        if octave_shift != 0 {
+            self.shift_octave(octave_shift, &mut actions);
         }
-        let (response, painter) = ui.allocate_painter(piano_size, Sense::empty());
-        let rect = response.rect;
+
+        let painter = ui.painter_at(rect);
         const PIANO_RECT_CORNER_RADIUS: f32 = 1.0;
         painter.rect_filled(rect, PIANO_RECT_CORNER_RADIUS, ui.visuals().panel_fill);
         const MARGIN: f32 = 2.0;
-        // keys_rect represents the inner rectangle of the piano widget, after applying a margin.
-        // All piano key positions and sizes are calculated relative to this area.
-        let keys_rect = rect.shrink(MARGIN);
+        // All piano key positions and sizes are calculated relative to the inner rectangle of the piano widget,
+        // after applying a margin.
+        let layout = KeyboardLayout::new(rect.shrink(MARGIN), self.key_range());
+        // Pointers outside of the visible part of the piano, for example when it is scrolled, shouldn't press keys
+        let visible_rect = ui.clip_rect().intersect(rect);
         let shift_pressed = ui.input(|i| i.modifiers.shift);
 
         // Process all pointer events (touch and mouse)
@@ -81,7 +186,10 @@ impl PianoGui {
 
                     match phase {
                         TouchPhase::Start | TouchPhase::Move => {
-                            let target_note = self.find_key_at_position(*pos, keys_rect);
+                            let target_note = visible_rect
+                                .contains(*pos)
+                                .then(|| layout.find_key_at_position(*pos))
+                                .flatten();
                             self.handle_pointer_move(pointer_id, target_note);
                         }
                         TouchPhase::End | TouchPhase::Cancel => {
@@ -101,7 +209,10 @@ impl PianoGui {
 
             if let Some(pos) = mouse_pos {
                 if mouse_down {
-                    let target_note = self.find_key_at_position(pos, keys_rect);
+                    let target_note = visible_rect
+                        .contains(pos)
+                        .then(|| layout.find_key_at_position(pos))
+                        .flatten();
                     self.handle_pointer_move(mouse_pointer_id, target_note);
                 } else {
                     self.handle_pointer_release(mouse_pointer_id);
@@ -120,17 +231,36 @@ impl PianoGui {
         // Update PianoState with current GUI key state and get actions
         self.state.update_gui_keys(current_gui_keys, &mut actions);
 
-        // Render white keys first (so black keys appear on top)
-        for semitone in Semitone::white_keys() {
-            self.render_key(semitone, ui, &painter, keys_rect);
-        }
+        // Computer keyboard keys are polled rather than handled as events,
+        // since egui lets go of all keys when the window loses focus and the key up events would be lost
+        // Keys held while a text field gets focus are let go of
+        let computer_keys = ui.input(|i| {
+            let mut keys = NoteSet::default();
+            if typing {
+                return keys;
+            }
+            for (semitones, key) in (0..).zip(COMPUTER_KEYBOARD_KEYS) {
+                if i.key_down(key) {
+                    keys.set(
+                        u8::from(self.state.computer_key_note(semitones)) as usize,
+                        true,
+                    );
+                }
+            }
+            keys
+        });
+        self.state.update_computer_keys(computer_keys, &mut actions);
 
-        // Render black keys on top
-        for semitone in Semitone::black_keys() {
-            self.render_key(semitone, ui, &painter, keys_rect);
+        // Render white keys first (so black keys appear on top)
+        let (black_keys, white_keys): (Vec<Note>, Vec<Note>) = layout
+            .range
+            .notes()
+            .partition(|&note| layout.range.division().is_black_key(note));
+        for note in white_keys.into_iter().chain(black_keys) {
+            self.render_key(note, ui, &painter, &layout);
         }
 
-        (actions, keys_rect)
+        (actions, layout)
     }
 
     /// All keys currently held in some way, from gui or from midi, actively pressed or sustained
@@ -138,45 +268,56 @@ impl PianoGui {
         self.state.held_keys()
     }
 
-    /// Get keys currently pressed via GUI pointers (computed from pointers_holding_key)
-    fn pressed_keys(&self) -> KeySet {
-        let mut keys = KeySet::default();
+    /// All notes currently held in some way, in their actual octaves
+    pub fn held_notes(&self) -> NoteSet {
+        self.state.held_notes()
+    }
+
+    /// Get notes currently pressed via GUI pointers (computed from pointers_holding_key)
+    fn pressed_keys(&self) -> NoteSet {
+        let mut keys = NoteSet::default();
         for (&note, pointers) in &self.pointers_holding_key {
             if !pointers.is_empty() {
-                let semitone = Semitone::from_note(note);
-                keys.set(semitone.as_index(), true);
+                keys.set(u8::from(note) as usize, true);
             }
         }
         keys
     }
 
+    /// Name of the held keys as a chord, or the names of the notes if they don't form one
     pub fn selected_chord_name(&self) -> Option<String> {
-        selected_chord_name(&self.held_keys())
+        chord::describe(&self.held_keys(), self.bass())
+    }
+
+    /// All the ways the held keys can be named as a chord, most likely first
+    pub fn chord_names(&self) -> Vec<ChordName> {
+        chord::recognize(&self.held_keys(), self.bass())
+    }
+
+    /// Pitch class of the lowest held note, which makes chords built on other notes inversions
+    fn bass(&self) -> Option<Semitone> {
+        if !self.division().is_semitones() {
+            return None;
+        }
+        self.held_notes()
+            .first_one()
+            .map(|note| Semitone::from_note(Note::from_u8_lossy(note as u8)))
     }
 
     /// Render a single piano key (pure rendering, no action generation).
-    fn render_key(
-        &mut self,
-        semitone: Semitone,
-        ui: &mut Ui,
-        painter: &egui::Painter,
-        keys_rect: Rect,
-    ) {
-        let note = semitone.to_note_in_octave(self.state.octave());
-        let key_rect = key_rect_for_semitone(semitone, keys_rect);
-
-        // Allocate space for the key (needed for proper UI layout)
-        ui.allocate_rect(key_rect, Sense::click_and_drag());
+    fn render_key(&self, note: Note, ui: &Ui, painter: &egui::Painter, layout: &KeyboardLayout) {
+        let key_rect = layout.key_rect(note);
 
         let is_pressed = self
             .pointers_holding_key
             .get(&note)
-            .is_some_and(|pointers| !pointers.is_empty());
+            .is_some_and(|pointers| !pointers.is_empty())
+            || self.state.is_computer_pressed(note);
 
         // Get state information from PianoState
-        let sustained_selected = self.state.gui_sustained_keys()[semitone.as_index()];
-        let external_selected = self.state.is_external_pressed(semitone);
-        let sustained_external = self.state.is_external_sustained(semitone);
+        let sustained_selected = self.state.is_gui_sustained(note);
+        let external_selected = self.state.is_external_pressed(note);
+        let sustained_external = self.state.is_external_sustained(note);
 
         let key_fill = if is_pressed {
             // Currently pressed via GUI
@@ -211,32 +352,20 @@ impl PianoGui {
                 egui::StrokeKind::Middle,
             );
         }
-    }
-
-    /// Find which key is at the given position, checking black keys first for proper layering
-    fn find_key_at_position(&self, pos: egui::Pos2, keys_rect: Rect) -> Option<wmidi::Note> {
-        debug_assert!(
-            keys_rect.is_positive(),
-            "Keys rect must have positive dimensions"
-        );
-
-        // Check black keys first (they're on top)
-        for semitone in Semitone::black_keys() {
-            let key_rect = key_rect_for_semitone(semitone, keys_rect);
-            if key_rect.contains(pos) {
-                return Some(semitone.to_note_in_octave(self.state.octave()));
-            }
-        }
-
-        // If not on a black key, check white keys
-        for semitone in Semitone::white_keys() {
-            let key_rect = key_rect_for_semitone(semitone, keys_rect);
-            if key_rect.contains(pos) {
-                return Some(semitone.to_note_in_octave(self.state.octave()));
-            }
+        let division = layout.range.division();
+        if division.step_in_octave(note) == 0 && layout.range.len() > usize::from(division.steps())
+        {
+            // Label the octaves so that wide pianos can be navigated
+            const OCTAVE_LABEL_FONT_SIZE: f32 = 10.0;
+            const OCTAVE_LABEL_OFFSET: f32 = 4.0;
+            painter.text(
+                key_rect.center_bottom() - vec2(0.0, OCTAVE_LABEL_OFFSET),
+                Align2::CENTER_BOTTOM,
+                format!("C{}", division.octave_of(note)),
+                FontId::proportional(OCTAVE_LABEL_FONT_SIZE),
+                theme::KEYBOARD_LABEL,
+            );
         }
-
-        None
     }
 
     /// Handle a pointer moving to a new key (or moving off all keys)
@@ -309,37 +438,30 @@ impl PianoGui {
     }
 }
 
-/// Returns the rectangle for a piano key.
-/// * `semitone` - The semitone index (0-11) representing the key within the octave. Determines which piano key's rectangle to compute.
-/// * `rect` - The bounding rectangle of the entire piano area. All key positions and sizes are calculated relative to this rectangle.
-fn key_rect_for_semitone(semitone: Semitone, rect: Rect) -> Rect {
-    debug_assert!(
-        rect.is_positive(),
-        "Piano rect must have positive dimensions"
-    );
+const SEMITONES_IN_OCTAVE: f32 = 12.0;
 
+/// Horizontal position and width of the key for `note`, in semitones from C-1.
+/// White keys are wider than a semitone so that they fill the space around the black keys.
+/// Octaves with other divisions have all keys side by side, one step wide.
+fn key_span_for_note(note: Note, division: OctaveDivision) -> (f32, f32) {
+    if !division.is_semitones() {
+        return (f32::from(u8::from(note)), 1.0);
+    }
     const WHITE_KEY_X_POSITIONS: [f32; 7] = [0.0, 1.5, 3.5, 5.0, 6.5, 8.5, 10.5];
     const BLACK_KEY_X_POSITIONS: [f32; 5] = [1.0, 3.0, 6.0, 8.0, 10.0];
-    const SEMITONES_IN_OCTAVE: f32 = 12.0;
-    const BLACK_KEY_HEIGHT_RATIO: f32 = 0.6;
+    const BLACK_KEY_WIDTH: f32 = 1.0;
 
+    let semitone = Semitone::from_note(note);
+    let octave_x = (u8::from(note) / 12) as f32 * SEMITONES_IN_OCTAVE;
     if semitone.is_black_key() {
         let black_key_index = semitone.black_key_index();
         debug_assert!(
             black_key_index < BLACK_KEY_X_POSITIONS.len(),
             "Black key index out of bounds"
         );
-        let x_pos = BLACK_KEY_X_POSITIONS[black_key_index];
-        let key_size = vec2(
-            rect.width() / SEMITONES_IN_OCTAVE,
-            rect.height() * BLACK_KEY_HEIGHT_RATIO,
-        );
-        Rect::from_min_size(
-            pos2(
-                rect.min.x + x_pos / SEMITONES_IN_OCTAVE * rect.width(),
-                rect.min.y,
-            ),
-            key_size,
+        (
+            octave_x + BLACK_KEY_X_POSITIONS[black_key_index],
+            BLACK_KEY_WIDTH,
         )
     } else {
         let white_key_index = semitone.white_key_index();
@@ -351,74 +473,86 @@ fn key_rect_for_semitone(semitone: Semitone, rect: Rect) -> Rect {
         let next_x_pos = WHITE_KEY_X_POSITIONS
             .get(white_key_index + 1)
             .unwrap_or(&SEMITONES_IN_OCTAVE);
-        let key_size = vec2(
-            (next_x_pos - x_pos) / SEMITONES_IN_OCTAVE * rect.width(),
-            rect.height(),
-        );
-        Rect::from_min_size(
-            pos2(
-                rect.min.x + x_pos / SEMITONES_IN_OCTAVE * rect.width(),
-                rect.min.y,
-            ),
-            key_size,
-        )
+        (octave_x + x_pos, next_x_pos - x_pos)
     }
 }
 
-/// Determine the chord name for a given set of held keys
-/// Returns the chord name if recognizable, otherwise returns individual note names
-pub fn selected_chord_name(held_keys: &KeySet) -> Option<String> {
-    // AI generated. But seems mostly sensible
-    let mut selected_semitones: Vec<usize> = held_keys.iter_ones().collect();
-    if selected_semitones.is_empty() {
-        return None;
+/// Width of the keys of `range`, in semitones or steps
+fn key_span(range: KeyRange) -> f32 {
+    let (start, _) = key_span_for_note(range.lowest(), range.division());
+    let (highest_x, highest_width) = key_span_for_note(range.highest(), range.division());
+    highest_x + highest_width - start
+}
+
+/// Where the keys of the piano were placed, so that other widgets can line up with them
+pub struct KeyboardLayout {
+    keys_rect: Rect,
+    range: KeyRange,
+    /// Width of a black key, and of a semitone or step column of the interval display
+    semitone_width: f32,
+}
+
+impl KeyboardLayout {
+    fn new(keys_rect: Rect, range: KeyRange) -> Self {
+        debug_assert!(
+            keys_rect.is_positive(),
+            "Piano rect must have positive dimensions"
+        );
+        Self {
+            keys_rect,
+            range,
+            semitone_width: keys_rect.width() / key_span(range),
+        }
     }
 
-    // Sort semitones to normalize chord representation
-    selected_semitones.sort();
+    pub fn keys_rect(&self) -> Rect {
+        self.keys_rect
+    }
 
-    // Try all rotations of the chord (all possible roots)
-    for rotation in 0..selected_semitones.len() {
-        let root_semitone = selected_semitones[rotation];
-        let root = Semitone::from_usize(root_semitone).name();
+    pub fn range(&self) -> KeyRange {
+        self.range
+    }
 
-        let mut intervals: Vec<usize> = Vec::new();
-        for &semitone in selected_semitones.iter() {
-            if semitone != root_semitone {
-                intervals.push((semitone as i32 - root_semitone as i32).rem_euclid(12) as usize);
-            }
-        }
-        intervals.sort();
-
-        let chord_type = match (intervals.as_slice(), selected_semitones.len()) {
-            ([4, 7], 3) => "maj",      // Major triad
-            ([3, 7], 3) => "min",      // Minor triad
-            ([3, 6], 3) => "dim",      // Diminished triad
-            ([4, 8], 3) => "aug",      // Augmented triad
-            ([4, 7, 11], 4) => "maj7", // Major seventh
-            ([3, 7, 10], 4) => "min7", // Minor seventh
-            ([4, 7, 10], 4) => "7",    // Dominant seventh
-            ([3, 6, 9], 4) => "dim7",  // Diminished seventh
-            ([3, 6, 10], 4) => "m7b5", // Half-diminished seventh
-            _ => "",                   // Unknown chord type
-        };
+    pub fn semitone_width(&self) -> f32 {
+        self.semitone_width
+    }
 
-        if !chord_type.is_empty() {
-            return Some(format!("{root}{chord_type}"));
-        }
+    /// Horizontal center of a semitone wide column above `note`
+    pub fn column_center_x(&self, note: Note) -> f32 {
+        let (start, _) = key_span_for_note(self.range.lowest(), self.range.division());
+        self.keys_rect.left() + (u8::from(note) as f32 + 0.5 - start) * self.semitone_width
     }
 
-    if selected_semitones.len() == 1 {
-        Some(
-            Semitone::from_usize(selected_semitones[0])
-                .name()
-                .to_string(),
+    /// Returns the rectangle for the piano key of `note`
+    fn key_rect(&self, note: Note) -> Rect {
+        const BLACK_KEY_HEIGHT_RATIO: f32 = 0.6;
+        debug_assert!(self.range.contains(note), "Note must be on the piano");
+        let division = self.range.division();
+        let (start, _) = key_span_for_note(self.range.lowest(), division);
+        let (x_pos, width) = key_span_for_note(note, division);
+        let height = if division.is_black_key(note) {
+            self.keys_rect.height() * BLACK_KEY_HEIGHT_RATIO
+        } else {
+            self.keys_rect.height()
+        };
+        Rect::from_min_size(
+            pos2(
+                self.keys_rect.left() + (x_pos - start) * self.semitone_width,
+                self.keys_rect.top(),
+            ),
+            vec2(width * self.semitone_width, height),
         )
-    } else {
-        let notes: Vec<String> = selected_semitones
-            .iter()
-            .map(|&semitone| Semitone::from_usize(semitone).name().to_string())
-            .collect();
-        Some(notes.join("/"))
+    }
+
+    /// Find which key is at the given position, checking black keys first for proper layering
+    fn find_key_at_position(&self, pos: Pos2) -> Option<Note> {
+        let (black_keys, white_keys): (Vec<Note>, Vec<Note>) = self
+            .range
+            .notes()
+            .partition(|&note| self.range.division().is_black_key(note));
+        black_keys
+            .into_iter()
+            .chain(white_keys)
+            .find(|&note| self.key_rect(note).contains(pos))
     }
 }
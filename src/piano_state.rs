@@ -1,6 +1,6 @@
 use wmidi::Note;
 
-use crate::piano_types::{ExternalKeySet, KeySet, Semitone};
+use crate::piano_types::{KeyRange, KeySet, KeyboardSize, NoteSet, OctaveDivision};
 
 /// Actions that can be generated by the piano state
 #[derive(Debug, Clone, PartialEq)]
@@ -13,28 +13,40 @@ pub enum Action {
 /// The core business logic state for piano key management, sustain logic, and action generation.
 /// This struct is separate from GUI concerns and can be unit tested independently.
 pub struct PianoState {
-    /// Keys that had GUI input in the previous frame.
+    /// Notes that had GUI or computer keyboard input in the previous frame.
     /// Used to detect press/release transitions for action generation.
     /// Does NOT include sustained keys or keys held via external MIDI input.
-    previous_gui_pressed_keys: KeySet,
+    previous_gui_pressed_keys: NoteSet,
 
-    /// Keys that are currently pressed via GUI input.
+    /// Notes that are currently pressed via GUI input.
     /// This represents the current state of GUI key presses.
-    current_gui_pressed_keys: KeySet,
+    current_gui_pressed_keys: NoteSet,
 
-    /// Keys that were pressed via GUI while sustain was active, but have since been released.
+    /// Notes that are currently pressed via the computer keyboard.
+    /// Tracked separately from the GUI so that releasing a key doesn't release a note that a pointer still holds.
+    computer_pressed_keys: NoteSet,
+
+    /// Notes that were pressed via GUI while sustain was active, but have since been released.
     /// These keys will remain active until the sustain pedal is released.
-    sustained_keys: KeySet,
+    sustained_keys: NoteSet,
 
     /// Keys that are currently pressed via external MIDI input.
-    /// Tracks all 128 MIDI notes, not just the current octave.
-    external_pressed_keys: ExternalKeySet,
+    /// Tracks all 128 MIDI notes, not just the ones shown on the piano.
+    external_pressed_keys: NoteSet,
 
     /// Keys that are sustained due to sustain pedal being active when they were released.
     /// These keys will remain active until the sustain pedal is released.
-    external_sustained_keys: ExternalKeySet,
+    external_sustained_keys: NoteSet,
+
+    /// How many keys the piano shows
+    keyboard_size: KeyboardSize,
 
-    /// The octave that this piano state operates on (default: 4, meaning C4-B4)
+    /// How many keys make up an octave
+    division: OctaveDivision,
+
+    /// The lowest octave shown by the piano (default: 4, meaning the keys start at C4).
+    /// Also the octave played by the computer keyboard.
+    /// The full size piano always shows A0-C8, so there it only affects the computer keyboard.
     octave: u8,
 
     /// Whether the shift key was active in the previous frame
@@ -54,9 +66,12 @@ impl PianoState {
         Self {
             previous_gui_pressed_keys: Default::default(),
             current_gui_pressed_keys: Default::default(),
+            computer_pressed_keys: Default::default(),
             sustained_keys: Default::default(),
             external_pressed_keys: Default::default(),
             external_sustained_keys: Default::default(),
+            keyboard_size: KeyboardSize::Octaves(1),
+            division: OctaveDivision::SEMITONES,
             octave: DEFAULT_OCTAVE,
             previous_shift_sustain_active: false,
             shift_sustain_active: false,
@@ -65,11 +80,17 @@ impl PianoState {
     }
 
     /// Update the GUI pressed keys state and generate appropriate actions
-    pub fn update_gui_keys(&mut self, pressed_keys: KeySet, actions: &mut Vec<Action>) {
+    pub fn update_gui_keys(&mut self, pressed_keys: NoteSet, actions: &mut Vec<Action>) {
         self.current_gui_pressed_keys = pressed_keys;
         self.generate_actions_for_gui_keys(actions);
     }
 
+    /// Update the notes pressed via the computer keyboard and generate appropriate actions
+    pub fn update_computer_keys(&mut self, pressed_keys: NoteSet, actions: &mut Vec<Action>) {
+        self.computer_pressed_keys = pressed_keys;
+        self.generate_actions_for_gui_keys(actions);
+    }
+
     /// Update the shift sustain state and generate appropriate actions
     pub fn update_shift_sustain(&mut self, active: bool, actions: &mut Vec<Action>) {
         let was_overall_sustain_active = self.is_sustain_active();
@@ -138,96 +159,168 @@ impl PianoState {
         self.shift_sustain_active || self.external_sustain_active
     }
 
-    /// Get all keys currently held in some way, from GUI or from MIDI, actively pressed or sustained
+    /// Get all keys currently held in some way, from GUI or from MIDI, actively pressed or sustained,
+    /// folded into a single octave.
+    /// Empty when the octave isn't divided into semitones, since the keys then aren't pitch classes of 12.
     pub fn held_keys(&self) -> KeySet {
-        let mut keys = self.current_gui_pressed_keys;
-
-        // Add sustained GUI keys
-        for sustained_key in self.sustained_keys.iter_ones() {
-            keys.set(sustained_key, true);
+        // TODO: generalize `Semitone`, `KeySet` and `Interval` over the number of steps per octave,
+        // so that chord naming, set analysis and key detection work in other divisions.
+        // Until then the app shows that they are unavailable there.
+        let mut keys = KeySet::default();
+        if !self.division.is_semitones() {
+            return keys;
         }
-
-        // Add currently active external keys
-        for external_key in self.external_pressed_keys.iter_ones() {
-            keys.set(external_key % 12, true);
+        for note in self.held_notes().iter_ones() {
+            keys.set(note % 12, true);
         }
+        keys
+    }
 
-        // Add sustained external keys
-        for sustained_key in self.external_sustained_keys.iter_ones() {
-            keys.set(sustained_key % 12, true);
-        }
+    /// Get all notes currently held in some way, from GUI or from MIDI, actively pressed or sustained
+    pub fn held_notes(&self) -> NoteSet {
+        self.current_gui_pressed_keys
+            | self.computer_pressed_keys
+            | self.sustained_keys
+            | self.external_pressed_keys
+            | self.external_sustained_keys
+    }
 
-        keys
+    pub fn keyboard_size(&self) -> KeyboardSize {
+        self.keyboard_size
+    }
+
+    /// Change how many keys the piano shows.
+    /// The octave is lowered if needed to keep all keys within the MIDI range.
+    pub fn set_keyboard_size(&mut self, keyboard_size: KeyboardSize, actions: &mut Vec<Action>) {
+        let previous_range = self.key_range();
+        self.keyboard_size = keyboard_size;
+        self.octave = self.clamp_octave(self.octave);
+        self.release_gui_keys_if_moved(previous_range, actions);
+    }
+
+    pub fn division(&self) -> OctaveDivision {
+        self.division
     }
 
-    /// Get the current octave
+    /// Change how many keys make up an octave.
+    /// The octave is moved if needed to keep all keys within the MIDI range.
+    pub fn set_division(&mut self, division: OctaveDivision, actions: &mut Vec<Action>) {
+        let previous_range = self.key_range();
+        self.division = division;
+        self.octave = self.clamp_octave(self.octave);
+        self.release_gui_keys_if_moved(previous_range, actions);
+    }
+
+    /// Get the lowest octave shown by the piano
     pub fn octave(&self) -> u8 {
         self.octave
     }
 
-    /// Get keys that are sustained via GUI (were pressed while sustain was active, now released)
-    pub fn gui_sustained_keys(&self) -> &KeySet {
-        &self.sustained_keys
+    /// Move the piano so that it starts at `octave`, clamped to the octaves the keyboard size allows
+    pub fn set_octave(&mut self, octave: u8, actions: &mut Vec<Action>) {
+        let previous_range = self.key_range();
+        self.octave = self.clamp_octave(octave);
+        self.release_gui_keys_if_moved(previous_range, actions);
     }
 
-    /// Check if a specific semitone is pressed via external MIDI in any octave
-    pub fn is_external_pressed(&self, semitone: Semitone) -> bool {
-        let target_semitone = semitone.as_index();
-        self.external_pressed_keys
-            .iter_ones()
-            .any(|note_value| note_value % 12 == target_semitone)
+    /// The octaves the keyboard size and division allow
+    fn clamp_octave(&self, octave: u8) -> u8 {
+        octave.clamp(
+            self.division.min_octave(),
+            self.keyboard_size.max_octave(self.division),
+        )
     }
 
-    /// Check if a specific semitone is sustained via external MIDI in any octave
-    pub fn is_external_sustained(&self, semitone: Semitone) -> bool {
-        let target_semitone = semitone.as_index();
-        self.external_sustained_keys
-            .iter_ones()
-            .any(|note_value| note_value % 12 == target_semitone)
+    /// The note played by the computer keyboard key `steps` above the first key of the current octave
+    pub fn computer_key_note(&self, steps: u8) -> Note {
+        let note = self.division.octave_start(self.octave) + i16::from(steps);
+        debug_assert!((0..128).contains(&note), "MIDI note value must be < 128");
+        Note::from_u8_lossy(note as u8)
     }
 
-    /// Generate actions for GUI key state changes
-    fn generate_actions_for_gui_keys(&mut self, actions: &mut Vec<Action>) {
-        for semitone in Semitone::iter() {
-            let note = semitone.to_note_in_octave(self.octave);
-            let semitone_index = semitone.as_index();
+    /// The notes shown on the piano
+    pub fn key_range(&self) -> KeyRange {
+        self.keyboard_size.key_range(self.octave, self.division)
+    }
+
+    /// Check if a note shown on the piano is pressed via the computer keyboard.
+    /// Notes outside the shown range count as the key they fold onto.
+    pub fn is_computer_pressed(&self, note: Note) -> bool {
+        self.is_folded_onto(&self.computer_pressed_keys, note)
+    }
+
+    /// Check if a note shown on the piano is sustained via GUI (was pressed while sustain was active, now released).
+    /// Notes outside the shown range count as the key they fold onto.
+    pub fn is_gui_sustained(&self, note: Note) -> bool {
+        self.is_folded_onto(&self.sustained_keys, note)
+    }
+
+    /// Check if a note shown on the piano is pressed via external MIDI.
+    /// Notes outside the shown range count as the key they fold onto.
+    pub fn is_external_pressed(&self, note: Note) -> bool {
+        self.is_folded_onto(&self.external_pressed_keys, note)
+    }
+
+    /// Check if a note shown on the piano is sustained via external MIDI.
+    /// Notes outside the shown range count as the key they fold onto.
+    pub fn is_external_sustained(&self, note: Note) -> bool {
+        self.is_folded_onto(&self.external_sustained_keys, note)
+    }
 
-            let is_pressed = self.current_gui_pressed_keys[semitone_index];
-            let was_pressed = self.previous_gui_pressed_keys[semitone_index];
+    /// Let go of all GUI pressed keys if the shown keys have changed.
+    /// The keys under the pointers now belong to different notes, so the old notes would otherwise get stuck.
+    /// Keys released while sustain is active stay sustained, like any other released key.
+    fn release_gui_keys_if_moved(&mut self, previous_range: KeyRange, actions: &mut Vec<Action>) {
+        if self.key_range() != previous_range {
+            self.update_gui_keys(NoteSet::default(), actions);
+        }
+    }
+
+    /// Whether any note in `notes` is `note` once folded into the shown range
+    fn is_folded_onto(&self, notes: &NoteSet, note: Note) -> bool {
+        let range = self.key_range();
+        debug_assert!(range.contains(note), "Note must be shown on the piano");
+        notes
+            .iter_ones()
+            .any(|value| range.fold(note_from_index(value)) == note)
+    }
 
-            if is_pressed && !was_pressed {
+    /// Generate actions for GUI and computer keyboard key state changes.
+    /// A note is pressed while either source holds it, so releasing it from one source keeps it playing.
+    fn generate_actions_for_gui_keys(&mut self, actions: &mut Vec<Action>) {
+        let pressed_keys = self.current_gui_pressed_keys | self.computer_pressed_keys;
+        let changed_keys = pressed_keys ^ self.previous_gui_pressed_keys;
+        for note_index in changed_keys.iter_ones() {
+            let note = note_from_index(note_index);
+            if pressed_keys[note_index] {
                 actions.push(Action::Pressed(note));
                 // If this key was sustained, remove it from sustained set since it's now actively pressed
-                self.sustained_keys.set(semitone_index, false);
-            } else if !is_pressed && was_pressed {
-                if self.is_sustain_active() {
-                    // If sustain is active, move to sustained but don't generate Action::Released
-                    self.sustained_keys.set(semitone_index, true);
-                } else {
-                    // If sustain is not active, generate release action and clear sustained
-                    actions.push(Action::Released(note));
-                    self.sustained_keys.set(semitone_index, false);
-                }
+                self.sustained_keys.set(note_index, false);
+            } else if self.is_sustain_active() {
+                // If sustain is active, move to sustained but don't generate Action::Released
+                self.sustained_keys.set(note_index, true);
+            } else if self.external_pressed_keys[note_index] {
+                // The synth would release the note from MIDI as well, so leave it to the MIDI note off
+            } else {
+                // If sustain is not active, generate release action and clear sustained
+                actions.push(Action::Released(note));
+                self.sustained_keys.set(note_index, false);
             }
         }
 
         // Update previous state for next frame
-        self.previous_gui_pressed_keys = self.current_gui_pressed_keys;
+        self.previous_gui_pressed_keys = pressed_keys;
     }
 
     /// Handle GUI sustain release - release all sustained GUI keys that aren't currently pressed
     fn handle_gui_sustain_release(&mut self, actions: &mut Vec<Action>) {
-        for semitone in Semitone::iter() {
-            let semitone_index = semitone.as_index();
-            let note = semitone.to_note_in_octave(self.octave);
-            let is_currently_pressed = self.current_gui_pressed_keys[semitone_index];
-
-            // Clear sustained selection if key is not currently being pressed
-            if !is_currently_pressed && self.sustained_keys[semitone_index] {
-                self.sustained_keys.set(semitone_index, false);
-                actions.push(Action::Released(note));
-            }
+        // Keys that are pressed again are removed from the sustained set when pressed
+        debug_assert!((self.sustained_keys & self.previous_gui_pressed_keys).not_any());
+        // Notes still held via MIDI are left for the MIDI note off to release
+        for note_index in (self.sustained_keys & !self.external_pressed_keys).iter_ones() {
+            actions.push(Action::Released(note_from_index(note_index)));
         }
+        self.sustained_keys.fill(false);
     }
 
     /// Handle sustain pedal release for external (MIDI) keys
@@ -238,6 +331,11 @@ impl PianoState {
     }
 }
 
+fn note_from_index(index: usize) -> Note {
+    debug_assert!(index < 128, "MIDI note value must be < 128");
+    Note::from_u8_lossy(index as u8)
+}
+
 impl Default for PianoState {
     fn default() -> Self {
         Self::new()
@@ -247,6 +345,11 @@ impl Default for PianoState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::piano_types::Semitone;
+
+    fn index(note: Note) -> usize {
+        u8::from(note) as usize
+    }
 
     #[test]
     fn test_new_piano_state() {
@@ -260,8 +363,8 @@ mod tests {
         let mut state = PianoState::new();
 
         // Press C key (semitone 0)
-        let mut pressed_keys = KeySet::default();
-        pressed_keys.set(0, true);
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true);
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
 
@@ -270,7 +373,7 @@ mod tests {
         assert!(state.held_keys()[0]);
 
         // Release C key
-        let pressed_keys = KeySet::default();
+        let pressed_keys = NoteSet::default();
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
 
@@ -284,10 +387,10 @@ mod tests {
         let mut state = PianoState::new();
 
         // Test pressing multiple keys via GUI
-        let mut pressed_keys = KeySet::default();
-        pressed_keys.set(0, true); // C
-        pressed_keys.set(4, true); // E
-        pressed_keys.set(7, true); // G
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true); // C
+        pressed_keys.set(index(Note::E4), true); // E
+        pressed_keys.set(index(Note::G4), true); // G
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
 
@@ -307,9 +410,9 @@ mod tests {
         assert!(!held[1]); // D should not be held
 
         // Release one key via GUI
-        let mut pressed_keys = KeySet::default();
-        pressed_keys.set(0, true); // C still pressed
-        pressed_keys.set(7, true); // G still pressed
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true); // C still pressed
+        pressed_keys.set(index(Note::G4), true); // G still pressed
         // E (index 4) is released
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
@@ -337,15 +440,15 @@ mod tests {
         assert!(state.is_sustain_active());
 
         // Press and release C key while sustain is active
-        let mut pressed_keys = KeySet::default();
-        pressed_keys.set(0, true);
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true);
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
         assert_eq!(actions.len(), 1);
         assert!(matches!(actions[0], Action::Pressed(_)));
 
         // Release key while sustain is active - should not generate release action
-        let pressed_keys = KeySet::default();
+        let pressed_keys = NoteSet::default();
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
         assert!(actions.is_empty());
@@ -413,8 +516,8 @@ mod tests {
         actions.clear(); // Clear sustain pedal action
 
         // Press C key via GUI
-        let mut pressed_keys = KeySet::default();
-        pressed_keys.set(0, true); // C
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true); // C
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
 
@@ -423,7 +526,7 @@ mod tests {
         assert!(state.held_keys()[0]); // C should be held
 
         // Release C key while sustain is active - should become sustained
-        let pressed_keys = KeySet::default();
+        let pressed_keys = NoteSet::default();
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
 
@@ -431,8 +534,8 @@ mod tests {
         assert!(state.held_keys()[0]); // C should still be held (sustained)
 
         // Now press C key again while it's sustained - should generate only Pressed action
-        let mut pressed_keys = KeySet::default();
-        pressed_keys.set(0, true); // C pressed again
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true); // C pressed again
         let mut actions = Vec::new();
         state.update_gui_keys(pressed_keys, &mut actions);
 
@@ -666,28 +769,28 @@ mod tests {
         // Press C2 - should be detected as C pressed
         state.external_note_on(c2);
         assert!(
-            state.is_external_pressed(Semitone::C),
+            state.is_external_pressed(Note::C4),
             "C2 should be detected as C pressed"
         );
 
         // Press C6 as well
         state.external_note_on(c6);
         assert!(
-            state.is_external_pressed(Semitone::C),
+            state.is_external_pressed(Note::C4),
             "C should still be detected as pressed with both C2 and C6"
         );
 
         // Release C2, C should still be pressed due to C6
         state.external_note_off(c2);
         assert!(
-            state.is_external_pressed(Semitone::C),
+            state.is_external_pressed(Note::C4),
             "C should still be pressed after releasing C2"
         );
 
         // Release C6, C should no longer be pressed
         state.external_note_off(c6);
         assert!(
-            !state.is_external_pressed(Semitone::C),
+            !state.is_external_pressed(Note::C4),
             "C should not be pressed after releasing all C notes"
         );
 
@@ -699,7 +802,7 @@ mod tests {
         state.external_note_on(c2);
         state.external_note_off(c2);
         assert!(
-            state.is_external_sustained(Semitone::C),
+            state.is_external_sustained(Note::C4),
             "C should be sustained after releasing C2 with sustain active"
         );
 
@@ -707,7 +810,7 @@ mod tests {
         state.external_note_on(c6);
         state.external_note_off(c6);
         assert!(
-            state.is_external_sustained(Semitone::C),
+            state.is_external_sustained(Note::C4),
             "C should still be sustained with both C2 and C6 sustained"
         );
 
@@ -715,8 +818,433 @@ mod tests {
         actions.clear();
         state.set_external_sustain(false, &mut actions);
         assert!(
-            !state.is_external_sustained(Semitone::C),
+            !state.is_external_sustained(Note::C4),
             "C should not be sustained after releasing sustain"
         );
     }
+
+    #[test]
+    fn test_gui_keys_use_actual_notes() {
+        let mut state = PianoState::new();
+        state.set_keyboard_size(KeyboardSize::Octaves(2), &mut Vec::new());
+        assert_eq!(
+            state.key_range(),
+            KeyRange::octaves(OctaveDivision::SEMITONES, 4, 2)
+        );
+
+        // Press C4 and C5, which are both C but different keys
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true);
+        pressed_keys.set(index(Note::C5), true);
+        let mut actions = Vec::new();
+        state.update_gui_keys(pressed_keys, &mut actions);
+        assert_eq!(
+            actions,
+            vec![Action::Pressed(Note::C4), Action::Pressed(Note::C5)]
+        );
+        assert!(state.held_notes()[index(Note::C4)]);
+        assert!(state.held_notes()[index(Note::C5)]);
+        assert_eq!(state.held_keys().count_ones(), 1);
+
+        // Release only C5
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true);
+        let mut actions = Vec::new();
+        state.update_gui_keys(pressed_keys, &mut actions);
+        assert_eq!(actions, vec![Action::Released(Note::C5)]);
+        assert!(state.held_notes()[index(Note::C4)]);
+        assert!(!state.held_notes()[index(Note::C5)]);
+    }
+
+    #[test]
+    fn test_gui_sustain_release_uses_actual_notes() {
+        let mut state = PianoState::new();
+        state.set_keyboard_size(KeyboardSize::Octaves(2), &mut Vec::new());
+        let mut actions = Vec::new();
+        state.update_shift_sustain(true, &mut actions);
+
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::E5), true);
+        state.update_gui_keys(pressed_keys, &mut actions);
+        state.update_gui_keys(NoteSet::default(), &mut actions);
+        assert!(state.is_gui_sustained(Note::E5));
+        assert!(!state.is_gui_sustained(Note::E4));
+
+        let mut actions = Vec::new();
+        state.update_shift_sustain(false, &mut actions);
+        assert_eq!(
+            actions,
+            vec![Action::SustainPedal(false), Action::Released(Note::E5)]
+        );
+        assert!(!state.is_gui_sustained(Note::E5));
+    }
+
+    #[test]
+    fn test_external_notes_fold_into_key_range() {
+        let mut state = PianoState::new();
+        state.set_keyboard_size(KeyboardSize::Octaves(2), &mut Vec::new());
+
+        // Notes within the range are shown on their own key
+        state.external_note_on(Note::D5);
+        assert!(state.is_external_pressed(Note::D5));
+        assert!(!state.is_external_pressed(Note::D4));
+
+        // Notes outside the range are shown on the nearest key with the same pitch class
+        state.external_note_on(Note::G7);
+        assert!(state.is_external_pressed(Note::G5));
+        assert!(!state.is_external_pressed(Note::G4));
+        state.external_note_on(Note::A1);
+        assert!(state.is_external_pressed(Note::A4));
+        assert!(!state.is_external_pressed(Note::A5));
+
+        // The held notes keep their actual octave
+        let held_notes = state.held_notes();
+        assert!(held_notes[index(Note::G7)]);
+        assert!(held_notes[index(Note::A1)]);
+        assert!(!held_notes[index(Note::G5)]);
+    }
+
+    #[test]
+    fn test_full_piano_key_range() {
+        let mut state = PianoState::new();
+        state.set_keyboard_size(KeyboardSize::FullPiano, &mut Vec::new());
+        let range = state.key_range();
+        assert_eq!(range, KeyRange::FULL_PIANO);
+        assert_eq!(range.len(), 88);
+        assert_eq!(range.notes().count(), 88);
+
+        state.external_note_on(Note::A0);
+        state.external_note_on(Note::C8);
+        assert!(state.is_external_pressed(Note::A0));
+        assert!(state.is_external_pressed(Note::C8));
+
+        // Notes below A0 and above C8 fold onto the outermost octaves
+        assert_eq!(range.fold(Note::CMinus1), Note::C1);
+        assert_eq!(range.fold(Note::GSharp0), Note::GSharp1);
+        assert_eq!(range.fold(Note::CSharp8), Note::CSharp7);
+        assert_eq!(range.fold(Note::G9), Note::G7);
+    }
+
+    #[test]
+    fn test_keyboard_size_keeps_octave() {
+        let mut state = PianoState::new();
+        state.set_keyboard_size(KeyboardSize::FullPiano, &mut Vec::new());
+        state.set_keyboard_size(KeyboardSize::Octaves(3), &mut Vec::new());
+        assert_eq!(state.octave(), 4);
+        assert_eq!(
+            state.key_range(),
+            KeyRange::octaves(OctaveDivision::SEMITONES, 4, 3)
+        );
+        assert_eq!(state.key_range().lowest(), Note::C4);
+        assert_eq!(state.key_range().highest(), Note::B6);
+    }
+
+    #[test]
+    fn test_octave_shift_changes_played_notes() {
+        let mut state = PianoState::new();
+        assert_eq!(state.octave(), 4);
+
+        let mut actions = Vec::new();
+        state.set_octave(2, &mut actions);
+        assert!(actions.is_empty());
+        assert_eq!(state.octave(), 2);
+        assert_eq!(
+            state.key_range(),
+            KeyRange::octaves(OctaveDivision::SEMITONES, 2, 1)
+        );
+
+        // The same key now plays C2
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C2), true);
+        state.update_gui_keys(pressed_keys, &mut actions);
+        assert_eq!(actions, vec![Action::Pressed(Note::C2)]);
+    }
+
+    #[test]
+    fn test_octave_shift_releases_held_gui_keys() {
+        let mut state = PianoState::new();
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C4), true);
+        pressed_keys.set(index(Note::G4), true);
+        let mut actions = Vec::new();
+        state.update_gui_keys(pressed_keys, &mut actions);
+
+        let mut actions = Vec::new();
+        state.set_octave(5, &mut actions);
+        assert_eq!(
+            actions,
+            vec![Action::Released(Note::C4), Action::Released(Note::G4)]
+        );
+        assert!(state.held_notes().not_any());
+
+        // Pressing the key again after the shift plays the new octave, with nothing left to release
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::C5), true);
+        let mut actions = Vec::new();
+        state.update_gui_keys(pressed_keys, &mut actions);
+        assert_eq!(actions, vec![Action::Pressed(Note::C5)]);
+        let mut actions = Vec::new();
+        state.update_gui_keys(NoteSet::default(), &mut actions);
+        assert_eq!(actions, vec![Action::Released(Note::C5)]);
+    }
+
+    #[test]
+    fn test_octave_shift_with_sustain_keeps_notes_until_pedal_release() {
+        let mut state = PianoState::new();
+        let mut actions = Vec::new();
+        state.update_shift_sustain(true, &mut actions);
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::E4), true);
+        state.update_gui_keys(pressed_keys, &mut actions);
+
+        // Shifting while sustained lets go of the key, but the pedal keeps it sounding
+        let mut actions = Vec::new();
+        state.set_octave(3, &mut actions);
+        assert!(actions.is_empty());
+        assert!(state.held_notes()[index(Note::E4)]);
+
+        // E4 is outside of the shown octave, so it is shown folded onto E3
+        assert!(state.is_gui_sustained(Note::E3));
+
+        // Releasing the pedal releases the note in its original octave
+        let mut actions = Vec::new();
+        state.update_shift_sustain(false, &mut actions);
+        assert_eq!(
+            actions,
+            vec![Action::SustainPedal(false), Action::Released(Note::E4)]
+        );
+        assert!(state.held_notes().not_any());
+    }
+
+    #[test]
+    fn test_octave_shift_is_clamped() {
+        let mut state = PianoState::new();
+        let mut actions = Vec::new();
+        state.set_octave(0, &mut actions);
+        assert_eq!(state.octave(), 0);
+        assert_eq!(state.key_range().lowest(), Note::C0);
+
+        state.set_octave(8, &mut actions);
+        assert_eq!(state.octave(), 8);
+        assert_eq!(state.key_range().highest(), Note::B8);
+
+        state.set_octave(9, &mut actions);
+        assert_eq!(state.octave(), 8);
+
+        // Wider pianos can't go as high
+        state.set_keyboard_size(KeyboardSize::Octaves(3), &mut actions);
+        assert_eq!(state.octave(), 6);
+        assert_eq!(state.key_range().highest(), Note::B8);
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn test_octave_shift_to_same_range_keeps_keys() {
+        let mut state = PianoState::new();
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::D4), true);
+        let mut actions = Vec::new();
+        state.update_gui_keys(pressed_keys, &mut actions);
+
+        let mut actions = Vec::new();
+        state.set_octave(4, &mut actions);
+        assert!(actions.is_empty());
+        assert!(state.held_notes()[index(Note::D4)]);
+
+        // The full size piano doesn't move with the octave
+        state.set_keyboard_size(KeyboardSize::FullPiano, &mut actions);
+        assert_eq!(actions, vec![Action::Released(Note::D4)]);
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::D4), true);
+        let mut actions = Vec::new();
+        state.update_gui_keys(pressed_keys, &mut actions);
+        let mut actions = Vec::new();
+        state.set_octave(2, &mut actions);
+        assert!(actions.is_empty());
+        assert!(state.held_notes()[index(Note::D4)]);
+    }
+
+    #[test]
+    fn test_octave_shift_leaves_external_notes_alone() {
+        let mut state = PianoState::new();
+        state.external_note_on(Note::A4);
+        let mut actions = Vec::new();
+        state.set_octave(6, &mut actions);
+        assert!(actions.is_empty());
+        assert!(state.held_notes()[index(Note::A4)]);
+        assert!(state.is_external_pressed(Note::A6));
+    }
+
+    fn computer_keys(state: &PianoState, semitones: &[u8]) -> NoteSet {
+        let mut keys = NoteSet::default();
+        for &semitone in semitones {
+            keys.set(index(state.computer_key_note(semitone)), true);
+        }
+        keys
+    }
+
+    #[test]
+    fn test_computer_keys_play_current_octave() {
+        let mut state = PianoState::new();
+        assert_eq!(state.computer_key_note(0), Note::C4);
+        assert_eq!(state.computer_key_note(12), Note::C5);
+
+        let mut actions = Vec::new();
+        state.update_computer_keys(computer_keys(&state, &[0, 4]), &mut actions);
+        assert_eq!(
+            actions,
+            vec![Action::Pressed(Note::C4), Action::Pressed(Note::E4)]
+        );
+        assert!(state.is_computer_pressed(Note::C4));
+        assert!(state.held_keys()[Semitone::E.as_index()]);
+
+        let mut actions = Vec::new();
+        state.update_computer_keys(computer_keys(&state, &[4]), &mut actions);
+        assert_eq!(actions, vec![Action::Released(Note::C4)]);
+        assert!(!state.is_computer_pressed(Note::C4));
+    }
+
+    #[test]
+    fn test_computer_key_release_keeps_pointer_hold() {
+        let mut state = PianoState::new();
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::G4), true);
+        let mut actions = Vec::new();
+        state.update_gui_keys(pressed_keys, &mut actions);
+        assert_eq!(actions, vec![Action::Pressed(Note::G4)]);
+
+        // Pressing the same note from the computer keyboard doesn't retrigger it
+        let mut actions = Vec::new();
+        state.update_computer_keys(computer_keys(&state, &[7]), &mut actions);
+        assert!(actions.is_empty());
+
+        // Releasing the computer key leaves the pointer holding the note
+        state.update_computer_keys(NoteSet::default(), &mut actions);
+        assert!(actions.is_empty());
+        assert!(state.held_notes()[index(Note::G4)]);
+
+        // Releasing the pointer finally releases it
+        state.update_gui_keys(NoteSet::default(), &mut actions);
+        assert_eq!(actions, vec![Action::Released(Note::G4)]);
+    }
+
+    #[test]
+    fn test_pointer_release_keeps_computer_key_hold() {
+        let mut state = PianoState::new();
+        let mut actions = Vec::new();
+        state.update_computer_keys(computer_keys(&state, &[2]), &mut actions);
+        let mut pressed_keys = NoteSet::default();
+        pressed_keys.set(index(Note::D4), true);
+        state.update_gui_keys(pressed_keys, &mut actions);
+        assert_eq!(actions, vec![Action::Pressed(Note::D4)]);
+
+        let mut actions = Vec::new();
+        state.update_gui_keys(NoteSet::default(), &mut actions);
+        assert!(actions.is_empty());
+        assert!(state.is_computer_pressed(Note::D4));
+    }
+
+    #[test]
+    fn test_computer_key_release_keeps_midi_hold() {
+        let mut state = PianoState::new();
+        state.external_note_on(Note::A4);
+        let mut actions = Vec::new();
+        state.update_computer_keys(computer_keys(&state, &[9]), &mut actions);
+        assert_eq!(actions, vec![Action::Pressed(Note::A4)]);
+
+        // The synth releases all voices of a note at once, so the release is left to the MIDI note off
+        let mut actions = Vec::new();
+        state.update_computer_keys(NoteSet::default(), &mut actions);
+        assert!(actions.is_empty());
+        assert!(state.is_external_pressed(Note::A4));
+
+        // Same thing when the computer key was sustained
+        state.update_shift_sustain(true, &mut actions);
+        state.update_computer_keys(computer_keys(&state, &[9]), &mut actions);
+        state.update_computer_keys(NoteSet::default(), &mut actions);
+        let mut actions = Vec::new();
+        state.update_shift_sustain(false, &mut actions);
+        assert_eq!(actions, vec![Action::SustainPedal(false)]);
+    }
+
+    #[test]
+    fn test_computer_keys_with_sustain() {
+        let mut state = PianoState::new();
+        let mut actions = Vec::new();
+        state.update_shift_sustain(true, &mut actions);
+        state.update_computer_keys(computer_keys(&state, &[5]), &mut actions);
+
+        let mut actions = Vec::new();
+        state.update_computer_keys(NoteSet::default(), &mut actions);
+        assert!(actions.is_empty());
+        assert!(state.is_gui_sustained(Note::F4));
+
+        state.update_shift_sustain(false, &mut actions);
+        assert_eq!(
+            actions,
+            vec![Action::SustainPedal(false), Action::Released(Note::F4)]
+        );
+    }
+
+    #[test]
+    fn test_computer_keys_follow_octave_shift() {
+        let mut state = PianoState::new();
+        let mut actions = Vec::new();
+        state.update_computer_keys(computer_keys(&state, &[0]), &mut actions);
+
+        // While the key stays down the piano sends the note of the new octave on the next update
+        let mut actions = Vec::new();
+        state.set_octave(5, &mut actions);
+        assert_eq!(state.computer_key_note(0), Note::C5);
+        state.update_computer_keys(computer_keys(&state, &[0]), &mut actions);
+        assert_eq!(
+            actions,
+            vec![Action::Released(Note::C4), Action::Pressed(Note::C5)]
+        );
+
+        // The full size piano doesn't move, but the computer keyboard still changes octave
+        state.set_keyboard_size(KeyboardSize::FullPiano, &mut actions);
+        let mut actions = Vec::new();
+        state.set_octave(2, &mut actions);
+        assert!(actions.is_empty());
+        assert_eq!(state.computer_key_note(0), Note::C2);
+    }
+
+    #[test]
+    fn test_octave_division_keys() {
+        let mut state = PianoState::new();
+        let division = OctaveDivision::new(31);
+        let mut actions = Vec::new();
+        state.set_division(division, &mut actions);
+        let range = state.key_range();
+        assert_eq!(range.lowest(), Note::C4);
+        assert_eq!(range.len(), 31);
+
+        // Every key is a step, so the computer keyboard plays consecutive MIDI notes from middle C
+        let step = |steps: u8| Note::from_u8_lossy(u8::from(Note::C4) + steps);
+        assert_eq!(state.computer_key_note(0), Note::C4);
+        assert_eq!(state.computer_key_note(5), step(5));
+        // The white keys are the steps closest to the white keys of a regular piano, with D 5 steps up
+        assert!(!division.is_black_key(step(0)));
+        assert!(division.is_black_key(step(1)));
+        assert!(!division.is_black_key(step(5)));
+        assert_eq!(division.octave_of(step(30)), 4);
+        assert_eq!(division.octave_of(step(31)), 5);
+
+        // Notes fold by 31 steps, and don't count as pitch classes of 12
+        state.external_note_on(step(31 + 10));
+        assert!(state.is_external_pressed(step(10)));
+        assert!(state.held_keys().not_any());
+
+        // Wide octaves leave room for fewer of them, the lowest octave starts where it still fits
+        let division = OctaveDivision::new(53);
+        state.set_division(division, &mut actions);
+        state.set_keyboard_size(KeyboardSize::Octaves(4), &mut actions);
+        assert_eq!(state.octave(), 3);
+        let range = state.key_range();
+        assert_eq!(range.lowest(), Note::from_u8_lossy(u8::from(Note::C4) - 53));
+        assert_eq!(range.highest(), Note::HIGHEST_NOTE);
+        state.set_octave(0, &mut actions);
+        assert_eq!(state.octave(), 3);
+    }
 }
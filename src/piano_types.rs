@@ -109,21 +109,6 @@ impl Semitone {
         }
     }
 
-    /// Convert this semitone to a Note in the specified octave
-    pub fn to_note_in_octave(self, octave: u8) -> Note {
-        debug_assert!(
-            octave <= 9,
-            "Octave must be in range 0-9 for valid MIDI notes"
-        );
-
-        // Calculate the MIDI note number: (octave + 1) * 12 + semitone
-        // The +1 is because MIDI octave numbering starts at -1, so C4 = 60
-        let midi_note = (octave as usize + 1) * 12 + self.as_usize();
-        debug_assert!(midi_note <= 127, "MIDI note number must be <= 127");
-
-        Note::try_from(midi_note as u8).unwrap()
-    }
-
     /// Convert a Note to its semitone representation (0-11) within its octave
     pub fn from_note(note: Note) -> Self {
         Self::new(u8::from(note) % 12)
@@ -133,16 +118,6 @@ impl Semitone {
     pub fn iter() -> impl Iterator<Item = Semitone> {
         (0..12).map(Semitone::new)
     }
-
-    /// Create an iterator over white key semitones (C, D, E, F, G, A, B)
-    pub fn white_keys() -> impl Iterator<Item = Semitone> {
-        [0, 2, 4, 5, 7, 9, 11].into_iter().map(Semitone::new)
-    }
-
-    /// Create an iterator over black key semitones (C#, D#, F#, G#, A#)
-    pub fn black_keys() -> impl Iterator<Item = Semitone> {
-        [1, 3, 6, 8, 10].into_iter().map(Semitone::new)
-    }
 }
 
 /// Identifies a pointer (mouse or touch) in the GUI
@@ -156,6 +131,229 @@ pub enum PointerId {
 /// Used for tracking which keys are pressed in the piano GUI
 pub type KeySet = BitArr!(for 12, in u16, Msb0);
 
-/// A set of external MIDI keys across all octaves (128 notes)
-/// Used for tracking which MIDI keys are pressed from external sources
-pub type ExternalKeySet = BitArr!(for 128, in u32, Msb0);
+/// A set of MIDI notes across all octaves (128 notes)
+/// Used for tracking which actual notes are held, from the GUI or from external sources
+pub type NoteSet = BitArr!(for 128, in u32, Msb0);
+
+/// How an octave is divided into keys: the 12 semitones of a regular piano, or the steps of an equal
+/// division of the octave with one MIDI note per step.
+/// Octaves are counted the same way for every division, with middle C starting octave 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OctaveDivision {
+    steps: u8,
+}
+
+impl OctaveDivision {
+    pub const SEMITONES: Self = Self { steps: 12 };
+
+    pub fn new(steps: u8) -> Self {
+        debug_assert!(
+            (12..=64).contains(&steps),
+            "An octave must fit the computer keyboard and at least twice in the MIDI range"
+        );
+        Self { steps }
+    }
+
+    /// Number of keys in an octave
+    pub fn steps(self) -> u8 {
+        self.steps
+    }
+
+    pub fn is_semitones(self) -> bool {
+        self == Self::SEMITONES
+    }
+
+    /// MIDI note number of the first key of `octave`, negative if it is below the MIDI range
+    pub fn octave_start(self, octave: u8) -> i16 {
+        const MIDDLE_C: i16 = 60;
+        const MIDDLE_C_OCTAVE: i16 = 4;
+        MIDDLE_C + (i16::from(octave) - MIDDLE_C_OCTAVE) * i16::from(self.steps)
+    }
+
+    /// The octave that `note` is in
+    pub fn octave_of(self, note: Note) -> i16 {
+        (i16::from(u8::from(note)) - self.octave_start(0)).div_euclid(i16::from(self.steps))
+    }
+
+    /// Number of steps from the first key of the octave to `note`
+    pub fn step_in_octave(self, note: Note) -> u8 {
+        (i16::from(u8::from(note)) - self.octave_start(0)).rem_euclid(i16::from(self.steps)) as u8
+    }
+
+    /// The lowest octave that starts within the MIDI range
+    pub fn min_octave(self) -> u8 {
+        (0..)
+            .find(|&octave| self.octave_start(octave) >= 0)
+            .unwrap()
+    }
+
+    /// Whether `note` is drawn as a black key. The white keys are the steps closest to the white keys
+    /// of a regular piano, which gives the usual layouts of for example 19 and 31 steps.
+    pub fn is_black_key(self, note: Note) -> bool {
+        const WHITE_KEY_SEMITONES: [u16; 7] = [0, 2, 4, 5, 7, 9, 11];
+        let steps = u16::from(self.steps);
+        let step = u16::from(self.step_in_octave(note));
+        !WHITE_KEY_SEMITONES
+            .iter()
+            .any(|&semitone| (semitone * steps + 6) / 12 == step)
+    }
+}
+
+/// A contiguous range of MIDI notes, shown as keys on the on-screen piano
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRange {
+    lowest: Note,
+    highest: Note,
+    division: OctaveDivision,
+}
+
+impl KeyRange {
+    /// The 88 keys of a standard piano, A0 to C8
+    pub const FULL_PIANO: Self = Self {
+        lowest: Note::A0,
+        highest: Note::C8,
+        division: OctaveDivision::SEMITONES,
+    };
+
+    pub fn new(lowest: Note, highest: Note, division: OctaveDivision) -> Self {
+        debug_assert!(
+            u8::from(highest) - u8::from(lowest) >= division.steps() - 1,
+            "Key range must span at least an octave so every note can be folded into it"
+        );
+        Self {
+            lowest,
+            highest,
+            division,
+        }
+    }
+
+    /// All 128 MIDI notes
+    pub fn all_notes(division: OctaveDivision) -> Self {
+        Self::new(Note::LOWEST_NOTE, Note::HIGHEST_NOTE, division)
+    }
+
+    /// The whole octaves from the first key of `lowest_octave` up to the last key of the octave
+    /// `num_octaves - 1` above it. Octaves that don't fit in the MIDI range are cut off at its top.
+    pub fn octaves(division: OctaveDivision, lowest_octave: u8, num_octaves: u8) -> Self {
+        debug_assert!(num_octaves >= 1);
+        debug_assert!(lowest_octave >= division.min_octave());
+        let lowest = division.octave_start(lowest_octave);
+        let highest = lowest + i16::from(num_octaves) * i16::from(division.steps()) - 1;
+        let highest_note = i16::from(u8::from(Note::HIGHEST_NOTE));
+        Self::new(
+            Note::from_u8_lossy(lowest as u8),
+            Note::from_u8_lossy(highest.min(highest_note) as u8),
+            division,
+        )
+    }
+
+    pub fn lowest(self) -> Note {
+        self.lowest
+    }
+
+    pub fn highest(self) -> Note {
+        self.highest
+    }
+
+    pub fn division(self) -> OctaveDivision {
+        self.division
+    }
+
+    /// Number of keys in the range
+    pub fn len(self) -> usize {
+        (u8::from(self.highest) - u8::from(self.lowest)) as usize + 1
+    }
+
+    pub fn contains(self, note: Note) -> bool {
+        (self.lowest..=self.highest).contains(&note)
+    }
+
+    /// Iterate over all notes in the range, from lowest to highest
+    pub fn notes(self) -> impl Iterator<Item = Note> {
+        (u8::from(self.lowest)..=u8::from(self.highest)).map(|n| Note::try_from(n).unwrap())
+    }
+
+    /// Move `note` by whole octaves until it is within the range.
+    /// Notes already in the range are returned unchanged.
+    pub fn fold(self, note: Note) -> Note {
+        let octave = self.division.steps();
+        let mut value = u8::from(note);
+        while value < u8::from(self.lowest) {
+            value += octave;
+        }
+        while value > u8::from(self.highest) {
+            value -= octave;
+        }
+        debug_assert!(value >= u8::from(self.lowest));
+        Note::try_from(value).unwrap()
+    }
+}
+
+/// The selectable sizes of the on-screen piano
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardSize {
+    /// Whole octaves starting at C, positioned by the piano's octave
+    Octaves(u8),
+    /// All 88 keys of a standard piano, or every MIDI note when the octave has other divisions than semitones
+    FullPiano,
+}
+
+impl KeyboardSize {
+    pub const PRESETS: [Self; 5] = [
+        Self::Octaves(1),
+        Self::Octaves(2),
+        Self::Octaves(3),
+        Self::Octaves(4),
+        Self::FullPiano,
+    ];
+
+    /// The keys shown when the lowest shown octave is `octave`
+    pub fn key_range(self, octave: u8, division: OctaveDivision) -> KeyRange {
+        match self {
+            Self::Octaves(num_octaves) => KeyRange::octaves(division, octave, num_octaves),
+            Self::FullPiano if division.is_semitones() => KeyRange::FULL_PIANO,
+            Self::FullPiano => KeyRange::all_notes(division),
+        }
+    }
+
+    /// The highest octave that the lowest shown octave can be moved to
+    pub fn max_octave(self, division: OctaveDivision) -> u8 {
+        let highest_start = match self {
+            // The highest octave where all keys have MIDI notes
+            Self::Octaves(num_octaves) => {
+                i16::from(u8::from(Note::HIGHEST_NOTE)) + 1
+                    - i16::from(num_octaves) * i16::from(division.steps())
+            }
+            // The full piano doesn't depend on the octave, so allow any octave where the computer keyboard,
+            // which plays a bit more than an octave, stays within the MIDI range
+            Self::FullPiano => i16::from(u8::from(Note::HIGHEST_NOTE)) - 12,
+        };
+        (division.min_octave()..)
+            .take_while(|&octave| division.octave_start(octave) <= highest_start)
+            .last()
+            // Octaves that are too large for the MIDI range are cut off at the top
+            .unwrap_or(division.min_octave())
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            Self::Octaves(1) => "1 octave".to_string(),
+            Self::Octaves(num_octaves) => format!("{num_octaves} octaves"),
+            Self::FullPiano => "88 keys".to_string(),
+        }
+    }
+
+    /// Short identifier used when persisting the setting
+    pub fn storage_key(self) -> String {
+        match self {
+            Self::Octaves(num_octaves) => num_octaves.to_string(),
+            Self::FullPiano => "88".to_string(),
+        }
+    }
+
+    pub fn from_storage_key(key: &str) -> Option<Self> {
+        Self::PRESETS
+            .into_iter()
+            .find(|size| size.storage_key() == key)
+    }
+}
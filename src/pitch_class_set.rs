@@ -0,0 +1,423 @@
+//! Pitch-class set analysis of the held keys, as used in post-tonal music theory.
+//!
+//! Prime forms follow Rahn's algorithm, which differs from Forte's original for a handful of set classes
+//! such as 5-20. The Forte numbers are the same either way.
+
+use std::fmt::Write as _;
+use std::sync::LazyLock;
+
+use crate::piano_types::{KeySet, Semitone};
+
+const PITCH_CLASSES: usize = 12;
+
+/// Pitch classes ordered from the first note of the set upwards
+pub type PitchClasses = Vec<u8>;
+
+/// Everything the analysis finds out about a set of held keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetAnalysis {
+    pub normal_form: Vec<Semitone>,
+    pub prime_form: PitchClasses,
+    pub forte_number: String,
+    pub interval_vector: [u8; 6],
+    /// The keys that aren't held
+    pub complement: KeySet,
+    pub complement_forte_number: String,
+    /// The set class with the same interval vector, for the sets that have one
+    pub z_partner: Option<String>,
+}
+
+impl SetAnalysis {
+    /// `None` if no keys are held
+    pub fn new(keys: &KeySet) -> Option<Self> {
+        if keys.not_any() {
+            return None;
+        }
+        let complement = complement(keys);
+        Some(Self {
+            normal_form: normal_form(keys).into_iter().map(Semitone::new).collect(),
+            prime_form: prime_form(keys),
+            forte_number: forte_number(keys),
+            interval_vector: interval_vector(keys),
+            complement,
+            complement_forte_number: forte_number(&complement),
+            z_partner: z_partner(keys),
+        })
+    }
+}
+
+fn pitch_classes(keys: &KeySet) -> PitchClasses {
+    keys.iter_ones().map(|pc| pc as u8).collect()
+}
+
+fn key_set(pitch_classes: &[u8]) -> KeySet {
+    let mut keys = KeySet::default();
+    for &pc in pitch_classes {
+        keys.set(usize::from(pc) % PITCH_CLASSES, true);
+    }
+    keys
+}
+
+/// The pitch classes that aren't in `keys`
+fn complement(keys: &KeySet) -> KeySet {
+    let mut complement = !*keys;
+    // The bits beyond the 12 pitch classes are only there to fill out the storage
+    complement[PITCH_CLASSES..].fill(false);
+    complement
+}
+
+fn interval(from: u8, to: u8) -> u8 {
+    (to + PITCH_CLASSES as u8 - from) % PITCH_CLASSES as u8
+}
+
+/// What makes an ordering of the set more compact: the span from the first to the last note,
+/// then the spans to the second to last note, the third to last and so on
+fn compactness(ordering: &[u8]) -> Vec<u8> {
+    let Some(&first) = ordering.first() else {
+        return Vec::new();
+    };
+    ordering
+        .iter()
+        .rev()
+        .map(|&pc| interval(first, pc))
+        .collect()
+}
+
+/// The most compact ordering of `pitch_classes`, which must be sorted
+fn most_compact(pitch_classes: &[u8]) -> PitchClasses {
+    (0..pitch_classes.len())
+        .map(|start| {
+            let mut rotation = pitch_classes.to_vec();
+            rotation.rotate_left(start);
+            rotation
+        })
+        // Ties are only possible for symmetrical sets, where they start on the lowest pitch class
+        .min_by_key(|rotation| compactness(rotation))
+        .unwrap_or_default()
+}
+
+/// The set in its most compact ordering, starting from its first note
+pub fn normal_form(keys: &KeySet) -> PitchClasses {
+    most_compact(&pitch_classes(keys))
+}
+
+/// Normal form transposed to start on 0
+fn transposed_to_zero(normal_form: &[u8]) -> PitchClasses {
+    let Some(&first) = normal_form.first() else {
+        return Vec::new();
+    };
+    normal_form.iter().map(|&pc| interval(first, pc)).collect()
+}
+
+/// The most compact of the normal forms of the set and its inversion, transposed to start on 0.
+/// All sets related by transposition or inversion share a prime form.
+pub fn prime_form(keys: &KeySet) -> PitchClasses {
+    let inversion = key_set(
+        &keys
+            .iter_ones()
+            .map(|pc| interval(pc as u8, 0))
+            .collect::<Vec<_>>(),
+    );
+    let original = transposed_to_zero(&normal_form(keys));
+    let inverted = transposed_to_zero(&normal_form(&inversion));
+    std::cmp::min_by_key(original, inverted, |form| compactness(form))
+}
+
+/// Number of pairs of notes a distance of each interval class 1 to 6 apart
+pub fn interval_vector(keys: &KeySet) -> [u8; 6] {
+    let mut vector = [0; 6];
+    let pitch_classes = pitch_classes(keys);
+    for (i, &a) in pitch_classes.iter().enumerate() {
+        for &b in &pitch_classes[i + 1..] {
+            let distance = interval(a, b);
+            let interval_class = distance.min(PITCH_CLASSES as u8 - distance);
+            vector[usize::from(interval_class) - 1] += 1;
+        }
+    }
+    vector
+}
+
+/// A set class with its Forte number
+struct SetClass {
+    forte_number: String,
+    prime_form: PitchClasses,
+    interval_vector: [u8; 6],
+}
+
+/// Forte's numbering of the set classes of 2 to 6 notes, in order, as the set classes of the larger sets
+/// are numbered after their complements. A Z marks sets that share their interval vector with another set class.
+const FORTE_TABLE: [&[&str]; 5] = [
+    &["01", "02", "03", "04", "05", "06"],
+    &[
+        "012", "013", "014", "015", "016", "024", "025", "026", "027", "036", "037", "048",
+    ],
+    &[
+        "0123", "0124", "0134", "0125", "0126", "0127", "0145", "0156", "0167", "0235", "0135",
+        "0236", "0136", "0237", "Z0146", "0157", "0347", "0147", "0148", "0158", "0246", "0247",
+        "0257", "0248", "0268", "0358", "0258", "0369", "Z0137",
+    ],
+    &[
+        "01234", "01235", "01245", "01236", "01237", "01256", "01267", "02346", "01246", "01346",
+        "02347", "Z01356", "01248", "01257", "01268", "01347", "Z01348", "Z01457", "01367",
+        "01568", "01458", "01478", "02357", "01357", "02358", "02458", "01358", "02368", "01368",
+        "01468", "01369", "01469", "02468", "02469", "02479", "Z01247", "Z03458", "Z01258",
+    ],
+    &[
+        "012345", "012346", "Z012356", "Z012456", "012367", "Z012567", "012678", "023457",
+        "012357", "Z013457", "Z012457", "Z012467", "Z013467", "013458", "012458", "014568",
+        "Z012478", "012578", "Z013478", "014589", "023468", "012468", "Z023568", "Z013468",
+        "Z013568", "Z013578", "013469", "Z013569", "Z023679", "013679", "014579", "024579",
+        "023579", "013579", "02468A", "Z012347", "Z012348", "Z012378", "Z023458", "Z012358",
+        "Z012368", "Z012369", "Z012568", "Z012569", "Z023469", "Z012469", "Z012479", "Z012579",
+        "Z013479", "Z014679",
+    ],
+];
+
+/// Every set class, from the empty set to the aggregate of all 12 pitch classes
+static SET_CLASSES: LazyLock<Vec<SetClass>> = LazyLock::new(|| {
+    let set_class = |forte_number: String, keys: &KeySet| SetClass {
+        forte_number,
+        prime_form: prime_form(keys),
+        interval_vector: interval_vector(keys),
+    };
+    let mut set_classes = Vec::new();
+    // Forte didn't number the sets of fewer than 3 or more than 9 notes, these are the usual extensions
+    for cardinality in [0, 1, 11, 12] {
+        let keys = key_set(&(0..cardinality).collect::<Vec<_>>());
+        set_classes.push(set_class(format!("{cardinality}-1"), &keys));
+    }
+    for (index, table) in FORTE_TABLE.iter().enumerate() {
+        let cardinality = index + 2;
+        for (ordinal, entry) in (1..).zip(table.iter()) {
+            let (z, digits) = match entry.strip_prefix('Z') {
+                Some(digits) => ("Z", digits),
+                None => ("", *entry),
+            };
+            let pitch_classes: Vec<u8> = digits
+                .chars()
+                .map(|digit| digit.to_digit(PITCH_CLASSES as u32).unwrap() as u8)
+                .collect();
+            let keys = key_set(&pitch_classes);
+            set_classes.push(set_class(format!("{cardinality}-{z}{ordinal}"), &keys));
+            // Hexachords are their own complements' set class or their Z partner's
+            if cardinality < 6 {
+                set_classes.push(set_class(
+                    format!("{}-{z}{ordinal}", PITCH_CLASSES - cardinality),
+                    &complement(&keys),
+                ));
+            }
+        }
+    }
+    set_classes
+});
+
+fn set_class(keys: &KeySet) -> &'static SetClass {
+    let prime_form = prime_form(keys);
+    SET_CLASSES
+        .iter()
+        .find(|set_class| set_class.prime_form == prime_form)
+        .expect("every set belongs to a set class")
+}
+
+/// Forte's name for the set class, such as 3-11 for major and minor triads
+pub fn forte_number(keys: &KeySet) -> String {
+    set_class(keys).forte_number.clone()
+}
+
+/// The other set class with the same interval vector and number of notes, for Z-related sets
+pub fn z_partner(keys: &KeySet) -> Option<String> {
+    let own = set_class(keys);
+    SET_CLASSES
+        .iter()
+        .find(|set_class| {
+            set_class.interval_vector == own.interval_vector
+                && set_class.prime_form.len() == own.prime_form.len()
+                && set_class.prime_form != own.prime_form
+        })
+        .map(|set_class| set_class.forte_number.clone())
+}
+
+/// Pitch classes written the usual way, with A and B for 10 and 11, such as (0148)
+pub fn format_pitch_classes(pitch_classes: &[u8]) -> String {
+    let mut text = String::from("(");
+    for &pc in pitch_classes {
+        write!(text, "{}", char::from_digit(u32::from(pc), 12).unwrap()).unwrap();
+    }
+    text.push(')');
+    text.to_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn keys(pitch_classes: &[u8]) -> KeySet {
+        key_set(pitch_classes)
+    }
+
+    #[test]
+    fn test_set_analysis() {
+        // (held pitch classes, normal form, prime form, Forte number, interval vector)
+        type Case = (
+            &'static [u8],
+            &'static [u8],
+            &'static [u8],
+            &'static str,
+            [u8; 6],
+        );
+        let cases: &[Case] = &[
+            // C major and A minor triads are both 3-11
+            (
+                &[0, 4, 7],
+                &[0, 4, 7],
+                &[0, 3, 7],
+                "3-11",
+                [0, 0, 1, 1, 1, 0],
+            ),
+            (
+                &[9, 0, 4],
+                &[9, 0, 4],
+                &[0, 3, 7],
+                "3-11",
+                [0, 0, 1, 1, 1, 0],
+            ),
+            // Dominant seventh
+            (
+                &[7, 11, 2, 5],
+                &[11, 2, 5, 7],
+                &[0, 2, 5, 8],
+                "4-27",
+                [0, 1, 2, 1, 1, 1],
+            ),
+            (
+                &[0, 3, 6, 9],
+                &[0, 3, 6, 9],
+                &[0, 3, 6, 9],
+                "4-28",
+                [0, 0, 4, 0, 0, 2],
+            ),
+            // The all-interval tetrachords
+            (
+                &[0, 1, 4, 6],
+                &[0, 1, 4, 6],
+                &[0, 1, 4, 6],
+                "4-Z15",
+                [1, 1, 1, 1, 1, 1],
+            ),
+            (
+                &[0, 1, 3, 7],
+                &[0, 1, 3, 7],
+                &[0, 1, 3, 7],
+                "4-Z29",
+                [1, 1, 1, 1, 1, 1],
+            ),
+            // Rahn's prime form differs from Forte's (01378)
+            (
+                &[0, 1, 3, 7, 8],
+                &[7, 8, 0, 1, 3],
+                &[0, 1, 5, 6, 8],
+                "5-20",
+                [2, 1, 1, 2, 3, 1],
+            ),
+            // Pentatonic and diatonic scales
+            (
+                &[0, 2, 4, 7, 9],
+                &[0, 2, 4, 7, 9],
+                &[0, 2, 4, 7, 9],
+                "5-35",
+                [0, 3, 2, 1, 4, 0],
+            ),
+            (
+                &[0, 2, 4, 5, 7, 9, 11],
+                &[11, 0, 2, 4, 5, 7, 9],
+                &[0, 1, 3, 5, 6, 8, 10],
+                "7-35",
+                [2, 5, 4, 3, 6, 1],
+            ),
+            (
+                &[0, 2, 4, 6, 8, 10],
+                &[0, 2, 4, 6, 8, 10],
+                &[0, 2, 4, 6, 8, 10],
+                "6-35",
+                [0, 6, 0, 6, 0, 3],
+            ),
+            (&[5], &[5], &[0], "1-1", [0; 6]),
+        ];
+        for &(held, normal, prime, forte, vector) in cases {
+            let analysis = SetAnalysis::new(&keys(held)).unwrap();
+            let normal: Vec<Semitone> = normal.iter().map(|&pc| Semitone::new(pc)).collect();
+            assert_eq!(analysis.normal_form, normal, "normal form of {held:?}");
+            assert_eq!(analysis.prime_form, prime, "prime form of {held:?}");
+            assert_eq!(analysis.forte_number, forte, "Forte number of {held:?}");
+            assert_eq!(
+                analysis.interval_vector, vector,
+                "interval vector of {held:?}"
+            );
+        }
+        assert_eq!(SetAnalysis::new(&KeySet::default()), None);
+    }
+
+    #[test]
+    fn test_complements() {
+        let major_triad = SetAnalysis::new(&keys(&[0, 4, 7])).unwrap();
+        assert_eq!(major_triad.complement, keys(&[1, 2, 3, 5, 6, 8, 9, 10, 11]));
+        assert_eq!(major_triad.complement_forte_number, "9-11");
+        // The diatonic scale is the complement of the pentatonic scale
+        let diatonic = SetAnalysis::new(&keys(&[0, 2, 4, 5, 7, 9, 11])).unwrap();
+        assert_eq!(diatonic.complement_forte_number, "5-35");
+        // A Z hexachord's complement is its Z partner, other hexachords are their own complement's set class
+        let hexachord = SetAnalysis::new(&keys(&[0, 1, 2, 3, 5, 6])).unwrap();
+        assert_eq!(hexachord.forte_number, "6-Z3");
+        assert_eq!(hexachord.complement_forte_number, "6-Z36");
+        let whole_tone = SetAnalysis::new(&keys(&[0, 2, 4, 6, 8, 10])).unwrap();
+        assert_eq!(whole_tone.complement_forte_number, "6-35");
+        assert_eq!(forte_number(&complement(&KeySet::default())), "12-1");
+    }
+
+    #[test]
+    fn test_z_partners() {
+        let cases = [
+            (&[0, 1, 4, 6][..], Some("4-Z29")),
+            (&[0, 1, 3, 7], Some("4-Z15")),
+            (&[0, 1, 3, 5, 6], Some("5-Z36")),
+            (&[0, 1, 2, 3, 5, 6], Some("6-Z36")),
+            (&[1, 2, 6, 7, 9, 10, 11], Some("7-Z17")),
+            (&[0, 4, 7], None),
+        ];
+        for (held, partner) in cases {
+            assert_eq!(z_partner(&keys(held)).as_deref(), partner, "{held:?}");
+        }
+    }
+
+    #[test]
+    fn test_every_set_has_exactly_one_set_class() {
+        // There are 224 set classes under transposition and inversion
+        assert_eq!(SET_CLASSES.len(), 224);
+        let prime_forms: HashSet<&PitchClasses> = SET_CLASSES
+            .iter()
+            .map(|set_class| &set_class.prime_form)
+            .collect();
+        assert_eq!(prime_forms.len(), SET_CLASSES.len());
+        for bits in 0u16..1 << PITCH_CLASSES {
+            let pitch_classes: Vec<u8> = (0..PITCH_CLASSES as u8)
+                .filter(|pc| bits & 1 << pc != 0)
+                .collect();
+            let keys = keys(&pitch_classes);
+            let name = forte_number(&keys);
+            assert!(
+                name.starts_with(&format!("{}-", pitch_classes.len())),
+                "{pitch_classes:?} named {name}"
+            );
+            // Exactly the sets marked with a Z share their interval vector with another set class
+            assert_eq!(name.contains('Z'), z_partner(&keys).is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_format_pitch_classes() {
+        assert_eq!(format_pitch_classes(&[0, 3, 7]), "(037)");
+        assert_eq!(format_pitch_classes(&[0, 2, 4, 6, 8, 10]), "(02468A)");
+        assert_eq!(format_pitch_classes(&[0, 1, 11]), "(01B)");
+    }
+}
@@ -0,0 +1,320 @@
+use crate::interval::ConsonanceModel;
+use itertools::Itertools as _;
+use shared_types::{SynthSpectrum, inharmonicity::InharmonicityModel};
+use wmidi::Note;
+
+/// A single sinusoidal component of a sound
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Partial {
+    pub frequency: f32,
+    pub amplitude: f32,
+}
+
+/// Sensory roughness between two partials according to the Plomp-Levelt critical band model.
+///
+/// Uses the parametrisation of the Plomp-Levelt curve from Sethares, "Tuning, Timbre, Spectrum, Scale".
+/// The roughness is zero at unison, peaks at roughly a quarter of the critical bandwidth
+/// and then decays as the partials move apart.
+pub fn pair_roughness(a: Partial, b: Partial) -> f32 {
+    // Rates of the two exponentials whose difference forms the roughness curve
+    const RISE_RATE: f32 = 3.5;
+    const DECAY_RATE: f32 = 5.75;
+
+    debug_assert!(a.frequency >= 0.0 && b.frequency >= 0.0);
+    debug_assert!(a.amplitude >= 0.0 && b.amplitude >= 0.0);
+    let (low, high) = if a.frequency <= b.frequency {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    // Distance between the partials in critical bands
+    let distance = (high.frequency - low.frequency) / critical_bandwidth(low.frequency);
+    low.amplitude.min(high.amplitude)
+        * ((-RISE_RATE * distance).exp() - (-DECAY_RATE * distance).exp())
+}
+
+/// Critical bandwidth in Hz around `frequency`, from the same fit by Sethares that [`pair_roughness`] uses.
+/// Partials closer together than this interfere with each other and are heard as beating or roughness.
+pub fn critical_bandwidth(frequency: f32) -> f32 {
+    // Linear approximation of how the distance of maximum roughness grows with frequency
+    const BANDWIDTH_SLOPE: f32 = 0.0207;
+    const BANDWIDTH_OFFSET_HZ: f32 = 18.96;
+    // Point of maximum roughness, in units of the critical bandwidth
+    const MAX_ROUGHNESS_POINT: f32 = 0.24;
+
+    debug_assert!(frequency >= 0.0);
+    BANDWIDTH_SLOPE.mul_add(frequency, BANDWIDTH_OFFSET_HZ) / MAX_ROUGHNESS_POINT
+}
+
+/// Total sensory roughness of a sound made up of the given partials.
+///
+/// This is the sum of the pairwise roughness of every pair of partials, so the partials of
+/// all notes of a chord can be passed together to get a single score for the whole chord.
+pub fn roughness(partials: &[Partial]) -> f32 {
+    partials
+        .iter()
+        .tuple_combinations()
+        .map(|(a, b)| pair_roughness(*a, *b))
+        .sum()
+}
+
+/// How the partials of a [`Spectrum`] deviate from integer multiples of the fundamental
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Inharmonicity {
+    /// Perfectly harmonic partials
+    None,
+    /// Stiff string inharmonicity with a fixed coefficient
+    Coefficient(f32),
+    /// The partial ratios of a synth spectrum, stretched by its piano string inharmonicity which varies with the note
+    Synth(SynthSpectrum),
+}
+
+/// Model of the partials of a tone, used to calculate roughness
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    /// Amplitudes of the partials, starting with the fundamental
+    amplitudes: Vec<f32>,
+    inharmonicity: Inharmonicity,
+}
+
+impl Spectrum {
+    /// Name of the roughness as a [`ConsonanceModel`]
+    pub const NAME: &'static str = "roughness";
+
+    pub fn new(amplitudes: Vec<f32>, inharmonicity: Inharmonicity) -> Self {
+        debug_assert!(amplitudes.iter().all(|&a| a >= 0.0));
+        Self {
+            amplitudes,
+            inharmonicity,
+        }
+    }
+
+    /// The sustained sound of the synth in the audio worklet when it plays `spectrum`, at medium velocity
+    pub fn synth(spectrum: &SynthSpectrum) -> Self {
+        Self::new(
+            spectrum.amplitudes.to_vec(),
+            Inharmonicity::Synth(*spectrum),
+        )
+    }
+
+    /// The synth's default piano spectrum
+    pub fn piano() -> Self {
+        Self::synth(&SynthSpectrum::default())
+    }
+
+    /// Harmonic spectrum with amplitudes falling off as 1/n, like a sawtooth wave
+    pub fn sawtooth(num_partials: usize) -> Self {
+        Self::new(
+            (1..=num_partials).map(|n| 1.0 / n as f32).collect(),
+            Inharmonicity::None,
+        )
+    }
+
+    /// The audible partials of `note` when played with this spectrum at `fundamental` Hz
+    pub fn partials(&self, note: Note, fundamental: f32) -> impl Iterator<Item = Partial> + '_ {
+        let (model, synth_spectrum) = match self.inharmonicity {
+            Inharmonicity::None => (InharmonicityModel::from_coefficient(0.0), None),
+            Inharmonicity::Coefficient(coefficient) => {
+                (InharmonicityModel::from_coefficient(coefficient), None)
+            }
+            Inharmonicity::Synth(spectrum) => {
+                (spectrum.inharmonicity(u8::from(note)), Some(spectrum))
+            }
+        };
+        self.amplitudes
+            .iter()
+            .enumerate()
+            .filter(|&(_, &amplitude)| amplitude > 0.0)
+            .map(move |(index, &amplitude)| Partial {
+                frequency: match &synth_spectrum {
+                    Some(spectrum) => spectrum.partial_frequency(&model, fundamental, index),
+                    None => model.partial_frequency(fundamental, index as u32 + 1),
+                },
+                amplitude,
+            })
+    }
+}
+
+impl ConsonanceModel for Spectrum {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn dissonance(&self, root: Note, fundamental: f32, cents: f32) -> f32 {
+        const CENTS_PER_OCTAVE: f32 = 1200.0;
+        dyad_roughness(self, root, fundamental, 2f32.powf(cents / CENTS_PER_OCTAVE))
+    }
+}
+
+/// Roughness of two tones with the same spectrum, the lower at `fundamental` Hz and the upper `ratio` times higher.
+/// Both get the inharmonicity of `note`, which should be one of them or close to them.
+pub fn dyad_roughness(spectrum: &Spectrum, note: Note, fundamental: f32, ratio: f32) -> f32 {
+    let partials: Vec<Partial> = spectrum
+        .partials(note, fundamental)
+        .chain(spectrum.partials(note, fundamental * ratio))
+        .collect();
+    roughness(&partials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32) -> Partial {
+        Partial {
+            frequency,
+            amplitude: 1.0,
+        }
+    }
+
+    /// Sethares' reference timbre: 6 harmonic partials with amplitudes falling off by 0.88
+    fn sethares_spectrum() -> Spectrum {
+        const NUM_PARTIALS: i32 = 6;
+        const AMPLITUDE_FALLOFF: f32 = 0.88;
+        Spectrum::new(
+            (0..NUM_PARTIALS)
+                .map(|n| AMPLITUDE_FALLOFF.powi(n))
+                .collect(),
+            Inharmonicity::None,
+        )
+    }
+
+    #[test]
+    fn test_unison_is_smooth() {
+        assert_eq!(pair_roughness(sine(440.0), sine(440.0)), 0.0);
+        assert_eq!(roughness(&[sine(440.0)]), 0.0);
+        assert_eq!(roughness(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_pair_roughness_is_symmetric() {
+        let a = Partial {
+            frequency: 440.0,
+            amplitude: 0.5,
+        };
+        let b = Partial {
+            frequency: 470.0,
+            amplitude: 0.8,
+        };
+        assert_eq!(pair_roughness(a, b), pair_roughness(b, a));
+    }
+
+    #[test]
+    fn test_pure_tone_roughness_peaks_at_quarter_critical_band() {
+        // Plomp & Levelt found that two pure tones are roughest when they are separated by
+        // about 25% of the critical bandwidth, and become smooth at about a full critical band.
+        for base in [250.0, 500.0, 1000.0, 2000.0] {
+            const STEP_HZ: f32 = 0.1;
+            let (peak_difference, _) = (1..5000)
+                .map(|i| i as f32 * STEP_HZ)
+                .map(|difference| {
+                    (
+                        difference,
+                        pair_roughness(sine(base), sine(base + difference)),
+                    )
+                })
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .unwrap();
+            let bandwidth = critical_bandwidth(base);
+            let peak_fraction = peak_difference / bandwidth;
+            assert!(
+                (0.15..0.35).contains(&peak_fraction),
+                "Roughness around {base}Hz peaks at {peak_fraction} of the critical band"
+            );
+
+            let peak = pair_roughness(sine(base), sine(base + peak_difference));
+            let one_band = pair_roughness(sine(base), sine(base + bandwidth));
+            const SMOOTH_FRACTION: f32 = 0.2;
+            assert!(
+                one_band < peak * SMOOTH_FRACTION,
+                "Tones a critical band apart around {base}Hz should be nearly smooth"
+            );
+        }
+    }
+
+    #[test]
+    fn test_harmonic_dissonance_curve_minima_at_just_ratios() {
+        // Sethares (Tuning, Timbre, Spectrum, Scale, fig. 3.5) shows that the dissonance curve
+        // of a harmonic tone has local minima at the simple just ratios.
+        let spectrum = sethares_spectrum();
+        const FUNDAMENTAL: f32 = 261.63;
+        const STEPS_PER_OCTAVE: usize = 2000;
+        let curve: Vec<(f32, f32)> = (0..=STEPS_PER_OCTAVE + STEPS_PER_OCTAVE / 10)
+            .map(|i| 1.0 + i as f32 / STEPS_PER_OCTAVE as f32)
+            .map(|ratio| {
+                (
+                    ratio,
+                    dyad_roughness(&spectrum, Note::C4, FUNDAMENTAL, ratio),
+                )
+            })
+            .collect();
+        let minima: Vec<f32> = curve
+            .windows(3)
+            .filter(|w| w[1].1 < w[0].1 && w[1].1 <= w[2].1)
+            .map(|w| w[1].0)
+            .collect();
+        for just_ratio in [6.0 / 5.0, 5.0 / 4.0, 4.0 / 3.0, 3.0 / 2.0, 5.0 / 3.0, 2.0] {
+            const TOLERANCE: f32 = 0.002;
+            assert!(
+                minima.iter().any(|m| (m - just_ratio).abs() < TOLERANCE),
+                "Expected a local minimum at {just_ratio}, found minima at {minima:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_harmonic_interval_ordering() {
+        // Ordering of the 12-TET intervals on Sethares' harmonic dissonance curve
+        let spectrum = sethares_spectrum();
+        const FUNDAMENTAL: f32 = 261.63;
+        let tempered = |semitones: i32| 2f32.powf(semitones as f32 / 12.0);
+        let octave = dyad_roughness(&spectrum, Note::C4, FUNDAMENTAL, tempered(12));
+        let fifth = dyad_roughness(&spectrum, Note::C4, FUNDAMENTAL, tempered(7));
+        let fourth = dyad_roughness(&spectrum, Note::C4, FUNDAMENTAL, tempered(5));
+        let major_third = dyad_roughness(&spectrum, Note::C4, FUNDAMENTAL, tempered(4));
+        let tritone = dyad_roughness(&spectrum, Note::C4, FUNDAMENTAL, tempered(6));
+        let minor_second = dyad_roughness(&spectrum, Note::C4, FUNDAMENTAL, tempered(1));
+        assert!(octave < fifth);
+        assert!(fifth < fourth);
+        assert!(fourth < major_third);
+        assert!(major_third < minor_second);
+        assert!(tritone < minor_second);
+    }
+
+    #[test]
+    fn test_piano_spectrum_is_inharmonic() {
+        let spectrum = Spectrum::piano();
+        let fundamental = Note::A2.to_freq_f32();
+        for (index, partial) in spectrum.partials(Note::A2, fundamental).enumerate().skip(1) {
+            let harmonic = fundamental * (index + 1) as f32;
+            assert!(
+                partial.frequency > harmonic,
+                "Partial {partial_number} should be sharp of its harmonic",
+                partial_number = index + 1
+            );
+        }
+    }
+
+    #[test]
+    fn test_synth_spectrum_partials() {
+        let mut amplitudes = [0.0; SynthSpectrum::NUM_PARTIALS];
+        amplitudes[0] = 1.0;
+        amplitudes[15] = 0.5;
+        let spectrum = Spectrum::synth(&SynthSpectrum {
+            amplitudes,
+            ratios: SynthSpectrum::HARMONIC_RATIOS,
+            stretch: 0.0,
+        });
+        let fundamental = Note::A2.to_freq_f32();
+        let partials: Vec<Partial> = spectrum.partials(Note::A2, fundamental).collect();
+        // Silent partials are left out, and without stretch the partials are harmonic
+        assert_eq!(partials.len(), 2);
+        assert!((partials[1].frequency - 16.0 * fundamental).abs() < 0.01);
+        assert_eq!(partials[1].amplitude, 0.5);
+
+        // The bell's partials are at its own ratios
+        let bell = Spectrum::synth(&shared_types::Instrument::Bell.spectrum());
+        let partials: Vec<Partial> = bell.partials(Note::A2, fundamental).collect();
+        assert!((partials[1].frequency - 1.19 * fundamental).abs() < 0.01);
+    }
+}
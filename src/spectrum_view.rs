@@ -0,0 +1,251 @@
+use crate::{
+    roughness::{self, Partial, Spectrum},
+    theme,
+};
+use egui::{Align2, Color32, FontId, Rect, Sense, Stroke, Ui, ecolor::Hsva, pos2, vec2};
+use itertools::Itertools as _;
+use wmidi::Note;
+
+/// Two partials of different notes close enough together to beat against each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collision {
+    pub low: Partial,
+    pub high: Partial,
+    /// How many times per second the loudness of the two partials together rises and falls
+    pub beat_rate: f32,
+    pub roughness: f32,
+}
+
+/// The partials of every note, as played by the synth
+fn note_partials(spectrum: &Spectrum, notes: &[(Note, f32)]) -> Vec<(Note, Partial)> {
+    notes
+        .iter()
+        .flat_map(|&(note, fundamental)| {
+            spectrum
+                .partials(note, fundamental)
+                .map(move |partial| (note, partial))
+        })
+        .collect()
+}
+
+/// The pairs of partials of different notes that fall within a critical band of each other, roughest first
+pub fn collisions(partials: &[(Note, Partial)]) -> Vec<Collision> {
+    let mut collisions: Vec<Collision> = partials
+        .iter()
+        .tuple_combinations()
+        .filter(|((a_note, _), (b_note, _))| a_note != b_note)
+        .filter_map(|(&(_, a), &(_, b))| {
+            let (low, high) = if a.frequency <= b.frequency {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            let beat_rate = high.frequency - low.frequency;
+            (beat_rate < roughness::critical_bandwidth(low.frequency)).then(|| Collision {
+                low,
+                high,
+                beat_rate,
+                roughness: roughness::pair_roughness(low, high),
+            })
+        })
+        .collect();
+    collisions.sort_by(|a, b| b.roughness.total_cmp(&a.roughness));
+    collisions
+}
+
+/// Plot of the partials of `notes` played with `spectrum` at their fundamental frequency in Hz,
+/// on a log-frequency axis. Colliding partials are joined and labelled with their beat rate.
+pub fn show(ui: &mut Ui, notes: &[(Note, f32)], spectrum: &Spectrum) {
+    const WIDTH: f32 = 480.0;
+    const HEIGHT: f32 = 180.0;
+    const AXIS_HEIGHT: f32 = 14.0;
+    const FONT_SIZE: f32 = 10.0;
+    // Only the roughest collisions are labelled, to keep the labels readable
+    const MAX_LABELS: usize = 8;
+    // Roughness of the pair of partials that is drawn fully opaque, smoother pairs are fainter
+    const FULL_ROUGHNESS: f32 = 0.1;
+    const MIN_OPACITY: f32 = 0.1;
+
+    let (rect, _) = ui.allocate_exact_size(vec2(WIDTH, HEIGHT), Sense::hover());
+    let plot_rect = Rect::from_min_max(rect.min, pos2(rect.right(), rect.bottom() - AXIS_HEIGHT));
+    let painter = ui.painter_at(rect);
+    const BACKGROUND_ALPHA: u8 = 40;
+    painter.rect_filled(plot_rect, 0.0, Color32::from_black_alpha(BACKGROUND_ALPHA));
+
+    let partials = note_partials(spectrum, notes);
+    if partials.is_empty() {
+        painter.text(
+            plot_rect.center(),
+            Align2::CENTER_CENTER,
+            "Hold some keys to see their partials",
+            FontId::proportional(FONT_SIZE),
+            ui.visuals().weak_text_color(),
+        );
+        return;
+    }
+
+    // Leave some room around the lowest and highest partials
+    const MARGIN_RATIO: f32 = 1.2;
+    let (min_frequency, max_frequency) = partials
+        .iter()
+        .map(|(_, partial)| partial.frequency)
+        .minmax()
+        .into_option()
+        .unwrap();
+    let (min_log, max_log) = (
+        (min_frequency / MARGIN_RATIO).log2(),
+        (max_frequency * MARGIN_RATIO).log2(),
+    );
+    let to_x = |frequency: f32| {
+        plot_rect.left() + plot_rect.width() * (frequency.log2() - min_log) / (max_log - min_log)
+    };
+    let max_amplitude = partials
+        .iter()
+        .map(|(_, partial)| partial.amplitude)
+        .fold(0.0, f32::max);
+    // Leave room above the loudest partial for the beat rate labels
+    const HEADROOM: f32 = 0.8;
+    let to_y = |amplitude: f32| {
+        plot_rect.bottom() - plot_rect.height() * HEADROOM * amplitude / max_amplitude
+    };
+
+    // Frequency axis with lines at the usual round frequencies
+    const GRID_FREQUENCIES: [f32; 9] = [
+        50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0, 10000.0, 20000.0,
+    ];
+    const GRID_WIDTH: f32 = 1.0;
+    const GRID_OPACITY: f32 = 0.5;
+    for frequency in GRID_FREQUENCIES {
+        let x = to_x(frequency);
+        if !plot_rect.x_range().contains(x) {
+            continue;
+        }
+        painter.vline(
+            x,
+            plot_rect.y_range(),
+            Stroke::new(
+                GRID_WIDTH,
+                theme::TEXT_TERTIARY.gamma_multiply(GRID_OPACITY),
+            ),
+        );
+        let label = if frequency >= 1000.0 {
+            format!("{}k", frequency / 1000.0)
+        } else {
+            format!("{frequency}")
+        };
+        painter.text(
+            pos2(x, rect.bottom()),
+            Align2::CENTER_BOTTOM,
+            label,
+            FontId::proportional(FONT_SIZE),
+            theme::TEXT_TERTIARY,
+        );
+    }
+
+    // One color per note, spread around the hue circle
+    let note_color = |note: Note| {
+        let index = notes.iter().position(|&(n, _)| n == note).unwrap_or(0);
+        const SATURATION: f32 = 0.5;
+        const VALUE: f32 = 0.9;
+        Color32::from(Hsva::new(
+            index as f32 / notes.len() as f32,
+            SATURATION,
+            VALUE,
+            1.0,
+        ))
+    };
+    const PARTIAL_WIDTH: f32 = 2.0;
+    for &(note, partial) in &partials {
+        let x = to_x(partial.frequency);
+        painter.line_segment(
+            [
+                pos2(x, plot_rect.bottom()),
+                pos2(x, to_y(partial.amplitude)),
+            ],
+            Stroke::new(PARTIAL_WIDTH, note_color(note)),
+        );
+    }
+    const LEGEND_MARGIN: f32 = 4.0;
+    const LEGEND_SPACING: f32 = 14.0;
+    for (index, &(note, _)) in notes.iter().enumerate() {
+        painter.text(
+            plot_rect.left_top()
+                + vec2(LEGEND_MARGIN, LEGEND_MARGIN + index as f32 * LEGEND_SPACING),
+            Align2::LEFT_TOP,
+            note.to_str(),
+            FontId::monospace(FONT_SIZE),
+            note_color(note),
+        );
+    }
+
+    // Join colliding partials just above the quieter of the two
+    const BRACKET_GAP: f32 = 4.0;
+    const LABEL_GAP: f32 = 2.0;
+    for (index, collision) in collisions(&partials).into_iter().enumerate() {
+        let alpha = (collision.roughness / FULL_ROUGHNESS).clamp(MIN_OPACITY, 1.0);
+        let color = theme::ATTENTION_TEXT.gamma_multiply(alpha);
+        let y = to_y(collision.low.amplitude.min(collision.high.amplitude)) - BRACKET_GAP;
+        let (low_x, high_x) = (
+            to_x(collision.low.frequency),
+            to_x(collision.high.frequency),
+        );
+        painter.line_segment(
+            [pos2(low_x, y), pos2(high_x, y)],
+            Stroke::new(PARTIAL_WIDTH, color),
+        );
+        if index < MAX_LABELS {
+            painter.text(
+                pos2((low_x + high_x) / 2.0, y - LABEL_GAP),
+                Align2::CENTER_BOTTOM,
+                format!("{:.1} Hz", collision.beat_rate),
+                FontId::monospace(FONT_SIZE),
+                color,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partials(notes: &[Note]) -> Vec<(Note, Partial)> {
+        let notes: Vec<(Note, f32)> = notes
+            .iter()
+            .map(|&note| (note, note.to_freq_f32()))
+            .collect();
+        note_partials(&Spectrum::piano(), &notes)
+    }
+
+    #[test]
+    fn test_partials_of_a_single_note_dont_collide() {
+        assert!(collisions(&partials(&[Note::C4])).is_empty());
+    }
+
+    #[test]
+    fn test_minor_second_fundamentals_beat_at_their_difference() {
+        let collisions = collisions(&partials(&[Note::C4, Note::Db4]));
+        // The fundamentals are the loudest partials, so they are the roughest pair
+        let roughest = collisions[0];
+        assert!((roughest.low.frequency - Note::C4.to_freq_f32()).abs() < 0.01);
+        let expected = Note::Db4.to_freq_f32() - Note::C4.to_freq_f32();
+        assert!((roughest.beat_rate - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_fifth_beats_slowly_at_its_shared_partial() {
+        let collisions = collisions(&partials(&[Note::C4, Note::G4]));
+        // The third partial of C and the second of G almost coincide in 12-TET
+        assert!(
+            collisions
+                .iter()
+                .any(|collision| collision.beat_rate < 2.0 && collision.low.frequency > 700.0)
+        );
+        // The fundamentals are too far apart to interfere
+        assert!(
+            collisions
+                .iter()
+                .all(|collision| collision.low.frequency > Note::C4.to_freq_f32() + 1.0)
+        );
+    }
+}
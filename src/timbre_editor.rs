@@ -0,0 +1,72 @@
+use egui::Ui;
+use shared_types::{Instrument, SynthSpectrum};
+
+/// Instrument presets, then sliders for the amplitude of each partial the synth plays and for how far they are
+/// stretched. Choosing an instrument replaces the spectrum with its own. Returns whether anything was changed.
+pub fn show(ui: &mut Ui, instrument: &mut Instrument, spectrum: &mut SynthSpectrum) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        for preset in Instrument::ALL {
+            if ui
+                .selectable_label(*instrument == preset, preset.name())
+                .clicked()
+            {
+                *instrument = preset;
+                *spectrum = preset.spectrum();
+                changed = true;
+            }
+        }
+    });
+    changed |= partial_sliders(ui, spectrum);
+    if ui
+        .button(format!("Reset to {}", instrument.name()))
+        .on_hover_text("The spectrum of the chosen instrument")
+        .clicked()
+    {
+        *spectrum = instrument.spectrum();
+        changed = true;
+    }
+    changed
+}
+
+/// Sliders for the amplitude of each partial of `spectrum` and for how far they are stretched.
+/// Returns whether anything was changed.
+pub fn partial_sliders(ui: &mut Ui, spectrum: &mut SynthSpectrum) -> bool {
+    const SLIDER_HEIGHT: f32 = 80.0;
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        for (index, (amplitude, ratio)) in spectrum
+            .amplitudes
+            .iter_mut()
+            .zip(spectrum.ratios)
+            .enumerate()
+        {
+            ui.vertical(|ui| {
+                ui.style_mut().spacing.slider_width = SLIDER_HEIGHT;
+                changed |= ui
+                    .add(
+                        egui::Slider::new(amplitude, 0.0..=1.0)
+                            .vertical()
+                            .show_value(false),
+                    )
+                    .on_hover_text(format!(
+                        "Amplitude of partial {}, at {ratio} times the fundamental",
+                        index + 1
+                    ))
+                    .changed();
+                ui.weak(format!("{}", index + 1));
+            });
+        }
+    });
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut spectrum.stretch, 0.0..=SynthSpectrum::MAX_STRETCH)
+                .text("stretch"),
+        )
+        .on_hover_text(
+            "How stiff the strings are compared to a piano's: 0 for harmonic partials, 1 for a piano, \
+             higher to stretch the partials further",
+        )
+        .changed();
+    changed
+}